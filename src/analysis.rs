@@ -0,0 +1,241 @@
+//! Post-flight analysis. Detects flight events from a sequence of vehicle
+//! states, using flight mode transitions, and derives the numbers we usually
+//! look at after a flight.
+
+use serde::Serialize;
+
+use mithril::telemetry::*;
+
+/// Time to wait after a deployment before measuring the descent rate, to
+/// allow the canopy to inflate.
+const CANOPY_SETTLING_TIME: f32 = 2.0;
+
+/// A single detected flight event.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FlightEvent {
+    /// FC time of the event [s]
+    pub time: f32,
+    /// Altitude of the vehicle at the time of the event [m ASL]
+    pub altitude_asl: Option<f32>,
+    /// Last known GPS position at the time of the event
+    pub latitude: Option<f32>,
+    pub longitude: Option<f32>,
+}
+
+/// Summary of a flight, derived from a log.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FlightSummary {
+    pub liftoff: Option<FlightEvent>,
+    pub burnout: Option<FlightEvent>,
+    pub apogee: Option<FlightEvent>,
+    pub drogue_deploy: Option<FlightEvent>,
+    pub main_deploy: Option<FlightEvent>,
+    pub landing: Option<FlightEvent>,
+    /// Ground altitude determined by the FC before liftoff [m ASL]
+    pub ground_altitude_asl: Option<f32>,
+    /// Maximum barometric altitude [m ASL]
+    pub apogee_baro_asl: Option<f32>,
+    /// Maximum GPS altitude [m ASL]
+    pub apogee_gps_asl: Option<f32>,
+    /// Apogee as determined by the FC's state estimator [m ASL]
+    pub apogee_fc_asl: Option<f32>,
+    /// Maximum vertical acceleration [m/s²]
+    pub max_acceleration: Option<f32>,
+    /// Maximum vertical speed [m/s]
+    pub max_vertical_speed: Option<f32>,
+    /// Mean descent rate under drogue [m/s]
+    pub descent_rate_drogue: Option<f32>,
+    /// Mean descent rate under main [m/s]
+    pub descent_rate_main: Option<f32>,
+    /// Time from liftoff to landing [s]
+    pub flight_time: Option<f32>,
+    /// Seed of the simulation the states came from, if simulated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub simulation_seed: Option<u64>,
+}
+
+impl FlightEvent {
+    fn new(time: f32, vs: &VehicleState, position: Option<(f32, f32)>) -> Self {
+        Self {
+            time,
+            altitude_asl: vs.altitude_asl,
+            latitude: position.map(|(lat, _)| lat),
+            longitude: position.map(|(_, lng)| lng),
+        }
+    }
+}
+
+fn max(a: Option<f32>, b: Option<f32>) -> Option<f32> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(f32::max(a, b)),
+        _ => a.or(b),
+    }
+}
+
+fn mean(values: impl Iterator<Item = f32>) -> Option<f32> {
+    let (sum, n) = values.fold((0.0, 0), |(sum, n), v| (sum + v, n + 1));
+    (n > 0).then_some(sum / (n as f32))
+}
+
+impl FlightSummary {
+    /// Analyzes the given vehicle states. States created from ground station
+    /// messages are ignored, since they don't contain FC time.
+    pub fn from_states<'a>(states: impl Iterator<Item = &'a VehicleState>) -> Self {
+        let states: Vec<(f32, &VehicleState)> = states
+            .filter(|vs| vs.gcs_lora_rssi.is_none())
+            .map(|vs| (vs.time as f32 / 1000.0, vs))
+            .collect();
+
+        let mut summary = Self::default();
+        let mut mode: Option<FlightMode> = None;
+        let mut position: Option<(f32, f32)> = None;
+        let mut max_altitude: Option<(f32, &VehicleState, Option<(f32, f32)>)> = None;
+
+        for (t, vs) in states.iter().copied() {
+            if vs.gps_fix.map(|f| !matches!(f, GPSFixType::NoFix)).unwrap_or(false) {
+                position = vs.latitude.zip(vs.longitude).or(position);
+            }
+
+            let new_mode = vs.mode.filter(|m| Some(*m) != mode);
+            if let Some(new_mode) = new_mode {
+                mode = Some(new_mode);
+                let event = Some(FlightEvent::new(t, vs, position));
+                match new_mode {
+                    FlightMode::Flight => summary.liftoff = summary.liftoff.or(event),
+                    FlightMode::RecoveryDrogue => summary.drogue_deploy = summary.drogue_deploy.or(event),
+                    FlightMode::RecoveryMain => summary.main_deploy = summary.main_deploy.or(event),
+                    FlightMode::Landed => summary.landing = summary.landing.or(event),
+                    _ => {}
+                }
+            }
+
+            if summary.liftoff.is_none() {
+                summary.ground_altitude_asl = vs.altitude_ground_asl.or(summary.ground_altitude_asl);
+                continue;
+            }
+
+            // The FC keeps reporting its apogee after landing, so don't stop here.
+            summary.apogee_fc_asl = max(summary.apogee_fc_asl, vs.apogee_asl);
+
+            if summary.landing.is_some() {
+                // Keep updating the landing position until we get a GPS fix
+                if let Some(landing) = summary.landing.as_mut() {
+                    landing.latitude = position.map(|(lat, _)| lat);
+                    landing.longitude = position.map(|(_, lng)| lng);
+                }
+                continue;
+            }
+
+            if let Some(alt) = vs.altitude_asl {
+                if max_altitude.map(|(_, max, _)| alt > max.altitude_asl.unwrap_or(f32::MIN)).unwrap_or(true) {
+                    max_altitude = Some((t, vs, position));
+                }
+            }
+
+            // Burnout is the first time the vehicle decelerates after having accelerated.
+            let accel = vs.vertical_accel.or(vs.vertical_accel_filtered);
+            let accelerated = summary.max_acceleration.map(|a| a > 0.0).unwrap_or(false);
+            let decelerating = vs.vertical_accel_filtered.map(|a| a < 0.0).unwrap_or(false);
+            if summary.burnout.is_none() && accelerated && decelerating {
+                summary.burnout = Some(FlightEvent::new(t, vs, position));
+            }
+
+            summary.apogee_baro_asl = max(summary.apogee_baro_asl, vs.altitude_baro);
+            summary.apogee_gps_asl = max(summary.apogee_gps_asl, vs.altitude_gps_asl);
+            summary.max_acceleration = max(summary.max_acceleration, accel);
+            summary.max_vertical_speed = max(summary.max_vertical_speed, vs.vertical_speed);
+        }
+
+        summary.apogee = max_altitude.map(|(t, vs, position)| FlightEvent::new(t, vs, position));
+
+        // Descent rates are averaged from shortly after deployment to the next event.
+        let descent_rate = |from: &Option<FlightEvent>, to: &Option<FlightEvent>| {
+            let start = from.as_ref()?.time + CANOPY_SETTLING_TIME;
+            let end = to.as_ref().map(|e| e.time).unwrap_or(f32::MAX);
+            mean(states.iter().filter(|(t, _)| *t >= start && *t < end).filter_map(|(_, vs)| vs.vertical_speed))
+                .map(|v| -v)
+        };
+
+        let after_drogue = summary.main_deploy.clone().or(summary.landing.clone());
+        summary.descent_rate_drogue = descent_rate(&summary.drogue_deploy, &after_drogue);
+        summary.descent_rate_main = descent_rate(&summary.main_deploy, &summary.landing);
+
+        summary.flight_time = summary.liftoff.as_ref().zip(summary.landing.as_ref()).map(|(l, e)| e.time - l.time);
+
+        summary
+    }
+
+    /// Altitude above the launch site, if the ground altitude is known.
+    fn agl(&self, altitude_asl: Option<f32>) -> Option<f32> {
+        altitude_asl.zip(self.ground_altitude_asl).map(|(a, g)| a - g)
+    }
+
+    /// Events in chronological order, with a descriptive name.
+    pub fn events(&self) -> Vec<(&'static str, &FlightEvent)> {
+        let events = [
+            ("Liftoff", &self.liftoff),
+            ("Burnout", &self.burnout),
+            ("Apogee", &self.apogee),
+            ("Drogue Deployment", &self.drogue_deploy),
+            ("Main Deployment", &self.main_deploy),
+            ("Landing", &self.landing),
+        ];
+
+        let mut events: Vec<_> = events.into_iter().filter_map(|(name, e)| e.as_ref().map(|e| (name, e))).collect();
+        events.sort_by(|(_, a), (_, b)| a.time.total_cmp(&b.time));
+        events
+    }
+
+    /// Key figures of the flight as (name, value) pairs, formatted for display.
+    pub fn figures(&self) -> Vec<(&'static str, Option<String>)> {
+        let fmt = |v: Option<f32>, unit: &str| v.map(|v| format!("{:.1} {}", v, unit));
+        vec![
+            ("Apogee (Baro)", fmt(self.apogee_baro_asl, "m ASL")),
+            ("Apogee (Baro)", fmt(self.agl(self.apogee_baro_asl), "m AGL")),
+            ("Apogee (GPS)", fmt(self.apogee_gps_asl, "m ASL")),
+            ("Apogee (FC)", fmt(self.apogee_fc_asl, "m ASL")),
+            ("Apogee (FC)", fmt(self.agl(self.apogee_fc_asl), "m AGL")),
+            ("Max. Acceleration", fmt(self.max_acceleration, "m/s²")),
+            ("Max. Vertical Speed", fmt(self.max_vertical_speed, "m/s")),
+            ("Descent Rate (Drogue)", fmt(self.descent_rate_drogue, "m/s")),
+            ("Descent Rate (Main)", fmt(self.descent_rate_main, "m/s")),
+            ("Flight Time", fmt(self.flight_time, "s")),
+        ]
+    }
+
+    /// Time of the given event relative to liftoff.
+    pub fn time_since_liftoff(&self, event: &FlightEvent) -> Option<f32> {
+        self.liftoff.as_ref().map(|l| event.time - l.time)
+    }
+
+    /// Renders the summary as a Markdown document.
+    pub fn to_markdown(&self) -> String {
+        let na = || "N/A".to_string();
+        let mut md = String::from("# Flight Summary\n\n");
+        if let Some(seed) = self.simulation_seed {
+            md += &format!("Simulated with seed {}.\n\n", seed);
+        }
+
+        md += "| Event | FC Time [s] | T+ [s] | Altitude [m ASL] | Altitude [m AGL] | Position |\n";
+        md += "|---|---:|---:|---:|---:|---|\n";
+        for (name, e) in self.events() {
+            md += &format!(
+                "| {} | {:.3} | {} | {} | {} | {} |\n",
+                name,
+                e.time,
+                self.time_since_liftoff(e).map(|t| format!("{:.2}", t)).unwrap_or_else(na),
+                e.altitude_asl.map(|a| format!("{:.1}", a)).unwrap_or_else(na),
+                self.agl(e.altitude_asl).map(|a| format!("{:.1}", a)).unwrap_or_else(na),
+                e.latitude.zip(e.longitude).map(|(lat, lng)| format!("{:.6}, {:.6}", lat, lng)).unwrap_or_else(na),
+            );
+        }
+
+        md += "\n| Figure | Value |\n";
+        md += "|---|---:|\n";
+        for (name, value) in self.figures() {
+            md += &format!("| {} | {} |\n", name, value.unwrap_or_else(na));
+        }
+
+        md
+    }
+}
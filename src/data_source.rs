@@ -14,11 +14,21 @@ use mithril::telemetry::*;
 
 use crate::settings::AppSettings;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod hil;
 pub mod log_file;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod loopback;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod network;
 pub mod serial;
 pub mod simulation;
 
 pub use log_file::LogFileDataSource;
+#[cfg(not(target_arch = "wasm32"))]
+pub use hil::{HilDataSource, HilTarget};
+#[cfg(not(target_arch = "wasm32"))]
+pub use network::NetworkDataSource;
 pub use serial::*;
 pub use simulation::SimulationDataSource;
 
@@ -28,6 +38,10 @@ pub trait DataSource {
     fn update(&mut self, ctx: &egui::Context);
     /// Return an iterator over all known states of the vehicle.
     fn vehicle_states<'a>(&'a self) -> Iter<'_, (Instant, VehicleState)>;
+    /// Return an iterator over all log messages received from the vehicle.
+    fn log_messages<'a>(&'a self) -> Iter<'_, (u32, String, LogLevel, String)> {
+        [].iter()
+    }
 
     /// Return the current flight computer settings, if known.
     fn fc_settings<'a>(&'a mut self) -> Option<&'a Settings>;
@@ -44,6 +58,11 @@ pub trait DataSource {
 
     fn end(&self) -> Option<Instant>;
 
+    /// Whether the data is received as it happens, rather than replayed or simulated.
+    fn is_live(&self) -> bool {
+        false
+    }
+
     fn status_bar_ui(&mut self, _ui: &mut egui::Ui) {
     }
 
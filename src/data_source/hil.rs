@@ -0,0 +1,410 @@
+//! Hardware-in-the-loop simulation. Runs the simulation in real time, sends
+//! the simulated sensor readings to a flight computer, and combines its
+//! telemetry with the true state of the simulated vehicle, so the FC's
+//! estimates can be compared with the truth.
+//!
+//! The FC is either a real one connected via USB serial, or a stand-in
+//! running mithril's state estimator on the host, for testing without
+//! hardware. mithril's `UplinkMessage` has no variant for sensor samples, so
+//! these are sent in frames of their own, using the same COBS framing. Their
+//! first byte is not a valid `UplinkMessage` variant, so FCs without HIL
+//! support discard them. A real FC has to run firmware that feeds the samples
+//! to its state estimator in place of its own sensors and uses their time as
+//! its own, which is what allows matching its telemetry with the truth.
+//!
+//! The simulated vehicle deploys its parachutes based on the host's flight
+//! mode, not the FC's. Restarting the simulation doesn't reset a real FC.
+
+use std::any::Any;
+use std::collections::VecDeque;
+use std::slice::Iter;
+use std::sync::mpsc::{Receiver, SendError, Sender};
+use std::time::{Duration, Instant};
+
+use egui::Color32;
+use serde::{Deserialize, Serialize};
+
+use mithril::settings::*;
+use mithril::telemetry::*;
+
+use crate::data_source::loopback::loopback_fc;
+use crate::data_source::{DataSource, SerialDataSource};
+use crate::settings::AppSettings;
+use crate::simulation::*;
+
+/// First byte of frames containing sensor samples
+const SENSOR_SAMPLE_TAG: u8 = 0x7f;
+/// Maximum size of an encoded sensor sample frame
+const MAX_FRAME_SIZE: usize = 256;
+/// Maximum difference between the time of a telemetry message and that of the
+/// true state it is combined with [ms]
+const MAX_TRUTH_OFFSET_MS: u32 = 10;
+/// Maximum number of true states waiting for telemetry, limiting the memory
+/// used if the FC doesn't respond
+const MAX_TRUTH_BACKLOG: usize = 10_000;
+
+/// The sensor readings of a single simulation tick, as sampled by the FC.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SensorSample {
+    pub time: u32,
+    pub gyroscope: Option<[f32; 3]>,
+    pub accelerometer1: Option<[f32; 3]>,
+    pub accelerometer2: Option<[f32; 3]>,
+    pub magnetometer: Option<[f32; 3]>,
+    pub pressure_baro: Option<f32>,
+    pub temperature_baro: Option<f32>,
+    pub arm_voltage: Option<u16>,
+    pub gps_fix: Option<GPSFixType>,
+    pub num_satellites: Option<u8>,
+    pub hdop: Option<u16>,
+    pub latitude: Option<f32>,
+    pub longitude: Option<f32>,
+    pub altitude_gps_asl: Option<f32>,
+}
+
+impl From<&VehicleState> for SensorSample {
+    fn from(vs: &VehicleState) -> Self {
+        Self {
+            time: vs.time,
+            gyroscope: vs.gyroscope.map(Into::into),
+            accelerometer1: vs.accelerometer1.map(Into::into),
+            accelerometer2: vs.accelerometer2.map(Into::into),
+            magnetometer: vs.magnetometer.map(Into::into),
+            pressure_baro: vs.pressure_baro,
+            temperature_baro: vs.temperature_baro,
+            arm_voltage: vs.arm_voltage,
+            gps_fix: vs.gps_fix,
+            num_satellites: vs.num_satellites,
+            hdop: vs.hdop,
+            latitude: vs.latitude,
+            longitude: vs.longitude,
+            altitude_gps_asl: vs.altitude_gps_asl,
+        }
+    }
+}
+
+impl SensorSample {
+    /// Encodes the sample as a COBS frame, including the zero delimiter.
+    pub fn to_frame(&self) -> Option<Vec<u8>> {
+        let mut buffer = [0u8; MAX_FRAME_SIZE];
+        postcard::to_slice_cobs(&(SENSOR_SAMPLE_TAG, self), &mut buffer).ok().map(|frame| frame.to_vec())
+    }
+
+    /// Decodes a frame created by `to_frame`, returning None for other frames.
+    pub fn from_frame(frame: &mut [u8]) -> Option<Self> {
+        match postcard::from_bytes_cobs::<(u8, Self)>(frame) {
+            Ok((SENSOR_SAMPLE_TAG, sample)) => Some(sample),
+            _ => None,
+        }
+    }
+}
+
+/// The flight computer the simulated sensor readings are sent to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HilTarget {
+    /// A real FC, connected via USB serial
+    Serial,
+    /// A stand-in for the FC, running on the host
+    Loopback,
+}
+
+enum FlightComputer {
+    Serial(Box<SerialDataSource>),
+    Loopback {
+        frame_tx: Sender<Vec<u8>>,
+        downlink_rx: Receiver<DownlinkMessage>,
+    },
+}
+
+impl FlightComputer {
+    /// Starts a new loopback FC with the given settings.
+    fn loopback(settings: &SimulationSettings) -> Self {
+        let (frame_tx, frame_rx) = std::sync::mpsc::channel::<Vec<u8>>();
+        let (downlink_tx, downlink_rx) = std::sync::mpsc::channel::<DownlinkMessage>();
+
+        let fc_settings = settings.fc_settings.clone();
+        std::thread::spawn(move || loopback_fc(fc_settings, frame_rx, downlink_tx));
+
+        Self::Loopback { frame_tx, downlink_rx }
+    }
+}
+
+pub struct HilDataSource {
+    pub settings: SimulationSettings,
+    fc: FlightComputer,
+    state: SimulationState,
+    done: bool,
+    /// Start of the simulation, once the FC is connected
+    start: Option<Instant>,
+    /// True states of the vehicle that have not been matched with telemetry yet
+    truth: VecDeque<VehicleState>,
+    /// Number of vehicle states of the serial data source processed so far
+    received: usize,
+    vehicle_states: Vec<(Instant, VehicleState)>,
+}
+
+impl HilDataSource {
+    /// Starts a real-time run of the given simulation against the given FC.
+    /// When using a serial FC, the simulation starts once it is connected.
+    pub fn new(
+        ctx: &egui::Context,
+        app_settings: &AppSettings,
+        settings: SimulationSettings,
+        target: HilTarget,
+    ) -> Self {
+        let fc = match target {
+            HilTarget::Serial => FlightComputer::Serial(Box::new(SerialDataSource::new(ctx, app_settings))),
+            HilTarget::Loopback => FlightComputer::loopback(&settings),
+        };
+
+        Self {
+            state: SimulationState::initialize(&settings),
+            settings,
+            fc,
+            done: false,
+            start: None,
+            truth: VecDeque::new(),
+            received: 0,
+            vehicle_states: Vec::new(),
+        }
+    }
+
+    pub fn target(&self) -> HilTarget {
+        match self.fc {
+            FlightComputer::Serial(_) => HilTarget::Serial,
+            FlightComputer::Loopback { .. } => HilTarget::Loopback,
+        }
+    }
+
+    fn fc_connected(&self) -> bool {
+        match &self.fc {
+            FlightComputer::Serial(serial) => serial.is_connected(),
+            FlightComputer::Loopback { .. } => true,
+        }
+    }
+
+    fn send_sample(&mut self, sample: &SensorSample) {
+        let Some(frame) = sample.to_frame() else {
+            return;
+        };
+
+        // This only fails if the FC or serial port thread stopped, in which
+        // case there is nobody to send samples to anyway.
+        let _ = match &mut self.fc {
+            FlightComputer::Serial(serial) => serial.send_frame(frame),
+            FlightComputer::Loopback { frame_tx, .. } => frame_tx.send(frame),
+        };
+    }
+
+    /// The telemetry received from the FC since the last call.
+    fn receive(&mut self, ctx: &egui::Context) -> Vec<(Instant, VehicleState)> {
+        match &mut self.fc {
+            FlightComputer::Serial(serial) => {
+                serial.update(ctx);
+                let states: Vec<_> = serial.vehicle_states().skip(self.received).cloned().collect();
+                self.received += states.len();
+                states
+            }
+            FlightComputer::Loopback { downlink_rx, .. } => {
+                downlink_rx.try_iter().map(|msg| (Instant::now(), msg.into())).collect()
+            }
+        }
+    }
+
+    /// Adds the true state of the vehicle at the FC time of the given state,
+    /// which is the time of the latest sample the FC received before it.
+    fn add_truth(&mut self, vs: &mut VehicleState) {
+        while self.truth.get(1).map(|t| t.time <= vs.time).unwrap_or(false) {
+            self.truth.pop_front();
+        }
+
+        let matching = |t: &&VehicleState| t.time <= vs.time && vs.time - t.time <= MAX_TRUTH_OFFSET_MS;
+        let Some(truth) = self.truth.front().filter(matching) else {
+            return;
+        };
+
+        vs.true_orientation = truth.true_orientation;
+        vs.true_euler_angles = truth.true_euler_angles;
+        vs.true_angle_of_attack = truth.true_angle_of_attack;
+        vs.true_vertical_accel = truth.true_vertical_accel;
+        vs.true_vertical_speed = truth.true_vertical_speed;
+    }
+}
+
+impl DataSource for HilDataSource {
+    fn update(&mut self, ctx: &egui::Context) {
+        if self.start.is_none() && self.fc_connected() {
+            self.start = Some(Instant::now());
+        }
+
+        // The FC runs in real time, so the simulation has to as well.
+        let elapsed = self.start.map(|start| start.elapsed().as_millis() as u32).unwrap_or(0);
+        while !self.done && self.state.time < elapsed {
+            if self.state.tick() {
+                self.done = true;
+                break;
+            }
+
+            let vs = VehicleState::from(&self.state);
+            self.send_sample(&SensorSample::from(&vs));
+            self.truth.push_back(vs);
+            if self.truth.len() > MAX_TRUTH_BACKLOG {
+                self.truth.pop_front();
+            }
+        }
+
+        for (t, mut vs) in self.receive(ctx).into_iter() {
+            self.add_truth(&mut vs);
+            self.vehicle_states.push((t, vs));
+        }
+
+        if !self.done {
+            ctx.request_repaint_after(Duration::from_millis(PLOT_STEP_MS as u64));
+        }
+    }
+
+    fn vehicle_states<'a>(&'a self) -> Iter<'_, (Instant, VehicleState)> {
+        self.vehicle_states.iter()
+    }
+
+    fn log_messages<'a>(&'a self) -> Iter<'_, (u32, String, LogLevel, String)> {
+        match &self.fc {
+            FlightComputer::Serial(serial) => serial.log_messages(),
+            FlightComputer::Loopback { .. } => [].iter(),
+        }
+    }
+
+    fn fc_settings<'a>(&'a mut self) -> Option<&'a Settings> {
+        match &mut self.fc {
+            FlightComputer::Serial(serial) => serial.fc_settings(),
+            FlightComputer::Loopback { .. } => None,
+        }
+    }
+
+    fn fc_settings_mut<'a>(&'a mut self) -> Option<&'a mut Settings> {
+        match &mut self.fc {
+            FlightComputer::Serial(serial) => serial.fc_settings_mut(),
+            FlightComputer::Loopback { .. } => None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = SimulationState::initialize(&self.settings);
+        self.done = false;
+        self.start = None;
+        self.truth.clear();
+        self.received = 0;
+        self.vehicle_states.clear();
+
+        match &mut self.fc {
+            FlightComputer::Serial(serial) => serial.reset(),
+            FlightComputer::Loopback { .. } => self.fc = FlightComputer::loopback(&self.settings),
+        }
+    }
+
+    fn send(&mut self, msg: UplinkMessage) -> Result<(), SendError<UplinkMessage>> {
+        match &mut self.fc {
+            FlightComputer::Serial(serial) => serial.send(msg),
+            FlightComputer::Loopback { .. } => Ok(()),
+        }
+    }
+
+    fn send_command(&mut self, cmd: Command) -> Result<(), SendError<UplinkMessage>> {
+        match &mut self.fc {
+            FlightComputer::Serial(serial) => serial.send_command(cmd),
+            FlightComputer::Loopback { .. } => Ok(()),
+        }
+    }
+
+    fn end(&self) -> Option<Instant> {
+        match &self.fc {
+            FlightComputer::Serial(serial) => serial.end(),
+            FlightComputer::Loopback { .. } if self.done => self.vehicle_states.last().map(|(t, _vs)| *t),
+            FlightComputer::Loopback { .. } => Some(Instant::now()),
+        }
+    }
+
+    fn is_live(&self) -> bool {
+        self.target() == HilTarget::Serial
+    }
+
+    fn status_bar_ui(&mut self, ui: &mut egui::Ui) {
+        if ui.button("⏮  Restart").clicked() {
+            self.reset();
+        }
+
+        ui.separator();
+        match self.target() {
+            HilTarget::Serial => ui.colored_label(Color32::KHAKI, "HIL Simulation"),
+            HilTarget::Loopback => ui.colored_label(Color32::KHAKI, "Loopback Simulation"),
+        };
+
+        if self.start.is_some() {
+            ui.weak(format!("T+{:.1}s", self.state.time as f32 / 1000.0));
+        } else {
+            ui.weak("Waiting for FC to be connected...");
+        }
+    }
+
+    fn link_quality(&self) -> Option<f32> {
+        match &self.fc {
+            FlightComputer::Serial(serial) => serial.link_quality(),
+            FlightComputer::Loopback { .. } => None,
+        }
+    }
+
+    fn apply_settings(&mut self, settings: &AppSettings) {
+        if let FlightComputer::Serial(serial) = &mut self.fc {
+            serial.apply_settings(settings);
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(time: u32) -> SensorSample {
+        SensorSample {
+            time,
+            gyroscope: Some([0.1, -0.2, 0.3]),
+            accelerometer1: Some([0.0, 0.0, 9.81]),
+            accelerometer2: None,
+            magnetometer: Some([20.0, 0.0, -40.0]),
+            pressure_baro: Some(1013.25),
+            temperature_baro: Some(20.0),
+            arm_voltage: Some(8400),
+            gps_fix: Some(GPSFixType::AutonomousFix),
+            num_satellites: Some(6),
+            hdop: Some(150),
+            latitude: Some(49.86),
+            longitude: Some(8.68),
+            altitude_gps_asl: Some(120.0),
+        }
+    }
+
+    #[test]
+    fn sensor_sample_frame_roundtrip() {
+        let mut frame = sample(1234).to_frame().unwrap();
+        assert_eq!(frame.last(), Some(&0x00));
+        assert!(!frame[..frame.len() - 1].contains(&0x00));
+        assert_eq!(SensorSample::from_frame(&mut frame), Some(sample(1234)));
+    }
+
+    #[test]
+    fn sensor_sample_frame_is_not_an_uplink_message() {
+        let mut frame = sample(1234).to_frame().unwrap();
+        assert!(postcard::from_bytes_cobs::<UplinkMessage>(&mut frame).is_err());
+
+        let mut heartbeat = UplinkMessage::Heartbeat.serialize().unwrap();
+        assert_eq!(SensorSample::from_frame(&mut heartbeat), None);
+    }
+}
@@ -4,7 +4,7 @@
 use std::any::Any;
 use std::collections::VecDeque;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::PathBuf;
 use std::slice::Iter;
 use std::sync::mpsc::SendError;
@@ -15,93 +15,292 @@ use std::time::Instant;
 #[cfg(target_arch = "wasm32")]
 use web_time::Instant;
 
+use eframe::egui::RichText;
 use eframe::epaint::Color32;
 use log::*;
+use serde::de::DeserializeOwned;
 
 use mithril::settings::*;
 use mithril::telemetry::*;
 
 use crate::data_source::DataSource;
+use crate::telemetry_ext::*;
+
+/// Format of a telemetry log.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// Sequence of postcard-serialized, COBS-encoded messages, each terminated by a zero
+    Cobs,
+    /// JSON array of messages
+    Json,
+}
+
+/// Statistics about corrupted data encountered while parsing a log.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LogCorruptionStats {
+    pub bad_frames: usize,
+    pub skipped_bytes: usize,
+}
+
+/// Decodes all complete COBS frames in the buffer, leaving an incomplete one
+/// at the end for the next call. Frames that fail to decode are skipped and
+/// counted in the given stats.
+pub fn decode_cobs_frames<T: DeserializeOwned>(buffer: &mut Vec<u8>, stats: &mut LogCorruptionStats) -> Vec<T> {
+    let mut msgs = Vec::new();
+    let mut start = 0;
+
+    while let Some(len) = buffer[start..].iter().position(|b| *b == 0x00) {
+        let frame = &mut buffer[start..(start + len + 1)];
+        start += len + 1;
+
+        // Consecutive delimiters, nothing to decode
+        if len == 0 {
+            continue;
+        }
+
+        match postcard::from_bytes_cobs(frame) {
+            Ok(msg) => msgs.push(msg),
+            Err(_e) => {
+                stats.bad_frames += 1;
+                stats.skipped_bytes += len + 1;
+            }
+        }
+    }
+
+    buffer.drain(..start);
+    msgs
+}
+
+/// Incremental parser for telemetry logs. Bytes can be pushed in chunks of
+/// arbitrary size, incomplete messages at the end of the buffer are kept
+/// until the rest arrives. Damaged messages are skipped and counted.
+pub struct LogParser {
+    format: Option<LogFormat>,
+    buffer: Vec<u8>,
+    json_array_started: bool,
+    pub stats: LogCorruptionStats,
+}
+
+impl LogParser {
+    /// Create a new parser. If no format is given, it is guessed from the first byte.
+    pub fn new(format: Option<LogFormat>) -> Self {
+        Self {
+            format,
+            buffer: Vec::new(),
+            json_array_started: false,
+            stats: LogCorruptionStats::default(),
+        }
+    }
+
+    /// Adds the given bytes, returning all messages completed by them.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<DownlinkMessage> {
+        self.buffer.extend_from_slice(bytes);
+
+        if self.format.is_none() {
+            match self.buffer.iter().find(|b| !b.is_ascii_whitespace()) {
+                Some(b'[') => self.format = Some(LogFormat::Json),
+                Some(_) => self.format = Some(LogFormat::Cobs),
+                None => return Vec::new(),
+            }
+        }
+
+        match self.format {
+            Some(LogFormat::Json) => self.parse_json(),
+            _ => self.parse_cobs(),
+        }
+    }
+
+    /// Signals that no more bytes will follow. Anything still in the buffer is
+    /// an incomplete message, which is discarded and counted as corrupted.
+    pub fn finish(&mut self) {
+        let remaining = self
+            .buffer
+            .iter()
+            .filter(|b| !(b.is_ascii_whitespace() || **b == b']' || **b == b',' || **b == 0x00))
+            .count();
+        if remaining > 0 {
+            self.stats.bad_frames += 1;
+            self.stats.skipped_bytes += self.buffer.len();
+        }
+        self.buffer.truncate(0);
+    }
+
+    fn parse_cobs(&mut self) -> Vec<DownlinkMessage> {
+        decode_cobs_frames(&mut self.buffer, &mut self.stats)
+    }
+
+    fn parse_json(&mut self) -> Vec<DownlinkMessage> {
+        let mut msgs = Vec::new();
+        let mut pos = 0;
+
+        loop {
+            // Skip whitespace and separators between messages
+            while pos < self.buffer.len() {
+                match self.buffer[pos] {
+                    b'[' if !self.json_array_started => self.json_array_started = true,
+                    b',' | b']' if self.json_array_started => {}
+                    b if b.is_ascii_whitespace() => {}
+                    _ if self.json_array_started => break,
+                    _ => self.stats.skipped_bytes += 1,
+                }
+                pos += 1;
+            }
+
+            if pos >= self.buffer.len() {
+                break;
+            }
+
+            let mut stream =
+                serde_json::Deserializer::from_slice(&self.buffer[pos..]).into_iter::<DownlinkMessage>();
+            match stream.next() {
+                Some(Ok(msg)) => {
+                    pos += stream.byte_offset();
+                    msgs.push(msg);
+                }
+                // The message is not complete yet, wait for more data
+                Some(Err(e)) if e.is_eof() => break,
+                // Damaged message, skip to the next line, where the next message starts
+                Some(Err(_e)) => match self.buffer[pos..].iter().position(|b| *b == b'\n') {
+                    Some(len) => {
+                        self.stats.bad_frames += 1;
+                        self.stats.skipped_bytes += len + 1;
+                        pos += len + 1;
+                    }
+                    None => break,
+                },
+                None => break,
+            }
+        }
+
+        self.buffer.drain(..pos);
+        msgs
+    }
+}
+
+/// Maximum number of bytes read from a log file each frame, to keep the UI
+/// responsive while opening large files.
+const MAX_READ_PER_FRAME: u64 = 4 * 1024 * 1024;
+
+/// Interval in which files are checked for new data, allowing files that are
+/// still being written to be tailed.
+const FILE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Playback speeds selectable in the replay controls.
+const REPLAY_SPEEDS: [f32; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+
+/// Playback state of a log file.
+struct Replay {
+    playing: bool,
+    speed: f32,
+    looping: bool,
+    /// Current position relative to the start of the log. None if we are
+    /// simply showing all data, including any newly appended to the file.
+    position: Option<Duration>,
+    last_frame: Option<Instant>,
+}
+
+impl Replay {
+    fn new(playing: bool) -> Self {
+        Self {
+            playing,
+            speed: 1.0,
+            looping: false,
+            position: playing.then_some(Duration::ZERO),
+            last_frame: None,
+        }
+    }
+}
 
 pub struct LogFileDataSource {
     path: Option<PathBuf>,
     name: Option<String>,
     file: Option<File>,
-    buffer: Vec<u8>,
-    messages: Vec<(Instant, DownlinkMessage)>,
-    is_json: bool,
+    parser: LogParser,
+    start: Instant,
     vehicle_states: Vec<(Instant, VehicleState)>,
+    log_messages: Vec<(u32, String, LogLevel, String)>,
+    log_message_times: Vec<Instant>,
+    mode_transitions: Vec<(Duration, FlightMode)>,
+    last_vehicle_times: VecDeque<u32>,
     last_time: Option<Instant>,
-    replay: bool,
+    replay: Replay,
 }
 
 impl LogFileDataSource {
     /// Open the given file as a data source.
     pub fn new(path: PathBuf) -> Result<Self, std::io::Error> {
         let file = File::open(&path)?;
-        let is_json = path.extension().map(|ext| ext == "json").unwrap_or(false);
+        let format = path.extension().map(|ext| if ext == "json" { LogFormat::Json } else { LogFormat::Cobs });
 
         Ok(Self {
             path: Some(path),
             name: None,
             file: Some(file),
-            buffer: Vec::new(),
-            messages: Vec::new(),
-            is_json,
+            parser: LogParser::new(format),
+            start: Instant::now(),
             vehicle_states: Vec::new(),
+            log_messages: Vec::new(),
+            log_message_times: Vec::new(),
+            mode_transitions: Vec::new(),
+            last_vehicle_times: VecDeque::new(),
             last_time: None,
-            replay: false,
+            replay: Replay::new(false),
         })
     }
 
     /// Create given data source using the given name and bytes. The name is
     /// only passed to the data source to allow identifying it based on the
-    /// status text.
+    /// status text. If `replay` is set, playback starts at the beginning of
+    /// the log instead of showing all data immediately.
     pub fn from_bytes(name: Option<String>, bytes: Vec<u8>, replay: bool) -> Self {
-        let is_json = bytes[0] == b'[';
+        let mut parser = LogParser::new(None);
+        let msgs = parser.push(&bytes);
+        parser.finish();
 
-        Self {
+        let mut data_source = Self {
             path: None,
             name,
             file: None,
-            buffer: bytes,
-            messages: Vec::new(),
-            is_json,
+            parser,
+            start: Instant::now(),
             vehicle_states: Vec::new(),
+            log_messages: Vec::new(),
+            log_message_times: Vec::new(),
+            mode_transitions: Vec::new(),
+            last_vehicle_times: VecDeque::new(),
             last_time: None,
-            replay,
-        }
+            replay: Replay::new(replay),
+        };
+
+        data_source.add_messages(msgs);
+        data_source
     }
-}
 
-impl DataSource for LogFileDataSource {
-    fn update(&mut self, ctx: &egui::Context) {
-        if let Some(file) = self.file.as_mut() {
-            if let Err(e) = file.read_to_end(&mut self.buffer) {
-                error!("Failed to read log file: {:?}", e);
-            }
+    /// Reads all bytes appended to the file since the last call, up to a limit.
+    /// Returns true if the limit was reached and more data might be available.
+    fn read_file(&mut self) -> bool {
+        let Some(file) = self.file.as_mut() else {
+            return false;
+        };
+
+        let mut bytes = Vec::new();
+        if let Err(e) = file.by_ref().take(MAX_READ_PER_FRAME).read_to_end(&mut bytes) {
+            error!("Failed to read log file: {:?}", e);
         }
 
-        let msgs = if self.is_json {
-            if self.buffer.len() > 0 {
-                serde_json::from_slice::<Vec<DownlinkMessage>>(&self.buffer).unwrap()
-            } else {
-                vec![]
-            }
-        } else {
-            self.buffer.split_mut(|b| *b == 0x00).filter_map(|b| postcard::from_bytes_cobs(b).ok()).collect()
-        };
+        let msgs = self.parser.push(&bytes);
+        self.add_messages(msgs);
 
-        self.buffer.truncate(0);
+        bytes.len() as u64 == MAX_READ_PER_FRAME
+    }
 
+    fn add_messages(&mut self, msgs: Vec<DownlinkMessage>) {
         // We have to give an Instant to every message. We can't only use
         // the time value contained in the packet, we need to handle the
         // occasional packet with a malformed time value.
-        let start = Instant::now();
-        self.last_time.get_or_insert(start);
-        let mut last_vehicle_times: VecDeque<u32> = VecDeque::new();
+        self.last_time.get_or_insert(self.start);
         for msg in msgs.into_iter() {
-            let last = last_vehicle_times.front();
+            let last = self.last_vehicle_times.front().copied();
 
             // The GCS msgs are sent by the ground station, immediately after the received
             // message. The time value used in those is the runtime of the GCS, so always
@@ -109,34 +308,161 @@ impl DataSource for LogFileDataSource {
             let since_previous = if let DownlinkMessage::TelemetryGCS(..) = msg {
                 0
             } else {
-                last.map(|l| msg.time().saturating_sub(*l)).unwrap_or(0)
+                last.map(|l| msg.time().saturating_sub(l)).unwrap_or(0)
             };
 
             self.last_time = self.last_time.map(|t| t + Duration::from_millis(since_previous as u64));
-            last_vehicle_times.push_front(u32::max(*last.unwrap_or(&0), msg.time()));
-            last_vehicle_times.truncate(5); // TODO: use these for better filtering?
+            self.last_vehicle_times.push_front(u32::max(last.unwrap_or(0), msg.time()));
+            self.last_vehicle_times.truncate(5); // TODO: use these for better filtering?
+
+            let t = self.last_time.unwrap();
+            match msg {
+                DownlinkMessage::Log(time, loc, ll, text) => {
+                    self.log_messages.push((time, loc, ll, text));
+                    self.log_message_times.push(t);
+                }
+                msg => {
+                    let vs: VehicleState = msg.into();
+                    if let Some(mode) = vs.mode {
+                        if self.mode_transitions.last().map(|(_, m)| *m != mode).unwrap_or(true) {
+                            self.mode_transitions.push((t.duration_since(self.start), mode));
+                        }
+                    }
+                    self.vehicle_states.push((t, vs));
+                }
+            }
+        }
+    }
+
+    /// Length of the log data read so far.
+    fn duration(&self) -> Duration {
+        self.last_time.map(|t| t.duration_since(self.start)).unwrap_or_default()
+    }
 
-            self.messages.push((self.last_time.unwrap(), msg));
+    /// The instant up to which data is currently shown.
+    fn cursor(&self) -> Option<Instant> {
+        self.replay.position.map(|p| self.start + p)
+    }
+
+    fn seek(&mut self, position: Duration) {
+        self.replay.position = Some(Duration::min(position, self.duration()));
+    }
+
+    /// Advances the replay position according to the time passed since the last frame.
+    fn advance_replay(&mut self, ctx: &egui::Context) {
+        let now = Instant::now();
+        let elapsed = self.replay.last_frame.map(|t| now.duration_since(t)).unwrap_or_default();
+        self.replay.last_frame = Some(now);
+
+        if !self.replay.playing {
+            return;
         }
 
-        let pointer = if self.replay {
-            let now = Instant::now();
-            self.messages.partition_point(|(t, _)| t <= &now)
+        let duration = self.duration();
+        let position = self.replay.position.unwrap_or(duration) + elapsed.mul_f32(self.replay.speed);
+        if position < duration {
+            self.replay.position = Some(position);
+        } else if self.replay.looping {
+            self.replay.position = Some(Duration::ZERO);
         } else {
-            self.messages.len()
-        };
+            // Reached the end, go back to simply showing all data.
+            self.replay.position = None;
+            self.replay.playing = false;
+        }
+
+        ctx.request_repaint_after(Duration::from_millis(16));
+    }
 
-        for (t, msg) in self.messages.drain(..pointer) {
-            self.vehicle_states.push((t, msg.into()));
+    fn replay_ui(&mut self, ui: &mut egui::Ui) {
+        let duration = self.duration();
+        let position = self.replay.position.unwrap_or(duration);
+
+        let transition_before = self
+            .mode_transitions
+            .iter()
+            .rev()
+            .find(|(t, _)| *t + Duration::from_millis(500) < position)
+            .map(|(t, _)| *t);
+        if ui.button("⏮").on_hover_text("Previous flight mode transition").clicked() {
+            self.seek(transition_before.unwrap_or_default());
         }
 
-        if self.replay {
-            ctx.request_repaint_after(Duration::from_millis(16));
+        let play_pause = if self.replay.playing { "⏸" } else { "▶" };
+        if ui.button(play_pause).clicked() {
+            self.replay.playing = !self.replay.playing;
+            if self.replay.playing && self.replay.position.is_none() {
+                self.seek(Duration::ZERO);
+            }
         }
+
+        let transition_after = self.mode_transitions.iter().find(|(t, _)| *t > position).map(|(t, _)| *t);
+        let next_button = ui.add_enabled(transition_after.is_some(), egui::Button::new("⏭"));
+        if next_button.on_hover_text("Next flight mode transition").clicked() {
+            self.seek(transition_after.unwrap_or_default());
+        }
+
+        ui.menu_button("⚑", |ui| {
+            for (t, mode) in self.mode_transitions.clone().into_iter() {
+                let text = format!("{:>7.1}s {:?}", t.as_secs_f32(), mode);
+                let text = RichText::new(text).monospace().color(mode.color());
+                if ui.button(text).clicked() {
+                    self.seek(t);
+                    ui.close_menu();
+                }
+            }
+        })
+        .response
+        .on_hover_text("Jump to flight mode transition");
+
+        let mut seconds = position.as_secs_f32();
+        ui.spacing_mut().slider_width = f32::max(ui.available_width() - 250.0, 50.0);
+        let slider = egui::Slider::new(&mut seconds, 0.0..=duration.as_secs_f32()).show_value(false);
+        if ui.add(slider).changed() {
+            self.seek(Duration::from_secs_f32(seconds));
+        }
+
+        ui.monospace(format!("{:>6.1}s / {:.1}s", position.as_secs_f32(), duration.as_secs_f32()));
+
+        egui::ComboBox::from_id_source("replay_speed")
+            .width(60.0)
+            .selected_text(format!("{}x", self.replay.speed))
+            .show_ui(ui, |ui| {
+                for speed in REPLAY_SPEEDS {
+                    ui.selectable_value(&mut self.replay.speed, speed, format!("{}x", speed));
+                }
+            });
+
+        ui.toggle_value(&mut self.replay.looping, "🔁").on_hover_text("Loop");
+    }
+}
+
+impl DataSource for LogFileDataSource {
+    fn update(&mut self, ctx: &egui::Context) {
+        if self.read_file() {
+            ctx.request_repaint();
+        } else if self.file.is_some() {
+            ctx.request_repaint_after(FILE_POLL_INTERVAL);
+        }
+
+        self.advance_replay(ctx);
     }
 
     fn vehicle_states<'a>(&'a self) -> Iter<'_, (Instant, VehicleState)> {
-        self.vehicle_states.iter()
+        let pointer = match self.cursor() {
+            Some(cursor) => self.vehicle_states.partition_point(|(t, _)| *t <= cursor),
+            None => self.vehicle_states.len(),
+        };
+
+        self.vehicle_states[..pointer].iter()
+    }
+
+    fn log_messages<'a>(&'a self) -> Iter<'_, (u32, String, LogLevel, String)> {
+        let pointer = match self.cursor() {
+            Some(cursor) => self.log_message_times.partition_point(|t| *t <= cursor),
+            None => self.log_messages.len(),
+        };
+
+        self.log_messages[..pointer].iter()
     }
 
     fn fc_settings<'a>(&'a mut self) -> Option<&'a Settings> {
@@ -148,8 +474,24 @@ impl DataSource for LogFileDataSource {
     }
 
     fn reset(&mut self) {
-        self.messages.truncate(0);
+        // Start reading the file from the beginning again, with a fresh parser
+        // so no partial frame from before the reset ends up in the new data.
+        if let Some(file) = self.file.as_mut() {
+            if let Err(e) = file.seek(SeekFrom::Start(0)) {
+                error!("Failed to rewind log file: {:?}", e);
+            }
+        }
+
+        self.parser = LogParser::new(self.parser.format);
+        self.start = Instant::now();
         self.vehicle_states.truncate(0);
+        self.log_messages.truncate(0);
+        self.log_message_times.truncate(0);
+        self.mode_transitions.truncate(0);
+        self.last_vehicle_times.truncate(0);
+        self.last_time = None;
+        self.replay.position = None;
+        self.replay.playing = false;
     }
 
     fn send(&mut self, _msg: UplinkMessage) -> Result<(), SendError<UplinkMessage>> {
@@ -161,12 +503,7 @@ impl DataSource for LogFileDataSource {
     }
 
     fn end(&self) -> Option<Instant> {
-        if self.replay {
-            let last = self.last_time.unwrap_or(Instant::now());
-            Some(Instant::min(Instant::now(), last))
-        } else {
-            self.last_time
-        }
+        self.cursor().or(self.last_time)
     }
 
     fn status_bar_ui(&mut self, ui: &mut egui::Ui) {
@@ -177,6 +514,17 @@ impl DataSource for LogFileDataSource {
             .or(self.name.clone())
             .unwrap_or_default();
         ui.weak(name);
+
+        let stats = self.parser.stats;
+        if stats.bad_frames > 0 || stats.skipped_bytes > 0 {
+            ui.colored_label(
+                Color32::from_rgb(0xfa, 0xbd, 0x2f),
+                format!("⚠ {} bad frames, {} bytes skipped", stats.bad_frames, stats.skipped_bytes),
+            );
+        }
+
+        ui.separator();
+        self.replay_ui(ui);
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -187,3 +535,135 @@ impl DataSource for LogFileDataSource {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(time: u32) -> DownlinkMessage {
+        DownlinkMessage::TelemetryGCS(TelemetryGCS {
+            time,
+            lora_rssi: 100,
+            lora_rssi_signal: 110,
+            lora_snr: 20,
+        })
+    }
+
+    fn times(msgs: &[DownlinkMessage]) -> Vec<u32> {
+        msgs.iter()
+            .map(|msg| match msg {
+                DownlinkMessage::TelemetryGCS(gcs) => gcs.time,
+                _ => panic!("unexpected message {:?}", msg),
+            })
+            .collect()
+    }
+
+    fn cobs_log(range: std::ops::Range<u32>) -> Vec<u8> {
+        range.flat_map(|t| message(t).serialize().unwrap()).collect()
+    }
+
+    fn json_log(range: std::ops::Range<u32>) -> String {
+        let lines: Vec<String> = range.map(|t| serde_json::to_string(&message(t)).unwrap()).collect();
+        format!("[\n{}\n]\n", lines.join(",\n"))
+    }
+
+    #[test]
+    fn cobs_frames_split_across_pushes() {
+        let log = cobs_log(0..10);
+        let mut parser = LogParser::new(None);
+
+        // Feed the log a few bytes at a time, splitting most frames.
+        let msgs: Vec<DownlinkMessage> = log.chunks(3).flat_map(|chunk| parser.push(chunk)).collect();
+        parser.finish();
+
+        assert_eq!(times(&msgs), (0..10).collect::<Vec<_>>());
+        assert_eq!(parser.stats, LogCorruptionStats::default());
+    }
+
+    #[test]
+    fn cobs_corrupt_frame_is_skipped() {
+        let mut log = cobs_log(0..3);
+        let garbage = [0x05, 0xff, 0xff, 0x00];
+        log.extend_from_slice(&garbage);
+        log.extend(cobs_log(3..6));
+
+        let mut parser = LogParser::new(Some(LogFormat::Cobs));
+        let msgs = parser.push(&log);
+        parser.finish();
+
+        assert_eq!(times(&msgs), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(parser.stats.bad_frames, 1);
+        assert_eq!(parser.stats.skipped_bytes, garbage.len());
+    }
+
+    #[test]
+    fn cobs_truncated_frame_counted_on_finish() {
+        let log = cobs_log(0..2);
+        let mut parser = LogParser::new(None);
+        let msgs = parser.push(&log[..log.len() - 2]);
+        assert_eq!(times(&msgs), vec![0]);
+
+        parser.finish();
+        assert_eq!(parser.stats.bad_frames, 1);
+    }
+
+    #[test]
+    fn json_partial_lines() {
+        let log = json_log(0..5);
+        let mut parser = LogParser::new(None);
+
+        let msgs: Vec<DownlinkMessage> = log.as_bytes().chunks(7).flat_map(|chunk| parser.push(chunk)).collect();
+        parser.finish();
+
+        assert_eq!(parser.format, Some(LogFormat::Json));
+        assert_eq!(times(&msgs), (0..5).collect::<Vec<_>>());
+        assert_eq!(parser.stats, LogCorruptionStats::default());
+    }
+
+    #[test]
+    fn json_corrupt_line_is_skipped() {
+        let mut lines: Vec<String> = (0..4).map(|t| serde_json::to_string(&message(t)).unwrap()).collect();
+        lines.insert(2, "{\"TelemetryGCS\": {\"time\": ".to_string());
+        let log = format!("[\n{}\n]\n", lines.join(",\n"));
+
+        let mut parser = LogParser::new(None);
+        let msgs = parser.push(log.as_bytes());
+        parser.finish();
+
+        assert_eq!(times(&msgs), vec![0, 1, 2, 3]);
+        assert_eq!(parser.stats.bad_frames, 1);
+    }
+
+    #[test]
+    fn json_truncated_log_counted_on_finish() {
+        let log = json_log(0..3);
+        let cut = log.rfind("time").unwrap();
+        let mut parser = LogParser::new(None);
+        let msgs = parser.push(&log.as_bytes()[..cut]);
+        parser.finish();
+
+        assert_eq!(times(&msgs), vec![0, 1]);
+        assert_eq!(parser.stats.bad_frames, 1);
+    }
+
+    #[test]
+    fn reset_rereads_file_from_start() {
+        // End the file in the middle of a frame, leaving it in the parser's buffer.
+        let mut log = cobs_log(0..10);
+        log.truncate(log.len() - 3);
+        let path = std::env::temp_dir().join(format!("sam-reset-test-{}.log", std::process::id()));
+        std::fs::write(&path, &log).unwrap();
+
+        let mut data_source = LogFileDataSource::new(path.clone()).unwrap();
+        data_source.read_file();
+        assert_eq!(data_source.vehicle_states.len(), 9);
+
+        data_source.reset();
+        assert!(data_source.last_time.is_none());
+        data_source.read_file();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(data_source.vehicle_states.len(), 9);
+        assert_eq!(data_source.parser.stats, LogCorruptionStats::default());
+    }
+}
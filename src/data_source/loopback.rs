@@ -0,0 +1,97 @@
+//! A stand-in for the flight computer, for hardware-in-the-loop simulations
+//! without hardware. Runs mithril's state estimator on the host, fed by the
+//! same sensor sample frames a real FC would receive.
+
+use std::sync::mpsc::{Receiver, Sender};
+
+use nalgebra::Vector3;
+
+use mithril::settings::*;
+use mithril::state_estimation::*;
+use mithril::telemetry::*;
+
+use crate::data_source::hil::SensorSample;
+use crate::simulation::atmosphere::pressure_to_altitude;
+use crate::simulation::link::scheduled_message;
+use crate::simulation::*;
+
+/// Estimates the vehicle's state from the received sensor sample frames and
+/// sends telemetry on the FC's schedule, until the data source is dropped.
+pub fn loopback_fc(settings: Settings, frame_rx: Receiver<Vec<u8>>, downlink_tx: Sender<DownlinkMessage>) {
+    let data_rate = settings.default_data_rate;
+    let mut state_estimator = StateEstimator::new(1000.0 / (SIMULATION_TICK_MS as f32), settings);
+    let mut mode = FlightMode::Idle;
+
+    for mut frame in frame_rx.iter() {
+        let Some(sample) = SensorSample::from_frame(&mut frame) else {
+            continue;
+        };
+
+        let gyroscope = sample.gyroscope.map(Vector3::from);
+        let accelerometer1 = sample.accelerometer1.map(Vector3::from);
+        let accelerometer2 = sample.accelerometer2.map(Vector3::from);
+        let magnetometer = sample.magnetometer.map(Vector3::from);
+        let altitude_baro = sample.pressure_baro.map(pressure_to_altitude);
+        state_estimator.update(
+            std::num::Wrapping(sample.time),
+            mode,
+            gyroscope,
+            accelerometer1,
+            accelerometer2,
+            magnetometer,
+            altitude_baro,
+        );
+
+        // Nobody can send us an arm command, so we arm as soon as the arm
+        // voltage is present.
+        let arm_voltage = sample.arm_voltage.unwrap_or(0);
+        if mode == FlightMode::Idle && arm_voltage > 0 {
+            mode = FlightMode::Armed;
+        }
+
+        if let Some(new_mode) = state_estimator.new_mode(arm_voltage, None) {
+            mode = new_mode;
+        }
+
+        let vs = VehicleState {
+            time: sample.time,
+            mode: Some(mode),
+            orientation: state_estimator.orientation,
+            altitude_asl: Some(state_estimator.altitude_asl()),
+            altitude_baro,
+            apogee_asl: Some(state_estimator.altitude_max),
+            vertical_speed: Some(state_estimator.vertical_speed()),
+            vertical_accel: state_estimator.acceleration_world_raw().map(|acc| acc.z),
+            vertical_accel_filtered: Some(state_estimator.vertical_acceleration()),
+            gyroscope,
+            accelerometer1,
+            accelerometer2,
+            magnetometer,
+            pressure_baro: sample.pressure_baro,
+            temperature_baro: sample.temperature_baro,
+            gps_fix: sample.gps_fix,
+            num_satellites: sample.num_satellites,
+            hdop: sample.hdop,
+            latitude: sample.latitude,
+            longitude: sample.longitude,
+            altitude_gps_asl: sample.altitude_gps_asl,
+            ..Default::default()
+        };
+
+        let Some(msg) = scheduled_message(&vs, data_rate) else {
+            continue;
+        };
+
+        let Ok(mut serialized) = msg.serialize() else {
+            continue;
+        };
+
+        let Ok(msg) = postcard::from_bytes_cobs(serialized.as_mut_slice()) else {
+            continue;
+        };
+
+        if downlink_tx.send(msg).is_err() {
+            break;
+        }
+    }
+}
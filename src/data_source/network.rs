@@ -0,0 +1,487 @@
+//! Network telemetry relay. A ground station with a connected USB device can
+//! rebroadcast all received downlink messages to other GCS instances, either
+//! via plain TCP or via WebSockets. Messages are sent using the same
+//! COBS/postcard framing used on the serial port. The `NetworkDataSource`
+//! connects to such a relay and behaves like a live data source.
+
+use std::any::Any;
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::slice::Iter;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, SendError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use eframe::epaint::Color32;
+use log::*;
+use tungstenite::Message;
+
+use mithril::settings::*;
+use mithril::telemetry::*;
+
+use crate::data_source::log_file::{decode_cobs_frames, LogCorruptionStats};
+use crate::data_source::serial::link_quality;
+use crate::data_source::DataSource;
+use crate::settings::RelaySettings;
+
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(1000);
+const READ_TIMEOUT: Duration = Duration::from_millis(10);
+const WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+
+fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// Transport used to talk to relay clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RelayTransport {
+    Tcp,
+    WebSocket,
+}
+
+/// A connected relay client, served by its own thread.
+struct RelayClient {
+    addr: SocketAddr,
+    downlink_tx: Sender<Arc<Vec<u8>>>,
+}
+
+/// Relays downlink messages to clients on the network. Uplink messages are
+/// only accepted from clients allowed by the relay settings.
+pub struct RelayServer {
+    running: Arc<AtomicBool>,
+    clients: Arc<Mutex<Vec<RelayClient>>>,
+    uplink_rx: Receiver<UplinkMessage>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl RelayServer {
+    /// Starts listening for TCP and WebSocket clients on the configured ports.
+    pub fn start(settings: &RelaySettings) -> Result<Self, std::io::Error> {
+        let running = Arc::new(AtomicBool::new(true));
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let (uplink_tx, uplink_rx) = std::sync::mpsc::channel::<UplinkMessage>();
+
+        let mut threads = Vec::new();
+        let listeners = [
+            (settings.tcp_port, RelayTransport::Tcp),
+            (settings.websocket_port, RelayTransport::WebSocket),
+        ];
+
+        for (port, transport) in listeners.into_iter() {
+            let listener = TcpListener::bind(("0.0.0.0", port))?;
+            listener.set_nonblocking(true)?;
+            info!("Relaying telemetry via {:?} on port {}", transport, port);
+
+            let running = running.clone();
+            let clients = clients.clone();
+            let uplink_tx = uplink_tx.clone();
+            let settings = settings.clone();
+            threads.push(std::thread::spawn(move || {
+                accept_clients(listener, transport, running, clients, uplink_tx, settings)
+            }));
+        }
+
+        Ok(Self {
+            running,
+            clients,
+            uplink_rx,
+            threads,
+        })
+    }
+
+    /// Sends the given message to all connected clients.
+    pub fn broadcast(&self, msg: &DownlinkMessage) {
+        let serialized = Arc::new(msg.serialize().unwrap_or_default());
+        if serialized.is_empty() {
+            return;
+        }
+
+        // Client threads drop their receiver when the connection is lost
+        self.clients.lock().unwrap().retain(|c| c.downlink_tx.send(serialized.clone()).is_ok());
+    }
+
+    /// Returns all uplink messages received from allowed clients since the last call.
+    pub fn uplink_messages(&self) -> Vec<UplinkMessage> {
+        self.uplink_rx.try_iter().collect()
+    }
+
+    /// Addresses of all currently connected clients.
+    pub fn client_addresses(&self) -> Vec<SocketAddr> {
+        self.clients.lock().unwrap().iter().map(|c| c.addr).collect()
+    }
+}
+
+impl Drop for RelayServer {
+    fn drop(&mut self) {
+        // Wait for the listeners to shut down, so the ports can be reused immediately.
+        self.running.store(false, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            thread.join().unwrap_or_default();
+        }
+    }
+}
+
+/// Accepts new relay clients until the relay is stopped, spawning a thread for each.
+fn accept_clients(
+    listener: TcpListener,
+    transport: RelayTransport,
+    running: Arc<AtomicBool>,
+    clients: Arc<Mutex<Vec<RelayClient>>>,
+    uplink_tx: Sender<UplinkMessage>,
+    settings: RelaySettings,
+) {
+    while running.load(Ordering::Relaxed) {
+        let (stream, addr) = match listener.accept() {
+            Ok(client) => client,
+            Err(e) => {
+                if !is_timeout(&e) {
+                    error!("Failed to accept relay client: {:?}", e);
+                }
+                std::thread::sleep(ACCEPT_INTERVAL);
+                continue;
+            }
+        };
+
+        let uplink_tx = settings.uplink_allowed(addr.ip()).then(|| uplink_tx.clone());
+        info!("Relay client {} connected via {:?}, uplink allowed: {}", addr, transport, uplink_tx.is_some());
+
+        let (downlink_tx, downlink_rx) = std::sync::mpsc::channel();
+        clients.lock().unwrap().push(RelayClient { addr, downlink_tx });
+
+        std::thread::spawn(move || {
+            if let Err(e) = serve_client(stream, transport, downlink_rx, uplink_tx) {
+                warn!("Relay client {} disconnected: {:?}", addr, e);
+            }
+        });
+    }
+}
+
+/// Forwards downlink messages to a single client and reads uplink messages
+/// from it, until the connection is closed or the relay is stopped.
+fn serve_client(
+    stream: TcpStream,
+    transport: RelayTransport,
+    downlink_rx: Receiver<Arc<Vec<u8>>>,
+    uplink_tx: Option<Sender<UplinkMessage>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Accepted sockets may inherit the non-blocking mode of the listener
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+    let mut uplink_buffer: Vec<u8> = Vec::new();
+    let forward_uplink = |buffer: &mut Vec<u8>| -> Result<(), Box<dyn std::error::Error>> {
+        for msg in decode_cobs_frames::<UplinkMessage>(buffer, &mut LogCorruptionStats::default()) {
+            match &uplink_tx {
+                Some(tx) => tx.send(msg)?,
+                None => warn!("Ignoring uplink message from relay client: {:?}", msg),
+            }
+        }
+        Ok(())
+    };
+
+    match transport {
+        RelayTransport::Tcp => {
+            let mut stream = stream;
+            stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+            loop {
+                loop {
+                    match downlink_rx.try_recv() {
+                        Ok(serialized) => stream.write_all(&serialized)?,
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => return Ok(()),
+                    }
+                }
+
+                let mut buffer = [0u8; 1024];
+                match stream.read(&mut buffer) {
+                    Ok(0) => return Ok(()),
+                    Ok(n) => {
+                        uplink_buffer.extend_from_slice(&buffer[..n]);
+                        forward_uplink(&mut uplink_buffer)?;
+                    }
+                    Err(e) if is_timeout(&e) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        RelayTransport::WebSocket => {
+            let mut websocket = tungstenite::accept(stream).map_err(|e| e.to_string())?;
+            websocket.get_mut().set_read_timeout(Some(READ_TIMEOUT))?;
+
+            loop {
+                loop {
+                    match downlink_rx.try_recv() {
+                        Ok(serialized) => websocket.send(Message::Binary(serialized.to_vec()))?,
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => return Ok(()),
+                    }
+                }
+
+                match websocket.read() {
+                    Ok(Message::Binary(bytes)) => {
+                        uplink_buffer.extend_from_slice(&bytes);
+                        forward_uplink(&mut uplink_buffer)?;
+                    }
+                    Ok(Message::Close(_)) => return Ok(()),
+                    Ok(_) => {}
+                    Err(tungstenite::Error::Io(e)) if is_timeout(&e) => {}
+                    Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+    }
+}
+
+/// The current state of our connection to a relay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkStatus {
+    Connecting,
+    Connected,
+    Error,
+}
+
+/// Connects to the relay at the given address, reads downlink messages to
+/// `downlink_tx`, and writes uplink messages from `uplink_rx` to the relay.
+/// Addresses starting with `ws://` are connected to via WebSocket, all others
+/// are treated as `host:port` of a plain TCP relay.
+fn relay_connection(
+    ctx: &egui::Context,
+    address: &str,
+    status_tx: &Sender<NetworkStatus>,
+    downlink_tx: &Sender<DownlinkMessage>,
+    uplink_rx: &Receiver<UplinkMessage>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut downlink_buffer: Vec<u8> = Vec::new();
+    let mut handle_downlink = |bytes: &[u8]| -> Result<(), Box<dyn std::error::Error>> {
+        downlink_buffer.extend_from_slice(bytes);
+        for msg in decode_cobs_frames::<DownlinkMessage>(&mut downlink_buffer, &mut LogCorruptionStats::default()) {
+            downlink_tx.send(msg)?;
+            ctx.request_repaint();
+        }
+        Ok(())
+    };
+
+    if address.starts_with("ws://") {
+        let (mut websocket, _response) = tungstenite::connect(address)?;
+        if let tungstenite::stream::MaybeTlsStream::Plain(stream) = websocket.get_mut() {
+            stream.set_read_timeout(Some(READ_TIMEOUT))?;
+            stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        }
+
+        status_tx.send(NetworkStatus::Connected)?;
+        ctx.request_repaint();
+
+        loop {
+            for msg in uplink_rx.try_iter() {
+                websocket.send(Message::Binary(msg.serialize().unwrap_or_default()))?;
+            }
+
+            match websocket.read() {
+                Ok(Message::Binary(bytes)) => handle_downlink(&bytes)?,
+                Ok(Message::Close(_)) => return Err("Connection closed by relay".into()),
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e)) if is_timeout(&e) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    } else {
+        let mut stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+        status_tx.send(NetworkStatus::Connected)?;
+        ctx.request_repaint();
+
+        loop {
+            for msg in uplink_rx.try_iter() {
+                stream.write_all(&msg.serialize().unwrap_or_default())?;
+            }
+
+            let mut buffer = [0u8; 4096];
+            match stream.read(&mut buffer) {
+                Ok(0) => return Err("Connection closed by relay".into()),
+                Ok(n) => handle_downlink(&buffer[..n])?,
+                Err(e) if is_timeout(&e) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Continuously (re-)connects to the given relay. Stops once the data source
+/// has been dropped.
+fn relay_monitor(
+    ctx: egui::Context,
+    address: String,
+    status_tx: Sender<NetworkStatus>,
+    downlink_tx: Sender<DownlinkMessage>,
+    uplink_rx: Receiver<UplinkMessage>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    loop {
+        status_tx.send(NetworkStatus::Connecting)?;
+        if let Err(e) = relay_connection(&ctx, &address, &status_tx, &downlink_tx, &uplink_rx) {
+            warn!("Relay connection to {} failed: {:?}", address, e);
+            status_tx.send(NetworkStatus::Error)?;
+            ctx.request_repaint();
+        }
+
+        std::thread::sleep(RECONNECT_INTERVAL);
+    }
+}
+
+/// A data source receiving telemetry from the relay of another ground station.
+pub struct NetworkDataSource {
+    address: String,
+    status_rx: Receiver<NetworkStatus>,
+    downlink_rx: Receiver<DownlinkMessage>,
+    uplink_tx: Sender<UplinkMessage>,
+    status: NetworkStatus,
+
+    vehicle_states: Vec<(Instant, VehicleState)>,
+    log_messages: Vec<(u32, String, LogLevel, String)>,
+    fc_settings: Option<Settings>,
+    message_receipt_times: VecDeque<(Instant, u32)>,
+    last_time: Option<Instant>,
+}
+
+impl NetworkDataSource {
+    /// Create a new data source, connecting to the relay at the given address.
+    pub fn new(ctx: &egui::Context, address: String) -> Self {
+        let (downlink_tx, downlink_rx) = std::sync::mpsc::channel::<DownlinkMessage>();
+        let (uplink_tx, uplink_rx) = std::sync::mpsc::channel::<UplinkMessage>();
+        let (status_tx, status_rx) = std::sync::mpsc::channel::<NetworkStatus>();
+
+        let ctx = ctx.clone();
+        let addr = address.clone();
+        std::thread::spawn(move || relay_monitor(ctx, addr, status_tx, downlink_tx, uplink_rx).unwrap_or_default());
+
+        Self {
+            address,
+            status_rx,
+            downlink_rx,
+            uplink_tx,
+            status: NetworkStatus::Connecting,
+            vehicle_states: Vec::new(),
+            log_messages: Vec::new(),
+            fc_settings: None,
+            message_receipt_times: VecDeque::new(),
+            last_time: None,
+        }
+    }
+}
+
+impl DataSource for NetworkDataSource {
+    fn update(&mut self, _ctx: &egui::Context) {
+        self.message_receipt_times.retain(|(i, _)| i.elapsed() < Duration::from_millis(1000));
+
+        for status in self.status_rx.try_iter() {
+            self.status = status;
+        }
+
+        for msg in self.downlink_rx.try_iter().collect::<Vec<_>>().into_iter() {
+            if let DownlinkMessage::TelemetryGCS(..) = msg {
+            } else {
+                self.message_receipt_times.push_back((Instant::now(), msg.time()));
+            }
+
+            match msg {
+                DownlinkMessage::Log(t, loc, ll, msg) => {
+                    self.log_messages.push((t, loc, ll, msg));
+                }
+                DownlinkMessage::Settings(settings) => {
+                    self.fc_settings = Some(settings);
+                }
+                _ => {
+                    let now = Instant::now();
+                    self.vehicle_states.push((now, msg.into()));
+                    self.last_time = Some(now);
+                }
+            }
+        }
+    }
+
+    fn vehicle_states<'a>(&'a self) -> Iter<'_, (Instant, VehicleState)> {
+        self.vehicle_states.iter()
+    }
+
+    fn log_messages<'a>(&'a self) -> Iter<'_, (u32, String, LogLevel, String)> {
+        self.log_messages.iter()
+    }
+
+    fn fc_settings<'a>(&'a mut self) -> Option<&'a Settings> {
+        self.fc_settings.as_ref()
+    }
+
+    fn fc_settings_mut<'a>(&'a mut self) -> Option<&'a mut Settings> {
+        self.fc_settings.as_mut()
+    }
+
+    fn reset(&mut self) {
+        self.vehicle_states.truncate(0);
+        self.log_messages.truncate(0);
+        self.fc_settings = None;
+        self.message_receipt_times.truncate(0);
+    }
+
+    /// Uplink messages are forwarded by the relay, if it allows uplink from this client.
+    fn send(&mut self, msg: UplinkMessage) -> Result<(), SendError<UplinkMessage>> {
+        self.uplink_tx.send(msg)
+    }
+
+    fn send_command(&mut self, cmd: Command) -> Result<(), SendError<UplinkMessage>> {
+        self.send(UplinkMessage::Command(cmd))
+    }
+
+    fn end(&self) -> Option<Instant> {
+        let postroll = Duration::from_secs_f64(10.0);
+
+        self.last_time.map(|t| {
+            if t.elapsed() < postroll {
+                Instant::now()
+            } else {
+                t + postroll
+            }
+        })
+    }
+
+    fn is_live(&self) -> bool {
+        true
+    }
+
+    fn link_quality(&self) -> Option<f32> {
+        let data_rate = self.vehicle_states.iter().rev().find_map(|(_t, vs)| vs.data_rate);
+        Some(link_quality(data_rate, self.message_receipt_times.len()))
+    }
+
+    fn status_bar_ui(&mut self, ui: &mut egui::Ui) {
+        if ui.button("⏮  Reset").clicked() {
+            self.reset();
+        }
+
+        ui.separator();
+
+        let (status_color, status_text) = match self.status {
+            NetworkStatus::Connecting => (Color32::from_rgb(0x92, 0x83, 0x74), "Connecting"),
+            NetworkStatus::Connected => (Color32::from_rgb(0x98, 0x97, 0x1a), "Relay"),
+            NetworkStatus::Error => (Color32::from_rgb(0xcc, 0x24, 0x1d), "Error"),
+        };
+
+        ui.colored_label(status_color, status_text);
+        ui.label(self.fc_settings().map(|s| s.identifier.clone()).unwrap_or_default());
+        ui.weak(&self.address);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
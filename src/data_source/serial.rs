@@ -21,8 +21,10 @@ use log::*;
 use mithril::settings::*;
 use mithril::telemetry::*;
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::data_source::network::RelayServer;
 use crate::data_source::DataSource;
-use crate::settings::AppSettings;
+use crate::settings::{AppSettings, RelaySettings};
 
 pub const BAUD_RATE: u32 = 115_200;
 pub const MESSAGE_TIMEOUT: Duration = Duration::from_millis(500);
@@ -46,7 +48,8 @@ pub enum SerialStatus {
 }
 
 /// Opens the given serial port, reads downlink messages to `downlink_tx`,
-/// and writes uplink messages from `uplink_rx` to the device.
+/// and writes uplink messages from `uplink_rx` to the device. Frames from
+/// `frame_rx` are written as they are, without being serialized.
 ///
 /// If `send_heartbeats` is set, regular heartbeat messages will be sent to
 /// the device. If no heartbeats are sent, the device will not send log
@@ -56,6 +59,7 @@ pub fn downlink_port(
     ctx: Option<egui::Context>,
     downlink_tx: &mut Sender<DownlinkMessage>,
     uplink_rx: &mut Receiver<UplinkMessage>,
+    frame_rx: Option<&Receiver<Vec<u8>>>,
     port: String,
     send_heartbeats: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -71,6 +75,9 @@ pub fn downlink_port(
     let mut last_heartbeat = Instant::now() - HEARTBEAT_INTERVAL * 2;
     let mut last_message = Instant::now();
 
+    // Frames queued while no device was connected are outdated.
+    frame_rx.iter().for_each(|rx| rx.try_iter().for_each(drop));
+
     // Stop if no messages are sent, even if a connection exists.
     while now.duration_since(last_message) < MESSAGE_TIMEOUT {
         // Send pending uplink messages, or heartbeats if necessary.
@@ -83,6 +90,16 @@ pub fn downlink_port(
             last_heartbeat = now;
         }
 
+        // Frames such as hardware-in-the-loop sensor samples have to arrive in
+        // real time, so all pending ones are written at once.
+        let frames: Vec<Vec<u8>> = frame_rx.iter().flat_map(|rx| rx.try_iter()).collect();
+        if !frames.is_empty() {
+            for frame in frames.iter() {
+                port.write_all(frame)?;
+            }
+            port.flush()?;
+        }
+
         // Read all available bytes from the serial port. Our timeout is really
         // short (we don't want to block here for too long), so timeouts are
         // common, and simply ignored, and treated like an empty read.
@@ -124,6 +141,18 @@ pub fn downlink_port(
     Ok(())
 }
 
+/// Estimates the link quality in percent, based on the number of messages
+/// received within the last second and the vehicle's telemetry data rate.
+pub fn link_quality(data_rate: Option<TelemetryDataRate>, messages_last_second: usize) -> f32 {
+    let expected = match data_rate.unwrap_or(TelemetryDataRate::Low) {
+        // TODO: don't hardcode these?
+        TelemetryDataRate::Low => 15,
+        TelemetryDataRate::High => 35,
+    };
+    let percentage = ((messages_last_second as f32) / (expected as f32)) * 100.0;
+    f32::min(percentage, 100.0)
+}
+
 /// Finds the first connected USB serial port
 pub fn find_serial_port() -> Option<String> {
     serialport::available_ports()
@@ -148,13 +177,21 @@ pub fn downlink_monitor(
     serial_status_tx: Sender<(SerialStatus, Option<String>)>,
     mut downlink_tx: Sender<DownlinkMessage>,
     mut uplink_rx: Receiver<UplinkMessage>,
+    frame_rx: Option<Receiver<Vec<u8>>>,
     send_heartbeats: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     loop {
         // If a device was connected, start reading messages.
         if let Some(p) = find_serial_port() {
             serial_status_tx.send((SerialStatus::Connected, Some(p.clone())))?;
-            if let Err(e) = downlink_port(ctx.clone(), &mut downlink_tx, &mut uplink_rx, p.clone(), send_heartbeats) {
+            if let Err(e) = downlink_port(
+                ctx.clone(),
+                &mut downlink_tx,
+                &mut uplink_rx,
+                frame_rx.as_ref(),
+                p.clone(),
+                send_heartbeats,
+            ) {
                 eprintln!("{:?}", e);
                 serial_status_tx.send((SerialStatus::Error, Some(p)))?;
                 if let Some(ctx) = &ctx {
@@ -174,10 +211,11 @@ pub fn spawn_downlink_monitor(
     serial_status_tx: Sender<(SerialStatus, Option<String>)>,
     downlink_tx: Sender<DownlinkMessage>,
     uplink_rx: Receiver<UplinkMessage>,
+    frame_rx: Option<Receiver<Vec<u8>>>,
     send_heartbeats: bool,
 ) -> JoinHandle<()> {
     std::thread::spawn(move || {
-        downlink_monitor(ctx, serial_status_tx, downlink_tx, uplink_rx, send_heartbeats).unwrap_or_default()
+        downlink_monitor(ctx, serial_status_tx, downlink_tx, uplink_rx, frame_rx, send_heartbeats).unwrap_or_default()
     })
 }
 
@@ -185,33 +223,40 @@ pub struct SerialDataSource {
     serial_status_rx: Receiver<(SerialStatus, Option<String>)>,
     downlink_rx: Receiver<DownlinkMessage>,
     uplink_tx: Sender<UplinkMessage>,
+    frame_tx: Sender<Vec<u8>>,
 
     serial_port: Option<String>,
     serial_status: SerialStatus,
 
     lora_settings: LoRaSettings,
+    relay_settings: RelaySettings,
+    #[cfg(not(target_arch = "wasm32"))]
+    relay: Option<RelayServer>,
 
     telemetry_log_path: PathBuf,
     telemetry_log_file: Result<File, std::io::Error>,
 
     vehicle_states: Vec<(Instant, VehicleState)>,
+    log_messages: Vec<(u32, String, LogLevel, String)>,
     fc_settings: Option<Settings>,
     message_receipt_times: VecDeque<(Instant, u32)>,
     last_time: Option<Instant>,
 }
 
 impl SerialDataSource {
-    /// Create a new serial port data source.
-    pub fn new(ctx: &egui::Context, lora_settings: LoRaSettings) -> Self {
+    /// Create a new serial port data source. If enabled in the settings, the
+    /// received telemetry is relayed to other ground stations.
+    pub fn new(ctx: &egui::Context, settings: &AppSettings) -> Self {
         let (downlink_tx, downlink_rx) = std::sync::mpsc::channel::<DownlinkMessage>();
         let (uplink_tx, uplink_rx) = std::sync::mpsc::channel::<UplinkMessage>();
+        let (frame_tx, frame_rx) = std::sync::mpsc::channel::<Vec<u8>>();
         let (serial_status_tx, serial_status_rx) = std::sync::mpsc::channel::<(SerialStatus, Option<String>)>();
 
         let ctx = ctx.clone();
 
         // There are no serial ports on wasm, and on android the Java side handles this.
         #[cfg(all(not(target_arch = "wasm32"), not(target_os = "android")))]
-        spawn_downlink_monitor(Some(ctx), serial_status_tx, downlink_tx, uplink_rx, true);
+        spawn_downlink_monitor(Some(ctx), serial_status_tx, downlink_tx, uplink_rx, Some(frame_rx), true);
 
         let telemetry_log_path = Self::new_telemetry_log_path();
         let telemetry_log_file = File::create(&telemetry_log_path);
@@ -220,12 +265,17 @@ impl SerialDataSource {
             serial_status_rx,
             downlink_rx,
             uplink_tx,
+            frame_tx,
             serial_port: None,
             serial_status: SerialStatus::Init,
-            lora_settings,
+            lora_settings: settings.lora.clone(),
+            relay_settings: settings.relay.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            relay: Self::start_relay(&settings.relay),
             telemetry_log_path,
             telemetry_log_file,
             vehicle_states: Vec::new(),
+            log_messages: Vec::new(),
             fc_settings: None,
             message_receipt_times: VecDeque::new(),
             last_time: None,
@@ -260,6 +310,33 @@ impl SerialDataSource {
         }
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start_relay(settings: &RelaySettings) -> Option<RelayServer> {
+        if !settings.enabled {
+            return None;
+        }
+
+        match RelayServer::start(settings) {
+            Ok(relay) => Some(relay),
+            Err(e) => {
+                error!("Failed to start telemetry relay: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// Whether a device is currently connected.
+    pub fn is_connected(&self) -> bool {
+        self.serial_status == SerialStatus::Connected
+    }
+
+    /// Sends a frame that is not an uplink message to the device, such as a
+    /// sensor sample for hardware-in-the-loop testing. The frame has to be
+    /// COBS-encoded already.
+    pub fn send_frame(&mut self, frame: Vec<u8>) -> Result<(), SendError<Vec<u8>>> {
+        self.frame_tx.send(frame)
+    }
+
     /// Stores a received message in the telemetry log.
     fn write_to_telemetry_log(&mut self, msg: &DownlinkMessage) {
         // TODO
@@ -301,6 +378,21 @@ impl DataSource for SerialDataSource {
         #[cfg(target_os = "android")]
         let msgs: Vec<_> = unsafe { DOWNLINK_MESSAGE_RECEIVER.as_mut().unwrap().try_iter().collect() };
 
+        // Pass everything on to other ground stations, and accept their uplink messages
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let uplink_msgs = self.relay.as_ref().map(|r| r.uplink_messages()).unwrap_or_default();
+            for msg in uplink_msgs.into_iter() {
+                self.send(msg).unwrap();
+            }
+
+            if let Some(relay) = self.relay.as_ref() {
+                for msg in msgs.iter() {
+                    relay.broadcast(msg);
+                }
+            }
+        }
+
         for msg in msgs.into_iter() {
             self.write_to_telemetry_log(&msg);
 
@@ -311,7 +403,9 @@ impl DataSource for SerialDataSource {
             }
 
             match msg {
-                DownlinkMessage::Log(..) => {}
+                DownlinkMessage::Log(t, loc, ll, msg) => {
+                    self.log_messages.push((t, loc, ll, msg));
+                }
                 DownlinkMessage::Settings(settings) => {
                     self.fc_settings = Some(settings);
                 }
@@ -329,6 +423,10 @@ impl DataSource for SerialDataSource {
         self.vehicle_states.iter()
     }
 
+    fn log_messages<'a>(&'a self) -> Iter<'_, (u32, String, LogLevel, String)> {
+        self.log_messages.iter()
+    }
+
     fn fc_settings<'a>(&'a mut self) -> Option<&'a Settings> {
         self.fc_settings.as_ref()
     }
@@ -341,6 +439,7 @@ impl DataSource for SerialDataSource {
         self.telemetry_log_path = Self::new_telemetry_log_path();
         self.telemetry_log_file = File::create(&self.telemetry_log_path);
         self.vehicle_states.truncate(0);
+        self.log_messages.truncate(0);
         self.fc_settings = None;
         self.message_receipt_times.truncate(0);
     }
@@ -378,25 +477,29 @@ impl DataSource for SerialDataSource {
         })
     }
 
+    fn is_live(&self) -> bool {
+        true
+    }
+
     fn apply_settings(&mut self, settings: &AppSettings) {
         self.lora_settings = settings.lora.clone();
         self.send(UplinkMessage::ApplyLoRaSettings(self.lora_settings.clone())).unwrap();
+
+        if settings.relay != self.relay_settings {
+            self.relay_settings = settings.relay.clone();
+
+            // Stop the previous relay first to free up the ports
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                self.relay = None;
+                self.relay = Self::start_relay(&self.relay_settings);
+            }
+        }
     }
 
     fn link_quality(&self) -> Option<f32> {
-        let telemetry_data_rate = self
-            .vehicle_states
-            .iter()
-            .rev()
-            .find_map(|(_t, msg)| msg.data_rate)
-            .unwrap_or(TelemetryDataRate::Low);
-        let expected = match telemetry_data_rate {
-            // TODO: don't hardcode these?
-            TelemetryDataRate::Low => 15,
-            TelemetryDataRate::High => 35,
-        };
-        let percentage = ((self.message_receipt_times.len() as f32) / (expected as f32)) * 100.0;
-        Some(f32::min(percentage, 100.0))
+        let data_rate = self.vehicle_states.iter().rev().find_map(|(_t, msg)| msg.data_rate);
+        Some(link_quality(data_rate, self.message_receipt_times.len()))
     }
 
     fn status_bar_ui(&mut self, ui: &mut egui::Ui) {
@@ -424,6 +527,15 @@ impl DataSource for SerialDataSource {
         };
 
         ui.weak(format!("{} {}", serial_port, telemetry_log_info));
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(relay) = self.relay.as_ref() {
+            let clients = relay.client_addresses();
+            let text = format!("📡 Relay: {} client(s)", clients.len());
+            let hover = clients.iter().map(|a| a.to_string()).collect::<Vec<_>>().join("\n");
+            ui.separator();
+            ui.label(text).on_hover_text(hover);
+        }
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -16,6 +16,8 @@ use mithril::settings::*;
 use mithril::telemetry::*;
 
 use crate::data_source::DataSource;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::simulation::monte_carlo::*;
 use crate::simulation::*;
 
 #[derive(Default)]
@@ -23,15 +25,57 @@ pub struct SimulationDataSource {
     pub settings: SimulationSettings,
     state: Option<SimulationState>,
     vehicle_states: Vec<(Instant, VehicleState)>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub monte_carlo_settings: MonteCarloSettings,
+    /// The last Monte Carlo analysis, run in the background
+    #[cfg(not(target_arch = "wasm32"))]
+    pub monte_carlo: Option<MonteCarlo>,
+}
+
+impl SimulationDataSource {
+    pub fn new(settings: SimulationSettings) -> Self {
+        Self {
+            settings,
+            ..Default::default()
+        }
+    }
+
+    /// Starts a new Monte Carlo analysis based on the current settings,
+    /// replacing any previous one.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start_monte_carlo(&mut self) {
+        self.monte_carlo = Some(MonteCarlo::start(&self.settings, &self.monte_carlo_settings));
+    }
 }
 
 impl DataSource for SimulationDataSource {
-    fn update(&mut self, _ctx: &egui::Context) {
+    fn update(&mut self, ctx: &egui::Context) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(monte_carlo) = self.monte_carlo.as_mut() {
+            monte_carlo.update();
+            if !monte_carlo.done() {
+                ctx.request_repaint_after(Duration::from_millis(100));
+            }
+        }
+
         if self.state.is_none() {
             self.state = Some(SimulationState::initialize(&self.settings));
         }
 
         while !self.state.as_mut().unwrap().tick() {
+            if self.settings.link.enabled {
+                for msg in self.state.as_mut().unwrap().downlink_messages() {
+                    let vs: VehicleState = msg.into();
+                    let time = self
+                        .vehicle_states
+                        .first()
+                        .map(|(t, first)| *t + Duration::from_millis(vs.time.saturating_sub(first.time) as u64))
+                        .unwrap_or(Instant::now());
+                    self.vehicle_states.push((time, vs));
+                }
+                continue;
+            }
+
             let sim_state = self.state.as_ref().unwrap();
             if !sim_state.plottable() {
                 continue;
@@ -68,7 +112,15 @@ impl DataSource for SimulationDataSource {
         Ok(())
     }
 
-    fn send_command(&mut self, _cmd: Command) -> Result<(), SendError<UplinkMessage>> {
+    fn send_command(&mut self, cmd: Command) -> Result<(), SendError<UplinkMessage>> {
+        // The simulation runs ahead, so we have to restart it for the changes to take effect.
+        match cmd {
+            Command::SetDataRate(data_rate) => self.settings.fc_settings.default_data_rate = data_rate,
+            Command::SetTransmitPower(transmit_power) => self.settings.link.transmit_power = transmit_power,
+            _ => return Ok(()),
+        }
+
+        self.reset();
         Ok(())
     }
 
@@ -76,6 +128,13 @@ impl DataSource for SimulationDataSource {
         self.vehicle_states.last().map(|(t, _vs)| *t)
     }
 
+    fn link_quality(&self) -> Option<f32> {
+        self.state
+            .as_ref()
+            .filter(|_| self.settings.link.enabled)
+            .and_then(|state| state.link.link_quality())
+    }
+
     fn status_bar_ui(&mut self, ui: &mut egui::Ui) {
         ui.colored_label(Color32::KHAKI, "Simulation");
         // TODO: maybe computation times or something?
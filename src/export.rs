@@ -0,0 +1,123 @@
+//! Exporting logs to formats usable by other tools, e.g. spreadsheets.
+
+pub mod csv;
+pub mod gpx;
+pub mod kml;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use mithril::telemetry::*;
+
+use crate::analysis::{FlightEvent, FlightSummary};
+
+/// A single GPS position of the vehicle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackPoint {
+    /// FC time [s]
+    pub time: f64,
+    pub latitude: f32,
+    pub longitude: f32,
+    /// GPS altitude [m ASL]
+    pub altitude: f32,
+    pub num_satellites: Option<u8>,
+}
+
+/// A continuous part of the track flown in a single flight mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackSegment {
+    pub mode: Option<FlightMode>,
+    pub points: Vec<TrackPoint>,
+}
+
+/// The GPS track of a flight, split by flight mode, along with the detected
+/// flight events.
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub name: String,
+    pub segments: Vec<TrackSegment>,
+    pub events: Vec<(&'static str, FlightEvent)>,
+    /// Wall clock time of the first track point. The FC has no real-time
+    /// clock, so this needs to be supplied from elsewhere.
+    pub start_time: DateTime<Utc>,
+}
+
+impl TrackSegment {
+    pub fn name(&self) -> String {
+        self.mode.map(|m| format!("{:?}", m)).unwrap_or("Unknown".into())
+    }
+}
+
+impl Track {
+    /// Builds a track from the given states. States from the ground station
+    /// and states without a GPS fix are ignored.
+    pub fn from_states<'a>(name: String, states: impl Iterator<Item = &'a VehicleState> + Clone) -> Self {
+        let summary = FlightSummary::from_states(states.clone());
+        let events = summary.events().into_iter().map(|(name, event)| (name, event.clone())).collect();
+
+        let mut segments: Vec<TrackSegment> = Vec::new();
+        let mut mode: Option<FlightMode> = None;
+
+        for vs in states.filter(|vs| vs.gcs_lora_rssi.is_none()) {
+            mode = vs.mode.or(mode);
+
+            let has_fix = vs.gps_fix.map(|f| !matches!(f, GPSFixType::NoFix)).unwrap_or(false);
+            let position = vs.latitude.zip(vs.longitude).zip(vs.altitude_gps_asl).filter(|_| has_fix);
+            let Some(((latitude, longitude), altitude)) = position else {
+                continue;
+            };
+
+            let point = TrackPoint {
+                time: vs.time as f64 / 1000.0,
+                latitude,
+                longitude,
+                altitude,
+                num_satellites: vs.num_satellites,
+            };
+
+            match segments.last_mut() {
+                Some(segment) if segment.mode == mode => segment.points.push(point),
+                last => {
+                    // Start the new segment at the end of the previous one, to avoid gaps in the track.
+                    let mut points: Vec<_> = last.and_then(|s| s.points.last().cloned()).into_iter().collect();
+                    points.push(point);
+                    segments.push(TrackSegment { mode, points });
+                }
+            }
+        }
+
+        Self {
+            name,
+            segments,
+            events,
+            start_time: Utc::now(),
+        }
+    }
+
+    /// All track points, in order.
+    pub fn points(&self) -> impl Iterator<Item = &TrackPoint> + Clone {
+        self.segments.iter().flat_map(|s| s.points.iter())
+    }
+
+    /// FC time of the first and last track point [s]
+    pub fn time_range(&self) -> Option<(f64, f64)> {
+        let first = self.points().next()?.time;
+        let last = self.points().last()?.time;
+        Some((first, last))
+    }
+
+    /// Converts the given FC time to an ISO 8601 timestamp.
+    pub fn timestamp(&self, fc_time: f64) -> String {
+        let first = self.time_range().map(|(first, _)| first).unwrap_or(0.0);
+        let offset = chrono::Duration::milliseconds(((fc_time - first) * 1000.0) as i64);
+        (self.start_time + offset).to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
+}
+
+/// Escapes the characters that are not allowed in XML text and attributes.
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,223 @@
+//! CSV export. Flattens vehicle states into one row per sample, with one
+//! column per field of the vehicle state.
+
+use std::io::Write;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use mithril::telemetry::*;
+
+/// A single exported field of the vehicle state.
+pub struct CsvField {
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub value: fn(&VehicleState) -> Option<String>,
+}
+
+impl CsvField {
+    /// Column header, including the unit.
+    pub fn column_name(&self) -> String {
+        if self.unit.is_empty() {
+            self.name.to_string()
+        } else {
+            format!("{} [{}]", self.name, self.unit)
+        }
+    }
+}
+
+const fn field(name: &'static str, unit: &'static str, value: fn(&VehicleState) -> Option<String>) -> CsvField {
+    CsvField { name, unit, value }
+}
+
+fn num<T: ToString>(value: Option<T>) -> Option<String> {
+    value.map(|v| v.to_string())
+}
+
+/// All exportable fields. Column names are kept stable, so existing scripts
+/// keep working, new fields should only be appended.
+pub const FIELDS: &[CsvField] = &[
+    field("mode", "", |vs| vs.mode.map(|m| format!("{:?}", m))),
+    field("roll", "°", |vs| num(vs.euler_angles.map(|a| a.z))),
+    field("pitch", "°", |vs| num(vs.euler_angles.map(|a| a.x))),
+    field("yaw", "°", |vs| num(vs.euler_angles.map(|a| a.y))),
+    field("angle_of_attack", "°", |vs| num(vs.angle_of_attack)),
+    field("altitude_asl", "m", |vs| num(vs.altitude_asl)),
+    field("altitude_baro", "m", |vs| num(vs.altitude_baro)),
+    field("altitude_ground_asl", "m", |vs| num(vs.altitude_ground_asl)),
+    field("apogee_asl", "m", |vs| num(vs.apogee_asl)),
+    field("vertical_speed", "m/s", |vs| num(vs.vertical_speed)),
+    field("vertical_accel", "m/s²", |vs| num(vs.vertical_accel)),
+    field("vertical_accel_filtered", "m/s²", |vs| num(vs.vertical_accel_filtered)),
+    field("gps_fix", "", |vs| vs.gps_fix.map(|f| format!("{:?}", f))),
+    field("num_satellites", "", |vs| num(vs.num_satellites)),
+    field("hdop", "", |vs| num(vs.hdop.map(|x| x as f32 / 100.0))),
+    field("latitude", "°", |vs| num(vs.latitude)),
+    field("longitude", "°", |vs| num(vs.longitude)),
+    field("altitude_gps_asl", "m", |vs| num(vs.altitude_gps_asl)),
+    field("gyro_x", "°/s", |vs| num(vs.gyroscope.map(|a| a.x))),
+    field("gyro_y", "°/s", |vs| num(vs.gyroscope.map(|a| a.y))),
+    field("gyro_z", "°/s", |vs| num(vs.gyroscope.map(|a| a.z))),
+    field("accel1_x", "m/s²", |vs| num(vs.accelerometer1.map(|a| a.x))),
+    field("accel1_y", "m/s²", |vs| num(vs.accelerometer1.map(|a| a.y))),
+    field("accel1_z", "m/s²", |vs| num(vs.accelerometer1.map(|a| a.z))),
+    field("accel2_x", "m/s²", |vs| num(vs.accelerometer2.map(|a| a.x))),
+    field("accel2_y", "m/s²", |vs| num(vs.accelerometer2.map(|a| a.y))),
+    field("accel2_z", "m/s²", |vs| num(vs.accelerometer2.map(|a| a.z))),
+    field("mag_x", "µT", |vs| num(vs.magnetometer.map(|a| a.x))),
+    field("mag_y", "µT", |vs| num(vs.magnetometer.map(|a| a.y))),
+    field("mag_z", "µT", |vs| num(vs.magnetometer.map(|a| a.z))),
+    field("pressure_baro", "mbar", |vs| num(vs.pressure_baro)),
+    field("temperature_baro", "°C", |vs| num(vs.temperature_baro)),
+    field("battery_voltage", "V", |vs| num(vs.battery_voltage.map(|v| v as f32 / 1000.0))),
+    field("arm_voltage", "V", |vs| num(vs.arm_voltage.map(|v| v as f32 / 1000.0))),
+    field("current", "A", |vs| num(vs.current.map(|v| v as f32 / 1000.0))),
+    field("charge_voltage", "V", |vs| num(vs.charge_voltage.map(|v| v as f32 / 1000.0))),
+    field("cpu_utilization", "%", |vs| num(vs.cpu_utilization)),
+    field("flash_pointer", "B", |vs| num(vs.flash_pointer)),
+    field("gcs_rssi", "dBm", |vs| num(vs.gcs_lora_rssi.map(|x| x as f32 / -2.0))),
+    field("gcs_signal_rssi", "dBm", |vs| num(vs.gcs_lora_rssi_signal.map(|x| x as f32 / -2.0))),
+    field("gcs_snr", "dB", |vs| num(vs.gcs_lora_snr.map(|x| x as f32 / 4.0))),
+    field("vehicle_rssi", "dBm", |vs| num(vs.lora_rssi.map(|x| x as f32 / -2.0))),
+];
+
+/// Source of the time column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeBase {
+    /// Runtime of the flight computer
+    FcTime,
+    /// Time at which the message was received (or reconstructed, for log files),
+    /// relative to the first message
+    ReceiveTime,
+}
+
+/// When resampling, gaps in the data longer than this [s] are not filled
+/// with the last known values. Resampling restarts after the gap instead.
+const MAX_RESAMPLE_GAP: f64 = 1.0;
+
+/// Options for the CSV export.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOptions {
+    /// Names of the fields to export. All fields are exported if this is empty.
+    pub columns: Vec<String>,
+    pub time_base: TimeBase,
+    /// If set, rows are resampled to this fixed rate [Hz], holding the last known value of each field.
+    pub resample_rate: Option<f64>,
+    /// Written as `# key: value` comment lines before the header, e.g. the
+    /// simulation seed.
+    pub metadata: Vec<(String, String)>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            columns: Vec::new(),
+            time_base: TimeBase::ReceiveTime,
+            resample_rate: None,
+            metadata: Vec::new(),
+        }
+    }
+}
+
+/// Looks up the fields with the given names.
+pub fn select_fields(names: &[String]) -> Result<Vec<&'static CsvField>, String> {
+    if names.is_empty() {
+        return Ok(FIELDS.iter().collect());
+    }
+
+    names
+        .iter()
+        .map(|name| {
+            FIELDS.iter().find(|f| f.name == name.trim()).ok_or_else(|| {
+                let available: Vec<_> = FIELDS.iter().map(|f| f.name).collect();
+                format!("Unknown column '{}'. Available columns: {}", name, available.join(", "))
+            })
+        })
+        .collect()
+}
+
+fn write_row(writer: &mut impl Write, time: f64, values: &[Option<String>]) -> Result<(), std::io::Error> {
+    let values: Vec<&str> = values.iter().map(|v| v.as_deref().unwrap_or("")).collect();
+    writeln!(writer, "{:.3},{}", time, values.join(","))
+}
+
+/// Writes the given states as CSV, returning the number of rows written.
+pub fn write_csv<'a>(
+    mut writer: impl Write,
+    states: impl Iterator<Item = &'a (Instant, VehicleState)>,
+    options: &CsvOptions,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let fields = select_fields(&options.columns)?;
+
+    for (key, value) in &options.metadata {
+        writeln!(writer, "# {}: {}", key, value)?;
+    }
+
+    let mut header = vec!["time [s]".to_string()];
+    header.extend(fields.iter().map(|f| f.column_name()));
+    writeln!(writer, "{}", header.join(","))?;
+
+    let mut first_instant: Option<Instant> = None;
+    let mut last_fc_time: f64 = 0.0;
+    let mut latest: Vec<Option<String>> = vec![None; fields.len()];
+    let mut next_sample: Option<f64> = None;
+    let mut previous_time: Option<f64> = None;
+    let mut rows = 0;
+
+    for (instant, vs) in states {
+        let time = match options.time_base {
+            TimeBase::ReceiveTime => instant.duration_since(*first_instant.get_or_insert(*instant)).as_secs_f64(),
+            // GCS messages contain the runtime of the ground station, so we
+            // use the time of the previous FC message instead.
+            TimeBase::FcTime if vs.gcs_lora_rssi.is_some() => last_fc_time,
+            TimeBase::FcTime => vs.time as f64 / 1000.0,
+        };
+        last_fc_time = time;
+
+        let values: Vec<Option<String>> = fields.iter().map(|f| (f.value)(vs)).collect();
+
+        let Some(rate) = options.resample_rate else {
+            write_row(&mut writer, time, &values)?;
+            rows += 1;
+            continue;
+        };
+
+        // If the time jumps backwards (e.g. after an FC reboot) or there is a
+        // long gap, finish the previous segment and restart resampling.
+        let rebooted = previous_time.map(|p| time < p).unwrap_or(false);
+        previous_time = Some(time);
+        if let Some(t) = next_sample.filter(|t| rebooted || time - t > MAX_RESAMPLE_GAP) {
+            write_row(&mut writer, t, &latest)?;
+            rows += 1;
+            next_sample = None;
+        }
+        if rebooted {
+            latest.fill(None);
+        }
+
+        // Emit all samples up to the current time using the previous values,
+        // then update the values with the current state.
+        let mut t = *next_sample.get_or_insert(time);
+        while t < time {
+            write_row(&mut writer, t, &latest)?;
+            rows += 1;
+            t += 1.0 / rate;
+        }
+        next_sample = Some(t);
+
+        for (l, v) in latest.iter_mut().zip(values.into_iter()) {
+            if v.is_some() {
+                *l = v;
+            }
+        }
+    }
+
+    if let Some(t) = next_sample {
+        write_row(&mut writer, t, &latest)?;
+        rows += 1;
+    }
+
+    Ok(rows)
+}
@@ -0,0 +1,68 @@
+//! GPX export, for use with mapping and GPS tools. Uses the same GPX 1.0
+//! layout as the trackers we fly alongside the FC, with one track segment
+//! per flight mode and waypoints for flight events.
+
+use std::io::Write;
+
+use crate::export::*;
+
+/// Writes the given track as a GPX document.
+pub fn write_gpx(mut writer: impl Write, track: &Track) -> Result<(), std::io::Error> {
+    let name = xml_escape(&track.name);
+
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(writer, "<gpx version=\"1.0\"")?;
+    writeln!(writer, "     creator=\"Sam Ground Station\"")?;
+    writeln!(writer, "     xmlns=\"http://www.topografix.com/GPX/1/0\"")?;
+    writeln!(writer, "     xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\"")?;
+    writeln!(
+        writer,
+        "     xsi:schemaLocation=\"http://www.topografix.com/GPX/1/0 http://www.topografix.com/GPX/1/0/gpx.xsd\">"
+    )?;
+    writeln!(writer, "<name>{}</name>", name)?;
+    writeln!(writer, "<time>{}</time>", track.start_time.to_rfc3339_opts(SecondsFormat::Millis, true))?;
+
+    let lat = track.points().map(|p| p.latitude);
+    let lng = track.points().map(|p| p.longitude);
+    if let (Some(min_lat), Some(max_lat)) = (lat.clone().reduce(f32::min), lat.reduce(f32::max)) {
+        let (min_lng, max_lng) = (lng.clone().reduce(f32::min).unwrap(), lng.reduce(f32::max).unwrap());
+        writeln!(
+            writer,
+            "<bounds minlat=\"{}\" minlon=\"{}\" maxlat=\"{}\" maxlon=\"{}\" />",
+            min_lat, min_lng, max_lat, max_lng
+        )?;
+    }
+    writeln!(writer)?;
+
+    for (name, event) in &track.events {
+        let Some((latitude, longitude)) = event.latitude.zip(event.longitude) else {
+            continue;
+        };
+
+        writeln!(writer, "<wpt lat=\"{}\" lon=\"{}\">", latitude, longitude)?;
+        if let Some(altitude) = event.altitude_asl {
+            writeln!(writer, " <ele>{:.3}</ele>", altitude)?;
+        }
+        writeln!(writer, " <time>{}</time>", track.timestamp(event.time as f64))?;
+        writeln!(writer, " <name>{}</name>", name)?;
+        writeln!(writer, "</wpt>")?;
+    }
+
+    writeln!(writer, "<trk>")?;
+    writeln!(writer, " <name>{}</name>", name)?;
+    for segment in &track.segments {
+        writeln!(writer, " <trkseg>")?;
+        for point in &segment.points {
+            write!(writer, "  <trkpt lat=\"{}\" lon=\"{}\">", point.latitude, point.longitude)?;
+            write!(writer, "<ele>{:.3}</ele><time>{}</time>", point.altitude, track.timestamp(point.time))?;
+            if let Some(sats) = point.num_satellites {
+                write!(writer, "<sat>{}</sat>", sats)?;
+            }
+            writeln!(writer, "</trkpt>")?;
+        }
+        writeln!(writer, " </trkseg>")?;
+    }
+    writeln!(writer, "</trk>")?;
+    writeln!(writer)?;
+    writeln!(writer, "</gpx>")
+}
@@ -0,0 +1,116 @@
+//! KML export, for viewing flights in Google Earth. Each flight mode gets its
+//! own track segment and color, and flight events are added as placemarks.
+
+use std::io::Write;
+
+use mithril::telemetry::*;
+
+use crate::export::*;
+use crate::telemetry_ext::ColorExt;
+
+const MODES: [FlightMode; 7] = [
+    FlightMode::Idle,
+    FlightMode::HardwareArmed,
+    FlightMode::Armed,
+    FlightMode::Flight,
+    FlightMode::RecoveryDrogue,
+    FlightMode::RecoveryMain,
+    FlightMode::Landed,
+];
+
+const EVENT_ICON: &str = "http://maps.google.com/mapfiles/kml/paddle/red-circle.png";
+
+/// KML colors are given as aabbggrr.
+fn kml_color(mode: Option<FlightMode>) -> String {
+    match mode.map(|m| m.color()) {
+        Some(c) => format!("ff{:02x}{:02x}{:02x}", c.b(), c.g(), c.r()),
+        None => "ffffffff".into(),
+    }
+}
+
+fn style_id(mode: Option<FlightMode>) -> String {
+    mode.map(|m| format!("{:?}", m)).unwrap_or("Unknown".into())
+}
+
+fn write_styles(writer: &mut impl Write) -> Result<(), std::io::Error> {
+    for mode in MODES.into_iter().map(Some).chain(std::iter::once(None)) {
+        writeln!(writer, "  <Style id=\"{}\">", style_id(mode))?;
+        writeln!(writer, "   <LineStyle><color>{}</color><width>3</width></LineStyle>", kml_color(mode))?;
+        writeln!(writer, "   <IconStyle><color>{}</color></IconStyle>", kml_color(mode))?;
+        writeln!(writer, "  </Style>")?;
+    }
+
+    writeln!(writer, "  <Style id=\"Event\">")?;
+    writeln!(writer, "   <IconStyle><Icon><href>{}</href></Icon></IconStyle>", EVENT_ICON)?;
+    writeln!(writer, "  </Style>")
+}
+
+fn write_segment(writer: &mut impl Write, track: &Track, segment: &TrackSegment) -> Result<(), std::io::Error> {
+    writeln!(writer, "   <Placemark>")?;
+    writeln!(writer, "    <name>{}</name>", segment.name())?;
+    writeln!(writer, "    <styleUrl>#{}</styleUrl>", style_id(segment.mode))?;
+    writeln!(writer, "    <gx:Track>")?;
+    writeln!(writer, "     <altitudeMode>absolute</altitudeMode>")?;
+    for point in &segment.points {
+        writeln!(writer, "     <when>{}</when>", track.timestamp(point.time))?;
+    }
+    for point in &segment.points {
+        writeln!(writer, "     <gx:coord>{} {} {}</gx:coord>", point.longitude, point.latitude, point.altitude)?;
+    }
+    writeln!(writer, "    </gx:Track>")?;
+    writeln!(writer, "   </Placemark>")
+}
+
+fn write_event(writer: &mut impl Write, track: &Track, name: &str, event: &FlightEvent) -> Result<(), std::io::Error> {
+    let (Some(latitude), Some(longitude)) = (event.latitude, event.longitude) else {
+        return Ok(());
+    };
+
+    // Without an altitude, we can only place the event on the ground.
+    let (altitude_mode, altitude) = match event.altitude_asl {
+        Some(altitude) => ("absolute", altitude),
+        None => ("clampToGround", 0.0),
+    };
+
+    writeln!(writer, "   <Placemark>")?;
+    writeln!(writer, "    <name>{}</name>", name)?;
+    writeln!(writer, "    <description>FC time: {:.3}s</description>", event.time)?;
+    writeln!(writer, "    <styleUrl>#Event</styleUrl>")?;
+    writeln!(writer, "    <TimeStamp><when>{}</when></TimeStamp>", track.timestamp(event.time as f64))?;
+    writeln!(writer, "    <Point>")?;
+    writeln!(writer, "     <altitudeMode>{}</altitudeMode>", altitude_mode)?;
+    writeln!(writer, "     <coordinates>{},{},{}</coordinates>", longitude, latitude, altitude)?;
+    writeln!(writer, "    </Point>")?;
+    writeln!(writer, "   </Placemark>")
+}
+
+/// Writes the given track as a KML document.
+pub fn write_kml(mut writer: impl Write, track: &Track) -> Result<(), std::io::Error> {
+    let name = xml_escape(&track.name);
+    let num_points = track.points().count();
+    let num_placemarks = track.events.iter().filter(|(_, e)| e.latitude.is_some() && e.longitude.is_some()).count();
+
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(writer, "<kml xmlns=\"http://www.opengis.net/kml/2.2\" xmlns:gx=\"http://www.google.com/kml/ext/2.2\">")?;
+    writeln!(writer, " <Document>")?;
+    writeln!(writer, "  <name>{}</name>", name)?;
+    writeln!(writer, "  <description>{} Trackpoints + {} Placemarks</description>", num_points, num_placemarks)?;
+    write_styles(&mut writer)?;
+
+    writeln!(writer, "  <Folder>")?;
+    writeln!(writer, "   <name>Track</name>")?;
+    for segment in &track.segments {
+        write_segment(&mut writer, track, segment)?;
+    }
+    writeln!(writer, "  </Folder>")?;
+
+    writeln!(writer, "  <Folder>")?;
+    writeln!(writer, "   <name>Events</name>")?;
+    for (name, event) in &track.events {
+        write_event(&mut writer, track, name, event)?;
+    }
+    writeln!(writer, "  </Folder>")?;
+
+    writeln!(writer, " </Document>")?;
+    writeln!(writer, "</kml>")
+}
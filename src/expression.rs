@@ -0,0 +1,692 @@
+//! A small expression language for derived telemetry channels, such as
+//! `norm(accelerometer1)`, `altitude_asl - altitude_ground_asl` or
+//! `derivative(altitude_baro)`.
+//!
+//! Expressions are evaluated on one vehicle state after another. Since each
+//! state only contains the values of a single message, channels hold their
+//! last known value, and a result is produced whenever one of the channels
+//! used in the expression is updated. The time is part of every state, so it
+//! doesn't count as an update.
+
+use nalgebra::Vector3;
+
+use mithril::telemetry::*;
+
+/// A value of a channel or intermediate result.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Scalar(f32),
+    Vector(Vector3<f32>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Scalar,
+    Vector,
+}
+
+impl Value {
+    fn kind(&self) -> Kind {
+        match self {
+            Value::Scalar(_) => Kind::Scalar,
+            Value::Vector(_) => Kind::Vector,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum ChannelValue {
+    Scalar(fn(&VehicleState) -> Option<f32>),
+    Vector(fn(&VehicleState) -> Option<Vector3<f32>>),
+}
+
+/// A field of the vehicle state, in its natural unit.
+pub struct Channel {
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub value: ChannelValue,
+}
+
+impl Channel {
+    pub fn is_vector(&self) -> bool {
+        matches!(self.value, ChannelValue::Vector(_))
+    }
+
+    fn get(&self, vs: &VehicleState) -> Option<Value> {
+        match self.value {
+            ChannelValue::Scalar(f) => f(vs).map(Value::Scalar),
+            ChannelValue::Vector(f) => f(vs).map(Value::Vector),
+        }
+    }
+}
+
+const fn scalar(name: &'static str, unit: &'static str, value: fn(&VehicleState) -> Option<f32>) -> Channel {
+    Channel {
+        name,
+        unit,
+        value: ChannelValue::Scalar(value),
+    }
+}
+
+const fn vector(name: &'static str, unit: &'static str, value: fn(&VehicleState) -> Option<Vector3<f32>>) -> Channel {
+    Channel {
+        name,
+        unit,
+        value: ChannelValue::Vector(value),
+    }
+}
+
+/// All channels usable in expressions, named after the vehicle state fields.
+pub const CHANNELS: &[Channel] = &[
+    scalar("time", "s", |vs| Some(vs.time as f32 / 1000.0)),
+    vector("euler_angles", "°", |vs| vs.euler_angles),
+    scalar("angle_of_attack", "°", |vs| vs.angle_of_attack),
+    scalar("altitude_asl", "m", |vs| vs.altitude_asl),
+    scalar("altitude_baro", "m", |vs| vs.altitude_baro),
+    scalar("altitude_ground_asl", "m", |vs| vs.altitude_ground_asl),
+    scalar("altitude_gps_asl", "m", |vs| vs.altitude_gps_asl),
+    scalar("apogee_asl", "m", |vs| vs.apogee_asl),
+    scalar("vertical_speed", "m/s", |vs| vs.vertical_speed),
+    scalar("vertical_accel", "m/s²", |vs| vs.vertical_accel),
+    scalar("vertical_accel_filtered", "m/s²", |vs| vs.vertical_accel_filtered),
+    scalar("latitude", "°", |vs| vs.latitude),
+    scalar("longitude", "°", |vs| vs.longitude),
+    scalar("num_satellites", "", |vs| vs.num_satellites.map(|x| x as f32)),
+    scalar("hdop", "", |vs| vs.hdop.map(|x| x as f32 / 100.0)),
+    vector("gyroscope", "°/s", |vs| vs.gyroscope),
+    vector("accelerometer1", "m/s²", |vs| vs.accelerometer1),
+    vector("accelerometer2", "m/s²", |vs| vs.accelerometer2),
+    vector("magnetometer", "µT", |vs| vs.magnetometer),
+    scalar("pressure_baro", "mbar", |vs| vs.pressure_baro),
+    scalar("temperature_baro", "°C", |vs| vs.temperature_baro),
+    scalar("battery_voltage", "V", |vs| vs.battery_voltage.map(|v| v as f32 / 1000.0)),
+    scalar("arm_voltage", "V", |vs| vs.arm_voltage.map(|v| v as f32 / 1000.0)),
+    scalar("current", "A", |vs| vs.current.map(|v| v as f32 / 1000.0)),
+    scalar("charge_voltage", "V", |vs| vs.charge_voltage.map(|v| v as f32 / 1000.0)),
+    scalar("cpu_utilization", "%", |vs| vs.cpu_utilization.map(|u| u as f32)),
+    scalar("gcs_lora_rssi", "dBm", |vs| vs.gcs_lora_rssi.map(|x| x as f32 / -2.0)),
+    scalar("gcs_lora_rssi_signal", "dBm", |vs| vs.gcs_lora_rssi_signal.map(|x| x as f32 / -2.0)),
+    scalar("gcs_lora_snr", "dB", |vs| vs.gcs_lora_snr.map(|x| x as f32 / 4.0)),
+    scalar("lora_rssi", "dBm", |vs| vs.lora_rssi.map(|x| x as f32 / -2.0)),
+    vector("true_euler_angles", "°", |vs| vs.true_euler_angles),
+    scalar("true_angle_of_attack", "°", |vs| vs.true_angle_of_attack),
+    scalar("true_vertical_accel", "m/s²", |vs| vs.true_vertical_accel),
+    scalar("true_vertical_speed", "m/s", |vs| vs.true_vertical_speed),
+];
+
+/// Names of the available functions, with a short description each.
+pub const FUNCTIONS: &[(&str, &str)] = &[
+    ("abs(x)", "Absolute value"),
+    ("sqrt(x)", "Square root"),
+    ("min(a, b)", "Smaller of two values"),
+    ("max(a, b)", "Larger of two values"),
+    ("norm(v)", "Length of a vector"),
+    ("derivative(x)", "Rate of change per second"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Number(f32),
+    Ident(usize, usize),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Dot,
+    Comma,
+    LParen,
+    RParen,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let bytes = input.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+        let token = match c {
+            ' ' | '\t' => {
+                i += 1;
+                continue;
+            }
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            ',' => Token::Comma,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '.' if !bytes.get(i + 1).map(|b| b.is_ascii_digit()).unwrap_or(false) => Token::Dot,
+            c if c.is_ascii_digit() || c == '.' => {
+                let digits =
+                    |start: usize| input[start..].find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len() - start);
+                let mut len = input[i..].find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(input.len() - i);
+
+                // Optional exponent, e.g. 1e-3
+                if matches!(bytes.get(i + len), Some(b'e' | b'E')) {
+                    let sign = matches!(bytes.get(i + len + 1), Some(b'+' | b'-')) as usize;
+                    let exponent = digits(i + len + 1 + sign);
+                    if exponent > 0 {
+                        len += 1 + sign + exponent;
+                    }
+                }
+
+                let number = &input[i..(i + len)];
+                i += len;
+                tokens.push(Token::Number(number.parse().map_err(|_| format!("Invalid number '{}'", number))?));
+                continue;
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let len =
+                    input[i..].find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(input.len() - i);
+                tokens.push(Token::Ident(i, i + len));
+                i += len;
+                continue;
+            }
+            c => return Err(format!("Unexpected character '{}'", c)),
+        };
+
+        tokens.push(token);
+        i += 1;
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Abs,
+    Sqrt,
+    Min,
+    Max,
+    Norm,
+}
+
+#[derive(Clone)]
+enum Expr {
+    Number(f32),
+    Channel {
+        channel: &'static Channel,
+        last: Option<Value>,
+    },
+    Component(Box<Expr>, usize),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+    Derivative {
+        arg: Box<Expr>,
+        last: Option<(u32, Value)>,
+    },
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> Result<(), String> {
+        match self.next() {
+            Some(t) if t == expected => Ok(()),
+            _ => Err(format!("Expected {}", what)),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => BinaryOp::Add,
+                Some(Token::Minus) => BinaryOp::Sub,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => BinaryOp::Mul,
+                Some(Token::Slash) => BinaryOp::Div,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some(Token::Minus) {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+
+        let mut expr = self.primary()?;
+        while self.peek() == Some(Token::Dot) {
+            self.pos += 1;
+            let component = match self.next() {
+                Some(Token::Ident(start, end)) => match &self.input[start..end] {
+                    "x" => 0,
+                    "y" => 1,
+                    "z" => 2,
+                    c => return Err(format!("Unknown component '{}', expected x, y or z", c)),
+                },
+                _ => return Err("Expected x, y or z after '.'".into()),
+            };
+            expr = Expr::Component(Box::new(expr), component);
+        }
+
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(x)) => Ok(Expr::Number(x)),
+            Some(Token::LParen) => {
+                let expr = self.expression()?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(start, end)) => {
+                let name = &self.input[start..end];
+                if self.peek() == Some(Token::LParen) {
+                    self.pos += 1;
+                    self.call(name)
+                } else {
+                    let channel = CHANNELS
+                        .iter()
+                        .find(|c| c.name == name)
+                        .ok_or_else(|| format!("Unknown channel '{}'", name))?;
+                    Ok(Expr::Channel { channel, last: None })
+                }
+            }
+            Some(_) => Err("Unexpected operator".into()),
+            None => Err("Unexpected end of expression".into()),
+        }
+    }
+
+    fn call(&mut self, name: &str) -> Result<Expr, String> {
+        let mut args = Vec::new();
+        if self.peek() != Some(Token::RParen) {
+            args.push(self.expression()?);
+            while self.peek() == Some(Token::Comma) {
+                self.pos += 1;
+                args.push(self.expression()?);
+            }
+        }
+        self.expect(Token::RParen, "')'")?;
+
+        let (function, num_args) = match name {
+            "abs" => (Some(Function::Abs), 1),
+            "sqrt" => (Some(Function::Sqrt), 1),
+            "min" => (Some(Function::Min), 2),
+            "max" => (Some(Function::Max), 2),
+            "norm" => (Some(Function::Norm), 1),
+            "derivative" => (None, 1),
+            _ => return Err(format!("Unknown function '{}'", name)),
+        };
+
+        if args.len() != num_args {
+            return Err(format!("{} expects {} argument(s)", name, num_args));
+        }
+
+        Ok(match function {
+            Some(function) => Expr::Call(function, args),
+            None => Expr::Derivative {
+                arg: Box::new(args.remove(0)),
+                last: None,
+            },
+        })
+    }
+}
+
+fn binary(op: BinaryOp, a: Value, b: Value) -> Option<Value> {
+    use Value::*;
+    Some(match (op, a, b) {
+        (BinaryOp::Add, Scalar(a), Scalar(b)) => Scalar(a + b),
+        (BinaryOp::Add, Vector(a), Vector(b)) => Vector(a + b),
+        (BinaryOp::Sub, Scalar(a), Scalar(b)) => Scalar(a - b),
+        (BinaryOp::Sub, Vector(a), Vector(b)) => Vector(a - b),
+        (BinaryOp::Mul, Scalar(a), Scalar(b)) => Scalar(a * b),
+        (BinaryOp::Mul, Vector(a), Scalar(b)) | (BinaryOp::Mul, Scalar(b), Vector(a)) => Vector(a * b),
+        (BinaryOp::Div, Scalar(a), Scalar(b)) => Scalar(a / b),
+        (BinaryOp::Div, Vector(a), Scalar(b)) => Vector(a / b),
+        _ => return None,
+    })
+}
+
+impl Expr {
+    /// Determines the kind of the result, rejecting invalid combinations.
+    fn kind(&self) -> Result<Kind, String> {
+        match self {
+            Expr::Number(_) => Ok(Kind::Scalar),
+            Expr::Channel { channel, .. } if channel.is_vector() => Ok(Kind::Vector),
+            Expr::Channel { .. } => Ok(Kind::Scalar),
+            Expr::Component(arg, _) => match arg.kind()? {
+                Kind::Vector => Ok(Kind::Scalar),
+                Kind::Scalar => Err("Components can only be taken of vectors".into()),
+            },
+            Expr::Neg(arg) => arg.kind(),
+            Expr::Binary(op, a, b) => {
+                let zero = |kind| match kind {
+                    Kind::Scalar => Value::Scalar(1.0),
+                    Kind::Vector => Value::Vector(Vector3::zeros()),
+                };
+                binary(*op, zero(a.kind()?), zero(b.kind()?))
+                    .map(|v| v.kind())
+                    .ok_or_else(|| "Invalid operation on a vector".to_string())
+            }
+            Expr::Call(Function::Norm, args) => match args[0].kind()? {
+                Kind::Vector => Ok(Kind::Scalar),
+                Kind::Scalar => Err("norm expects a vector".into()),
+            },
+            Expr::Call(_, args) => {
+                for arg in args {
+                    if arg.kind()? != Kind::Scalar {
+                        return Err("Function expects a scalar".into());
+                    }
+                }
+                Ok(Kind::Scalar)
+            }
+            Expr::Derivative { arg, .. } => arg.kind(),
+        }
+    }
+
+    /// Evaluates the expression, returning the result and whether any of the
+    /// channels it uses was updated by this state.
+    fn eval(&mut self, vs: &VehicleState) -> (Option<Value>, bool) {
+        match self {
+            Expr::Number(x) => (Some(Value::Scalar(*x)), false),
+            // GCS messages contain the runtime of the ground station, so the
+            // time of the last FC message is kept for those.
+            Expr::Channel { channel, last } if channel.name == "time" => {
+                if vs.gcs_lora_rssi.is_none() {
+                    *last = channel.get(vs);
+                }
+                (*last, false)
+            }
+            Expr::Channel { channel, last } => match channel.get(vs) {
+                Some(value) => {
+                    *last = Some(value);
+                    (Some(value), true)
+                }
+                None => (*last, false),
+            },
+            Expr::Component(arg, i) => {
+                let (value, fresh) = arg.eval(vs);
+                let value = match value {
+                    Some(Value::Vector(v)) => Some(Value::Scalar(v[*i])),
+                    _ => None,
+                };
+                (value, fresh)
+            }
+            Expr::Neg(arg) => {
+                let (value, fresh) = arg.eval(vs);
+                (value.and_then(|v| binary(BinaryOp::Mul, v, Value::Scalar(-1.0))), fresh)
+            }
+            Expr::Binary(op, a, b) => {
+                let ((a, a_fresh), (b, b_fresh)) = (a.eval(vs), b.eval(vs));
+                let value = a.zip(b).and_then(|(a, b)| binary(*op, a, b));
+                (value, a_fresh || b_fresh)
+            }
+            Expr::Call(function, args) => {
+                let results: Vec<_> = args.iter_mut().map(|a| a.eval(vs)).collect();
+                let fresh = results.iter().any(|(_, fresh)| *fresh);
+                let scalars: Option<Vec<f32>> = results
+                    .iter()
+                    .map(|(v, _)| match v {
+                        Some(Value::Scalar(x)) => Some(*x),
+                        _ => None,
+                    })
+                    .collect();
+                let value = match (function, &results[0].0, scalars) {
+                    (Function::Norm, Some(Value::Vector(v)), _) => Some(v.norm()),
+                    (Function::Abs, _, Some(x)) => Some(x[0].abs()),
+                    (Function::Sqrt, _, Some(x)) => Some(x[0].sqrt()),
+                    (Function::Min, _, Some(x)) => Some(f32::min(x[0], x[1])),
+                    (Function::Max, _, Some(x)) => Some(f32::max(x[0], x[1])),
+                    _ => None,
+                };
+                (value.map(Value::Scalar), fresh)
+            }
+            Expr::Derivative { arg, last } => {
+                let (value, fresh) = arg.eval(vs);
+                let Some(value) = value.filter(|_| fresh) else {
+                    return (None, false);
+                };
+
+                let derivative = last.filter(|(t, _)| *t < vs.time).and_then(|(t, previous)| {
+                    let dt = (vs.time - t) as f32 / 1000.0;
+                    binary(BinaryOp::Sub, value, previous).and_then(|d| binary(BinaryOp::Div, d, Value::Scalar(dt)))
+                });
+                *last = Some((vs.time, value));
+                (derivative, derivative.is_some())
+            }
+        }
+    }
+}
+
+/// A parsed expression, together with the state needed to evaluate it over
+/// a sequence of vehicle states.
+#[derive(Clone)]
+pub struct Expression {
+    expr: Expr,
+    /// The unevaluated expression, to start over with if the data restarts
+    initial: Expr,
+    last_time: u32,
+}
+
+impl Expression {
+    /// Parses an expression, which has to result in a single value.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            input,
+            tokens: tokenize(input)?,
+            pos: 0,
+        };
+
+        let expr = parser.expression()?;
+        if parser.pos < parser.tokens.len() {
+            return Err("Unexpected input after expression".into());
+        }
+
+        if expr.kind()? == Kind::Vector {
+            return Err("Expression results in a vector, use norm(…) or a component such as .x".into());
+        }
+
+        Ok(Self {
+            initial: expr.clone(),
+            expr,
+            last_time: 0,
+        })
+    }
+
+    /// Evaluates the expression for the next vehicle state. Returns None if
+    /// none of the channels used were updated.
+    pub fn eval(&mut self, vs: &VehicleState) -> Option<f32> {
+        // GCS messages contain the runtime of the ground station, so only FC
+        // times are used to detect that we are evaluating new data.
+        if vs.gcs_lora_rssi.is_none() {
+            if vs.time < self.last_time {
+                self.expr = self.initial.clone();
+            }
+            self.last_time = vs.time;
+        }
+
+        match self.expr.eval(vs) {
+            (Some(Value::Scalar(x)), true) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_all(input: &str, states: &[VehicleState]) -> Vec<Option<f32>> {
+        let mut expression = Expression::parse(input).unwrap();
+        states.iter().map(|vs| expression.eval(vs)).collect()
+    }
+
+    fn eval_one(input: &str, vs: &VehicleState) -> Option<f32> {
+        Expression::parse(input).unwrap().eval(vs)
+    }
+
+    fn altitude(time: u32, altitude: f32) -> VehicleState {
+        VehicleState {
+            time,
+            altitude_asl: Some(altitude),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn kind_checks() {
+        assert!(Expression::parse("norm(accelerometer1)").is_ok());
+        assert!(Expression::parse("norm(accelerometer1 - accelerometer2) / 2").is_ok());
+        assert!(Expression::parse("(2 * gyroscope).z").is_ok());
+        assert!(Expression::parse("abs(derivative(altitude_asl))").is_ok());
+
+        assert!(Expression::parse("accelerometer1").is_err());
+        assert!(Expression::parse("2 * accelerometer1").is_err());
+        assert!(Expression::parse("norm(altitude_asl)").is_err());
+        assert!(Expression::parse("altitude_asl.x").is_err());
+        assert!(Expression::parse("accelerometer1 + altitude_asl").is_err());
+        assert!(Expression::parse("altitude_asl / gyroscope").is_err());
+        assert!(Expression::parse("min(gyroscope, 1)").is_err());
+        assert!(Expression::parse("derivative(gyroscope)").is_err());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Expression::parse("unknown_channel").is_err());
+        assert!(Expression::parse("unknown(altitude_asl)").is_err());
+        assert!(Expression::parse("max(altitude_asl)").is_err());
+        assert!(Expression::parse("gyroscope.w").is_err());
+        assert!(Expression::parse("(altitude_asl").is_err());
+        assert!(Expression::parse("altitude_asl altitude_asl").is_err());
+        assert!(Expression::parse("altitude_asl +").is_err());
+        assert!(Expression::parse("altitude_asl % 2").is_err());
+    }
+
+    #[test]
+    fn numbers() {
+        let vs = altitude(0, 1.0);
+        assert_eq!(eval_one("altitude_asl * 2.5", &vs), Some(2.5));
+        assert_eq!(eval_one("altitude_asl * .5", &vs), Some(0.5));
+        assert_eq!(eval_one("altitude_asl * 1e3", &vs), Some(1000.0));
+        assert_eq!(eval_one("altitude_asl * 2.5E+2", &vs), Some(250.0));
+        assert_eq!(eval_one("altitude_asl * 1e-3", &vs), Some(0.001));
+        assert_eq!(eval_one("altitude_asl - 1e-3", &vs), Some(0.999));
+        assert!(Expression::parse("altitude_asl * 1e").is_err());
+        assert!(Expression::parse("altitude_asl * 1.2.3").is_err());
+    }
+
+    #[test]
+    fn components() {
+        let vs = VehicleState {
+            accelerometer1: Some(Vector3::new(1.0, 2.0, 3.0)),
+            ..Default::default()
+        };
+
+        assert_eq!(eval_one("accelerometer1.x", &vs), Some(1.0));
+        assert_eq!(eval_one("accelerometer1.z - accelerometer1.y", &vs), Some(1.0));
+        assert_eq!(eval_one("(accelerometer1 * 2).y", &vs), Some(4.0));
+        assert_eq!(eval_one("-accelerometer1.z", &vs), Some(-3.0));
+    }
+
+    #[test]
+    fn channels_hold_last_value() {
+        let states = [
+            altitude(0, 100.0),
+            VehicleState {
+                time: 10,
+                altitude_ground_asl: Some(40.0),
+                ..Default::default()
+            },
+            VehicleState {
+                time: 20,
+                ..Default::default()
+            },
+            altitude(30, 110.0),
+        ];
+
+        let results = eval_all("altitude_asl - altitude_ground_asl", &states);
+        assert_eq!(results, vec![None, Some(60.0), None, Some(70.0)]);
+    }
+
+    #[test]
+    fn time_is_not_an_update() {
+        let states = [
+            VehicleState {
+                time: 1000,
+                ..Default::default()
+            },
+            altitude(2000, 10.0),
+            VehicleState {
+                time: 3000,
+                ..Default::default()
+            },
+            VehicleState {
+                time: 500_000,
+                gcs_lora_rssi: Some(100),
+                altitude_asl: Some(20.0),
+                ..Default::default()
+            },
+        ];
+
+        assert_eq!(eval_all("time", &states), vec![None; 4]);
+
+        // GCS states contain the ground station's runtime, not the FC time.
+        let results = eval_all("altitude_asl + time", &states);
+        assert_eq!(results, vec![None, Some(12.0), None, Some(23.0)]);
+    }
+
+    #[test]
+    fn derivative() {
+        let states = [
+            altitude(1000, 0.0),
+            altitude(1500, 5.0),
+            altitude(2000, 15.0),
+            altitude(2000, 20.0),
+        ];
+        let results = eval_all("derivative(altitude_asl)", &states);
+        assert_eq!(results, vec![None, Some(10.0), Some(20.0), None]);
+    }
+
+    #[test]
+    fn derivative_restarts_when_time_goes_backwards() {
+        let states = [
+            altitude(1000, 0.0),
+            altitude(2000, 10.0),
+            altitude(500, 100.0),
+            altitude(1500, 90.0),
+        ];
+        let results = eval_all("derivative(altitude_asl)", &states);
+        assert_eq!(results, vec![None, Some(10.0), None, Some(-10.0)]);
+    }
+}
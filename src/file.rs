@@ -5,6 +5,15 @@ use std::fs::File;
 use mithril::settings::Settings;
 
 use crate::data_source::*;
+use crate::export::csv::*;
+use crate::export::gpx::write_gpx;
+use crate::export::kml::write_kml;
+use crate::export::Track;
+use crate::settings::PlotDefinition;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::simulation::monte_carlo::MonteCarloResults;
+use crate::simulation::motor::Motor;
+use crate::simulation::SimulationSettings;
 
 // TODO: file support for wasm
 
@@ -30,3 +39,87 @@ pub fn save_fc_settings_file(settings: &Settings) {
         serde_json::to_writer_pretty(f, settings).unwrap();
     }
 }
+
+#[cfg(target_arch = "x86_64")]
+pub fn save_csv_file(data_source: &dyn DataSource, options: &CsvOptions) {
+    let path = rfd::FileDialog::new().add_filter("CSV", &["csv"]).set_file_name("export.csv").save_file();
+    if let Some(f) = path.map(|p| File::create(p).ok()).flatten() {
+        match write_csv(std::io::BufWriter::new(f), data_source.vehicle_states(), options) {
+            Ok(rows) => log::info!("Exported {} rows to CSV.", rows),
+            Err(e) => log::error!("Failed to export CSV: {:?}", e),
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn save_kml_file(track: &Track) {
+    let path = rfd::FileDialog::new().add_filter("KML", &["kml"]).set_file_name("track.kml").save_file();
+    if let Some(f) = path.map(|p| File::create(p).ok()).flatten() {
+        if let Err(e) = write_kml(std::io::BufWriter::new(f), track) {
+            log::error!("Failed to export KML: {:?}", e);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn save_gpx_file(track: &Track) {
+    let path = rfd::FileDialog::new().add_filter("GPX", &["gpx"]).set_file_name("track.gpx").save_file();
+    if let Some(f) = path.map(|p| File::create(p).ok()).flatten() {
+        if let Err(e) = write_gpx(std::io::BufWriter::new(f), track) {
+            log::error!("Failed to export GPX: {:?}", e);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn save_monte_carlo_file(results: &MonteCarloResults) {
+    let path = rfd::FileDialog::new().add_filter("CSV", &["csv"]).set_file_name("monte_carlo.csv").save_file();
+    if let Some(p) = path {
+        if let Err(e) = std::fs::write(p, results.to_csv()) {
+            log::error!("Failed to export Monte Carlo results: {:?}", e);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn open_motor_file() -> Option<Motor> {
+    let path = rfd::FileDialog::new().add_filter("Thrust Curves", &["eng", "rse"]).pick_file()?;
+    let contents = std::fs::read_to_string(path).map_err(|e| log::error!("{:?}", e)).ok()?;
+    Motor::parse(&contents).map_err(|e| log::error!("Failed to load motor: {}", e)).ok()
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn open_scenario_file() -> Option<SimulationSettings> {
+    let path = rfd::FileDialog::new().add_filter("Scenario", &["json"]).pick_file()?;
+    let contents = std::fs::read_to_string(path).map_err(|e| log::error!("{:?}", e)).ok()?;
+    SimulationSettings::from_scenario(&contents)
+        .map_err(|e| log::error!("Failed to load scenario: {}", e))
+        .ok()
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn save_scenario_file(settings: &SimulationSettings) {
+    let path = rfd::FileDialog::new().add_filter("Scenario", &["json"]).set_file_name("scenario.json").save_file();
+    if let Some(f) = path.map(|p| File::create(p).ok()).flatten() {
+        if let Err(e) = serde_json::to_writer_pretty(std::io::BufWriter::new(f), settings) {
+            log::error!("Failed to save scenario: {:?}", e);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn open_plot_file() -> Option<PlotDefinition> {
+    let path = rfd::FileDialog::new().add_filter("Plot", &["json"]).pick_file()?;
+    let contents = std::fs::read_to_string(path).map_err(|e| log::error!("{:?}", e)).ok()?;
+    serde_json::from_str(&contents).map_err(|e| log::error!("Failed to load plot: {}", e)).ok()
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn save_plot_file(definition: &PlotDefinition) {
+    let path = rfd::FileDialog::new().add_filter("Plot", &["json"]).set_file_name("plot.json").save_file();
+    if let Some(f) = path.map(|p| File::create(p).ok()).flatten() {
+        if let Err(e) = serde_json::to_writer_pretty(std::io::BufWriter::new(f), definition) {
+            log::error!("Failed to save plot: {:?}", e);
+        }
+    }
+}
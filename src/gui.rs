@@ -11,6 +11,7 @@ use mithril::telemetry::*;
 
 mod panels;
 mod fc_settings;
+mod log_scroller;
 mod map;
 mod maxi_grid;
 mod misc;
@@ -27,22 +28,31 @@ use crate::gui::tabs::*;
 use crate::gui::theme::*;
 use crate::gui::windows::*;
 use crate::settings::AppSettings;
+use crate::simulation::SimulationSettings;
 
 // The main state object of our GUI application
 pub struct Sam {
     settings: AppSettings,
     data_source: Box<dyn DataSource>,
     tab: GuiTab,
+    launch_tab: LaunchTab,
     plot_tab: PlotTab,
     configure_tab: ConfigureTab,
     archive_window: ArchiveWindow,
+    report_window: ReportWindow,
+    export_window: ExportWindow,
+    plot_editor_window: PlotEditorWindow,
+    layout_editor_window: LayoutEditorWindow,
+    #[cfg(not(target_arch = "wasm32"))]
+    monte_carlo_window: MonteCarloWindow,
+    log_panel: LogPanel,
 }
 
 impl Sam {
     /// Initialize the application, including the state objects for widgets
     /// such as plots and maps.
     pub fn init(ctx: &egui::Context, settings: AppSettings, data_source: Option<Box<dyn DataSource>>) -> Self {
-        let data_source = data_source.unwrap_or(Box::new(SerialDataSource::new(ctx, settings.lora.clone())));
+        let data_source = data_source.unwrap_or(Box::new(SerialDataSource::new(ctx, &settings)));
 
         let mut fonts = egui::FontDefinitions::default();
         let roboto = egui::FontData::from_static(include_bytes!("../assets/fonts/RobotoMono-Regular.ttf"));
@@ -54,6 +64,7 @@ impl Sam {
 
         ctx.set_fonts(fonts);
 
+        let launch_tab = LaunchTab::init();
         let plot_tab = PlotTab::init(&settings);
         let configure_tab = ConfigureTab::init();
 
@@ -64,16 +75,24 @@ impl Sam {
             data_source,
 
             tab: GuiTab::Plot,
+            launch_tab,
             plot_tab,
             configure_tab,
 
             archive_window: ArchiveWindow::default(),
+            report_window: ReportWindow::default(),
+            export_window: ExportWindow::default(),
+            plot_editor_window: PlotEditorWindow::default(),
+            layout_editor_window: LayoutEditorWindow::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            monte_carlo_window: MonteCarloWindow::default(),
+            log_panel: LogPanel::default(),
         }
     }
 
     /// Closes the currently opened data source
     fn close_data_source(&mut self, ctx: &egui::Context) {
-        self.data_source = Box::new(SerialDataSource::new(ctx, self.settings.lora.clone()));
+        self.data_source = Box::new(SerialDataSource::new(ctx, &self.settings));
     }
 
     pub fn ui(&mut self, ctx: &egui::Context) {
@@ -98,6 +117,9 @@ impl Sam {
             self.tab = GuiTab::Plot;
         } else if ctx.input_mut(|i| i.consume_key(Modifiers::NONE, Key::F3)) {
             self.tab = GuiTab::Configure;
+        } else if ctx.input_mut(|i| i.consume_key(Modifiers::NONE, Key::F4)) {
+            self.tab = GuiTab::Plot;
+            self.plot_tab.next_layout();
         }
 
         let shortcut_mode = if ctx.input_mut(|i| i.consume_key(Modifiers::SHIFT, Key::F5)) {
@@ -141,6 +163,22 @@ impl Sam {
             self.data_source = Box::new(log);
         }
 
+        // A window summarizing the flight
+        self.report_window.show_if_open(ctx, self.data_source.as_ref());
+
+        // A window for exporting the current data
+        self.export_window.show_if_open(ctx, self.data_source.as_ref());
+
+        // A window for defining custom plots
+        if self.plot_editor_window.show_if_open(ctx, &mut self.settings) {
+            self.plot_tab.apply_custom_plots(&self.settings);
+        }
+
+        // A window for arranging the plot tab's dashboard layouts
+        if self.layout_editor_window.show_if_open(ctx, &mut self.settings) {
+            self.plot_tab.apply_layouts(&self.settings);
+        }
+
         // Top menu bar
         // TODO: avoid passing in self here
         MenuBarPanel::show(ctx, self, !self.archive_window.open);
@@ -148,10 +186,16 @@ impl Sam {
         // If our current data source is a simulation, show a config panel to the left
         if let Some(sim) = self.data_source.as_any_mut().downcast_mut::<SimulationDataSource>() {
             SimulationPanel::show(ctx, sim, !self.archive_window.open);
+
+            // A window for Monte Carlo analyses of the current simulation settings
+            #[cfg(not(target_arch = "wasm32"))]
+            self.monte_carlo_window.show_if_open(ctx, sim);
         }
 
         // Header containing text indicators and flight mode buttons
-        HeaderPanel::show(ctx, self.data_source.as_mut(), !self.archive_window.open);
+        // While the plot tab's measurement cursors are shown, the header shows the values at the active one.
+        let cursor = (self.tab == GuiTab::Plot).then(|| self.plot_tab.cursor_time(self.data_source.as_ref())).flatten();
+        HeaderPanel::show(ctx, self.data_source.as_mut(), !self.archive_window.open, cursor);
 
         // Bottom status bar
         egui::TopBottomPanel::bottom("bottombar").min_height(30.0).show(ctx, |ui| {
@@ -169,7 +213,7 @@ impl Sam {
                 // ... and the current tab some space on the right.
                 ui.allocate_ui_with_layout(ui.available_size(), Layout::right_to_left(Align::Center), |ui| {
                     match self.tab {
-                        GuiTab::Launch => {}
+                        GuiTab::Launch => self.launch_tab.bottom_bar_ui(ui, self.data_source.as_mut(), &self.settings),
                         GuiTab::Plot => self.plot_tab.bottom_bar_ui(ui, self.data_source.as_mut()),
                         GuiTab::Configure => {}
                     }
@@ -177,11 +221,18 @@ impl Sam {
             });
         });
 
+        // Log messages, either docked to the bottom/right or in a separate window. Clicking a message
+        // shows the plots at the time of the message. The plot tab applies the jump in this frame.
+        if let Some(time) = self.log_panel.show_if_open(ctx, self.data_source.as_ref(), !self.archive_window.open) {
+            self.tab = GuiTab::Plot;
+            self.plot_tab.jump_to_fc_time(time, self.data_source.as_ref());
+        }
+
         // Everything else. This has to be called after all the other panels are created.
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.set_enabled(!self.archive_window.open);
             match self.tab {
-                GuiTab::Launch => {}
+                GuiTab::Launch => self.launch_tab.main_ui(ui, self.data_source.as_mut(), &mut self.settings),
                 GuiTab::Plot => self.plot_tab.main_ui(ui, self.data_source.as_mut()),
                 GuiTab::Configure => {
                     let changed = self.configure_tab.main_ui(ui, self.data_source.as_mut(), &mut self.settings);
@@ -205,11 +256,14 @@ impl eframe::App for Sam {
 
 /// The main entrypoint for the egui interface.
 #[cfg(not(target_arch = "wasm32"))]
-pub fn main(log_file: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+pub fn main(log_file: Option<PathBuf>, scenario: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
     let app_settings = AppSettings::load().ok().unwrap_or(AppSettings::default());
 
     let data_source: Option<Box<dyn DataSource>> = if let Some(path) = log_file {
         Some(Box::new(LogFileDataSource::new(path)?))
+    } else if let Some(path) = scenario {
+        let settings = SimulationSettings::from_scenario(&std::fs::read_to_string(path)?)?;
+        Some(Box::new(SimulationDataSource::new(settings)))
     } else {
         None
     };
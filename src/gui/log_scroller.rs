@@ -1,16 +1,25 @@
 use eframe::egui;
-use egui::{Color32, Layout, RichText, Vec2};
+use egui::{Color32, CursorIcon, Layout, RichText, Sense, Vec2};
 
 use mithril::telemetry::*;
 
 use crate::telemetry_ext::*;
 
 pub trait LogUiExt {
-    fn log_scroller<'a>(&mut self, log_messages: impl Iterator<Item = &'a (u32, String, LogLevel, String)>);
+    /// Shows the given log messages, returning the time of a message if it was clicked.
+    fn log_scroller<'a>(
+        &mut self,
+        log_messages: impl Iterator<Item = &'a (u32, String, LogLevel, String)>,
+    ) -> Option<u32>;
 }
 
 impl LogUiExt for egui::Ui {
-    fn log_scroller<'a>(&mut self, log_messages: impl Iterator<Item = &'a (u32, String, LogLevel, String)>) {
+    fn log_scroller<'a>(
+        &mut self,
+        log_messages: impl Iterator<Item = &'a (u32, String, LogLevel, String)>,
+    ) -> Option<u32> {
+        let mut clicked = None;
+
         let h = self.available_height();
         egui::ScrollArea::vertical()
             .max_height(self.available_height())
@@ -22,7 +31,7 @@ impl LogUiExt for egui::Ui {
                 let gray = Color32::from_rgb(0x66, 0x5c, 0x54);
 
                 for (t, loc, ll, msg) in log_messages {
-                    ui.horizontal(|ui| {
+                    let response = ui.horizontal(|ui| {
                         ui.allocate_ui_with_layout(
                             Vec2::new(60.0, 10.0),
                             Layout::top_down(eframe::emath::Align::RIGHT),
@@ -57,8 +66,15 @@ impl LogUiExt for egui::Ui {
                                 ui.monospace(msg);
                             },
                         );
-                    });
+                    })
+                    .response;
+
+                    if response.interact(Sense::click()).on_hover_cursor(CursorIcon::PointingHand).clicked() {
+                        clicked = Some(*t);
+                    }
                 }
             });
+
+        clicked
     }
 }
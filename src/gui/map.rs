@@ -16,10 +16,16 @@ use slippy_map_tiles::{BBox, Tile};
 
 use eframe::egui;
 use egui::mutex::Mutex;
-use egui_plot::{Line, PlotBounds, PlotImage, PlotPoint, PlotPoints};
+use egui_plot::{Line, PlotBounds, PlotImage, PlotPoint, PlotPoints, PlotUi, Points};
 use egui::{Color32, ColorImage, Context, TextureHandle, Vec2};
 
 use crate::data_source::DataSource;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::data_source::SimulationDataSource;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::gui::windows::LANDING_ELLIPSE_CONFIDENCE;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::simulation::monte_carlo::MonteCarlo;
 
 const GRADIENT_MAX_ALT: f64 = 10000.0;
 
@@ -363,6 +369,18 @@ impl MapState {
     }
 }
 
+/// Draws the landing points and landing ellipse of a Monte Carlo analysis.
+#[cfg(not(target_arch = "wasm32"))]
+fn monte_carlo_overlay(plot_ui: &mut PlotUi, monte_carlo: &MonteCarlo) {
+    let points: PlotPoints =
+        monte_carlo.results.runs.iter().map(|r| [r.landing_longitude, r.landing_latitude]).collect();
+    plot_ui.points(Points::new(points).radius(2.0).color(Color32::WHITE));
+
+    if let Some(ellipse) = monte_carlo.results.landing_ellipse(&monte_carlo.settings, LANDING_ELLIPSE_CONFIDENCE) {
+        plot_ui.line(Line::new(ellipse.points()).width(1.5).color(Color32::from_rgb(0xfe, 0x80, 0x19)));
+    }
+}
+
 pub trait MapUiExt {
     fn map(&mut self, state: &MapState, data_source: &dyn DataSource);
 }
@@ -408,6 +426,13 @@ impl MapUiExt for egui::Ui {
 
                     plot_ui.line(line);
                 }
+
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(sim) = data_source.as_any().downcast_ref::<SimulationDataSource>() {
+                    if let Some(monte_carlo) = sim.monte_carlo.as_ref() {
+                        monte_carlo_overlay(plot_ui, monte_carlo);
+                    }
+                }
             });
         });
     }
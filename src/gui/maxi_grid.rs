@@ -32,7 +32,6 @@ pub struct MaxiGrid<'a> {
     available_rect: Rect,
     ui: &'a mut Ui,
     state: MaxiGridState,
-    current_cell: (usize, usize),
 }
 
 impl<'a> MaxiGrid<'a> {
@@ -44,16 +43,21 @@ impl<'a> MaxiGrid<'a> {
             available_rect,
             ui,
             state,
-            current_cell: (0, 0),
         }
     }
 
-    fn draw_minimized(&mut self, title: &'static str, cb: impl FnOnce(&mut Ui)) {
+    fn draw_minimized(
+        &mut self,
+        title: &str,
+        position: (usize, usize),
+        span: (usize, usize),
+        cb: impl FnOnce(&mut Ui),
+    ) {
         let top_left = self.available_rect.left_top();
         let cell_size = self.available_rect.size() / Vec2::new(self.cells.0 as f32, self.cells.1 as f32);
 
-        let cell_top_left = top_left + (cell_size * Vec2::new(self.current_cell.0 as f32, self.current_cell.1 as f32));
-        let cell_bottom_right = cell_top_left + cell_size;
+        let cell_top_left = top_left + (cell_size * Vec2::new(position.0 as f32, position.1 as f32));
+        let cell_bottom_right = cell_top_left + cell_size * Vec2::new(span.0 as f32, span.1 as f32);
         let rect =
             Rect::from_min_max(cell_top_left, cell_bottom_right).shrink2(self.ui.style().spacing.item_spacing / 2.0);
 
@@ -64,7 +68,7 @@ impl<'a> MaxiGrid<'a> {
                     ui.heading(title);
                     ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
                         if ui.button("🗖").clicked() {
-                            self.state.maximize(self.current_cell);
+                            self.state.maximize(position);
                         }
                     });
                 });
@@ -75,7 +79,7 @@ impl<'a> MaxiGrid<'a> {
         });
     }
 
-    fn draw_maximized(&mut self, title: &'static str, cb: impl FnOnce(&mut Ui)) {
+    fn draw_maximized(&mut self, title: &str, cb: impl FnOnce(&mut Ui)) {
         self.ui.vertical(|ui| {
             ui.horizontal(|ui| {
                 ui.heading(title);
@@ -90,18 +94,20 @@ impl<'a> MaxiGrid<'a> {
         });
     }
 
-    pub fn cell(mut self, title: &'static str, cb: impl FnOnce(&mut Ui)) -> Self {
+    /// Adds a cell at the given column and row, spanning the given number of
+    /// columns and rows.
+    pub fn spanning_cell(
+        mut self,
+        title: &str,
+        position: (usize, usize),
+        span: (usize, usize),
+        cb: impl FnOnce(&mut Ui),
+    ) -> Self {
         let maximized = self.state.maximized();
-        if maximized.map(|c| c == self.current_cell).unwrap_or(false) {
+        if maximized.map(|c| c == position).unwrap_or(false) {
             self.draw_maximized(title, cb);
         } else if maximized.is_none() {
-            self.draw_minimized(title, cb);
-        }
-
-        self.current_cell.0 += 1;
-        if self.current_cell.0 >= self.cells.0 {
-            self.current_cell.0 = 0;
-            self.current_cell.1 += 1;
+            self.draw_minimized(title, position, span, cb);
         }
 
         self
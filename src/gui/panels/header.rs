@@ -1,6 +1,12 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
 use egui::{CollapsingHeader, Vec2, Layout, Align};
 use mithril::telemetry::*;
 
+use crate::gui::plot::states_until;
 use crate::gui::top_bar::*;
 use crate::data_source::DataSource;
 
@@ -8,29 +14,37 @@ pub struct HeaderPanel {}
 
 impl HeaderPanel {
     /// Returns the "current" value for the given callback. This is the last
-    /// known of the value at the current time.
-    /// TODO: incorporate cursor position?
-    fn current<T>(data_source: &mut dyn DataSource, callback: impl Fn(&VehicleState) -> Option<T>) -> Option<T> {
-        data_source.vehicle_states().rev().find_map(|(_t, msg)| callback(msg))
+    /// known of the value at the given time, e.g. that of a plot cursor, or
+    /// at the current time.
+    fn current<T>(
+        data_source: &mut dyn DataSource,
+        time: Option<Instant>,
+        callback: impl Fn(&VehicleState) -> Option<T>,
+    ) -> Option<T> {
+        states_until(data_source, time).iter().rev().find_map(|(_t, msg)| callback(msg))
     }
 
-    fn text_telemetry(ui: &mut egui::Ui, data_source: &mut dyn DataSource) {
+    fn text_telemetry(ui: &mut egui::Ui, data_source: &mut dyn DataSource, cursor: Option<Instant>) {
         let spacing = 3.0; // TODO: this is ugly
 
-        let time = data_source
-            .vehicle_states()
+        let time = states_until(data_source, cursor)
             .last()
             .map(|(_t, msg)| format!("{:10.3}", (msg.time as f32) / 1000.0));
-        let rssi = Self::current(data_source, |vs| vs.gcs_lora_rssi.map(|x| x as f32 / -2.0));
-        let mode = Self::current(data_source, |vs| vs.mode).map(|s| format!("{:?}", s));
-
-        let alt_ground = Self::current(data_source, |vs| vs.altitude_ground_asl).unwrap_or(0.0);
-        let alt_agl = Self::current(data_source, |vs| vs.altitude_asl.map(|a| a - alt_ground));
-        let apogee_agl = Self::current(data_source, |vs| vs.apogee_asl.map(|a| a - alt_ground));
-        let vertical_accel = Self::current(data_source, |vs| vs.vertical_accel_filtered);
-
-        let last_gps = data_source
-            .vehicle_states()
+        let rssi = Self::current(data_source, cursor, |vs| vs.gcs_lora_rssi.map(|x| x as f32 / -2.0));
+        let mode = Self::current(data_source, cursor, |vs| vs.mode).map(|s| format!("{:?}", s));
+
+        let alt_ground = Self::current(data_source, cursor, |vs| vs.altitude_ground_asl).unwrap_or(0.0);
+        let alt_agl = Self::current(data_source, cursor, |vs| vs.altitude_asl.map(|a| a - alt_ground));
+        let apogee_agl = Self::current(data_source, cursor, |vs| vs.apogee_asl.map(|a| a - alt_ground));
+        let vertical_accel = Self::current(data_source, cursor, |vs| vs.vertical_accel_filtered);
+        let vertical_speed = Self::current(data_source, cursor, |vs| vs.vertical_speed);
+        let altitude_baro = Self::current(data_source, cursor, |vs| vs.altitude_baro);
+        let altitude_gps = Self::current(data_source, cursor, |vs| vs.altitude_gps_asl);
+        let temperature_baro = Self::current(data_source, cursor, |vs| vs.temperature_baro);
+        let num_satellites = Self::current(data_source, cursor, |vs| vs.num_satellites.map(|n| n as f32));
+
+        let last_gps = states_until(data_source, cursor)
+            .iter()
             .rev()
             .find_map(|(_t, vs)| vs.gps_fix.is_some().then(|| vs))
             .cloned();
@@ -45,7 +59,7 @@ impl HeaderPanel {
             ui.add_space(spacing);
             ui.telemetry_value("🕐", "Time [s]", time);
             ui.telemetry_value("🏷", "Mode", mode);
-            ui.nominal_value("🔥", "Baro Temp. [°C]", Self::current(data_source, |vs| vs.temperature_baro), 1, 0.0, 60.0);
+            ui.nominal_value("🔥", "Baro Temp. [°C]", temperature_baro, 1, 0.0, 60.0);
             ui.nominal_value("📡", "RSSI [dBm]", rssi, 1, -50.0, 0.0);
             ui.nominal_value("📶", "Link Quality [%]", data_source.link_quality(), 1, 90.0, 101.0);
             ui.add_space(ui.spacing().item_spacing.y);
@@ -56,8 +70,8 @@ impl HeaderPanel {
             ui.add_space(spacing);
             ui.nominal_value("📈", "Altitude (AGL) [m]", alt_agl, 1, -1.0, 10000.0);
             ui.nominal_value("📈", "Apogee (AGL) [m]", apogee_agl, 1, -1.0, 10000.0);
-            ui.nominal_value("☁", "Baro. Alt. (ASL) [m]", Self::current(data_source, |vs| vs.altitude_baro), 1, -100.0, 10000.0);
-            ui.nominal_value("⏱", "Vertical Speed [m/s]", Self::current(data_source, |vs| vs.vertical_speed), 2, -1.0, 1.0);
+            ui.nominal_value("☁", "Baro. Alt. (ASL) [m]", altitude_baro, 1, -100.0, 10000.0);
+            ui.nominal_value("⏱", "Vertical Speed [m/s]", vertical_speed, 2, -1.0, 1.0);
             ui.nominal_value("⬆", "Vertical Accel. [m/s²]", vertical_accel, 1, -1.0, 1.0);
         });
 
@@ -65,29 +79,29 @@ impl HeaderPanel {
             ui.set_width(ui.available_width());
             ui.add_space(spacing);
             ui.telemetry_value("🌍", "GPS Status", gps_status);
-            ui.nominal_value("📶", "# Sats", Self::current(data_source, |vs| vs.num_satellites.map(|n| n as f32)), 0, 5.0, 99.0);
+            ui.nominal_value("📶", "# Sats", num_satellites, 0, 5.0, 99.0);
             ui.nominal_value("🎯", "HDOP", hdop, 2, 0.0, 5.0);
-            ui.nominal_value("📡", "GPS Alt. (ASL) [m]", Self::current(data_source, |vs| vs.altitude_gps_asl), 1, -100.0, 10000.0);
+            ui.nominal_value("📡", "GPS Alt. (ASL) [m]", altitude_gps, 1, -100.0, 10000.0);
             ui.telemetry_value("🌐", "Coords", coords);
         });
     }
 
-    fn header_ui(ui: &mut egui::Ui, data_source: &mut dyn DataSource, vertical: bool) {
+    fn header_ui(ui: &mut egui::Ui, data_source: &mut dyn DataSource, vertical: bool, cursor: Option<Instant>) {
         if vertical {
             ui.horizontal(|ui| {
-                Self::text_telemetry(ui, data_source);
+                Self::text_telemetry(ui, data_source, cursor);
             });
         } else {
             ui.horizontal_centered(|ui| {
                 ui.set_width(ui.available_width() * 0.50);
-                Self::text_telemetry(ui, data_source);
+                Self::text_telemetry(ui, data_source, cursor);
             });
         }
 
         ui.separator();
 
-        let current_data_rate = Self::current(data_source, |vs| vs.data_rate).unwrap_or_default();
-        let current_transmit_power = Self::current(data_source, |vs| vs.transmit_power).unwrap_or_default();
+        let current_data_rate = Self::current(data_source, None, |vs| vs.data_rate).unwrap_or_default();
+        let current_transmit_power = Self::current(data_source, None, |vs| vs.transmit_power).unwrap_or_default();
 
         if vertical {
             ui.columns(4, |uis| {
@@ -116,19 +130,22 @@ impl HeaderPanel {
             ui.allocate_ui_with_layout(size, Layout::right_to_left(Align::Center), |ui| {
                 ui.command_button("⟲  Reboot", Command::Reboot, data_source);
                 ui.command_button("🗑 Erase Flash", Command::EraseFlash, data_source);
-                ui.flash_bar(ui.available_width() * 0.6, Self::current(data_source, |vs| vs.flash_pointer));
-                ui.battery_bar(ui.available_width(), Self::current(data_source, |vs| vs.battery_voltage.map(|v| v as f32 / 1000.0)));
+                ui.flash_bar(ui.available_width() * 0.6, Self::current(data_source, cursor, |vs| vs.flash_pointer));
+                let battery_voltage = Self::current(data_source, cursor, |vs| vs.battery_voltage.map(|v| v as f32 / 1000.0));
+                ui.battery_bar(ui.available_width(), battery_voltage);
             });
 
             ui.separator();
 
             ui.allocate_ui(ui.available_size(), |ui| {
-                ui.flight_mode_buttons(Self::current(data_source, |vs| vs.mode), data_source);
+                ui.flight_mode_buttons(Self::current(data_source, None, |vs| vs.mode), data_source);
             });
         });
     }
 
-    pub fn show(ctx: &egui::Context, data_source: &mut dyn DataSource, enabled: bool) {
+    /// Shows the header. If a cursor time is given, the indicators show the
+    /// values at that time instead of the latest ones.
+    pub fn show(ctx: &egui::Context, data_source: &mut dyn DataSource, enabled: bool, cursor: Option<Instant>) {
         #[cfg(feature = "profiling")]
         puffin::profile_function!();
 
@@ -136,14 +153,14 @@ impl HeaderPanel {
             egui::TopBottomPanel::top("topbar").min_height(60.0).max_height(60.0).show(ctx, |ui| {
                 ui.set_enabled(enabled);
                 ui.horizontal_centered(|ui| {
-                    Self::header_ui(ui, data_source, false);
+                    Self::header_ui(ui, data_source, false, cursor);
                 });
             });
         } else {
             egui::TopBottomPanel::top("topbar").min_height(20.0).max_height(300.0).show(ctx, |ui| {
                 ui.set_enabled(enabled);
                 CollapsingHeader::new("Status & Controls").default_open(false).show(ui, |ui| {
-                    Self::header_ui(ui, data_source, true);
+                    Self::header_ui(ui, data_source, true, cursor);
                     ui.add_space(10.0);
                 });
             });
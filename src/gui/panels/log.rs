@@ -0,0 +1,148 @@
+use std::collections::BTreeSet;
+
+use eframe::egui;
+use egui::{Align, Layout, TextEdit};
+
+use mithril::telemetry::*;
+
+use crate::data_source::DataSource;
+use crate::gui::log_scroller::*;
+
+/// Where the log panel is shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogPanelDock {
+    Bottom,
+    Right,
+    Window,
+}
+
+fn severity(ll: &LogLevel) -> u8 {
+    match ll {
+        LogLevel::Debug => 0,
+        LogLevel::Info => 1,
+        LogLevel::Warning => 2,
+        LogLevel::Error => 3,
+        LogLevel::Critical => 4,
+    }
+}
+
+pub struct LogPanel {
+    pub open: bool,
+    dock: LogPanelDock,
+    min_severity: u8,
+    location: Option<String>,
+    search: String,
+}
+
+impl Default for LogPanel {
+    fn default() -> Self {
+        Self {
+            open: false,
+            dock: LogPanelDock::Bottom,
+            min_severity: severity(&LogLevel::Info),
+            location: None,
+            search: String::new(),
+        }
+    }
+}
+
+impl LogPanel {
+    fn filter_ui(&mut self, ui: &mut egui::Ui, data_source: &dyn DataSource) {
+        ui.horizontal(|ui| {
+            ui.heading("Logs");
+            ui.separator();
+
+            let levels = [LogLevel::Debug, LogLevel::Info, LogLevel::Warning, LogLevel::Error, LogLevel::Critical];
+            let selected = levels.iter().find(|ll| severity(ll) == self.min_severity).map(|ll| ll.to_string());
+            egui::ComboBox::from_id_source("log_level_filter")
+                .selected_text(format!("≥ {}", selected.unwrap_or_default()))
+                .show_ui(ui, |ui| {
+                    for ll in levels.iter() {
+                        ui.selectable_value(&mut self.min_severity, severity(ll), ll.to_string());
+                    }
+                });
+
+            let locations: BTreeSet<&str> = data_source.log_messages().map(|(_, loc, _, _)| loc.as_str()).collect();
+            egui::ComboBox::from_id_source("log_location_filter")
+                .selected_text(self.location.clone().unwrap_or("All locations".to_string()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.location, None, "All locations");
+                    for loc in locations.into_iter() {
+                        ui.selectable_value(&mut self.location, Some(loc.to_string()), loc);
+                    }
+                });
+
+            ui.add(TextEdit::singleline(&mut self.search).hint_text("🔍 Search").desired_width(200.0));
+
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                ui.selectable_value(&mut self.dock, LogPanelDock::Window, "🗗");
+                ui.selectable_value(&mut self.dock, LogPanelDock::Right, "⏵");
+                ui.selectable_value(&mut self.dock, LogPanelDock::Bottom, "⏷");
+            });
+        });
+    }
+
+    fn main_ui(&mut self, ui: &mut egui::Ui, data_source: &dyn DataSource) -> Option<u32> {
+        self.filter_ui(ui, data_source);
+        ui.separator();
+
+        let search = self.search.to_lowercase();
+        let messages = data_source.log_messages().filter(|(_t, loc, ll, msg)| {
+            severity(ll) >= self.min_severity
+                && self.location.as_ref().map(|l| l == loc).unwrap_or(true)
+                && (search.is_empty() || msg.to_lowercase().contains(&search))
+        });
+
+        ui.log_scroller(messages)
+    }
+
+    /// Shows the log panel if it is open. Returns the time of a log message if one was clicked.
+    /// Has to be called before the central panel is created.
+    pub fn show_if_open(&mut self, ctx: &egui::Context, data_source: &dyn DataSource, enabled: bool) -> Option<u32> {
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+
+        if !self.open {
+            return None;
+        }
+
+        match self.dock {
+            LogPanelDock::Bottom => {
+                egui::TopBottomPanel::bottom("log_panel")
+                    .resizable(true)
+                    .default_height(200.0)
+                    .show(ctx, |ui| {
+                        ui.set_enabled(enabled);
+                        self.main_ui(ui, data_source)
+                    })
+                    .inner
+            }
+            LogPanelDock::Right => {
+                egui::SidePanel::right("log_panel")
+                    .resizable(true)
+                    .default_width(500.0)
+                    .show(ctx, |ui| {
+                        ui.set_enabled(enabled);
+                        self.main_ui(ui, data_source)
+                    })
+                    .inner
+            }
+            LogPanelDock::Window => {
+                // avoids mutably borrowing self
+                let mut open = self.open;
+                let clicked = egui::Window::new("Logs")
+                    .open(&mut open)
+                    .default_size([700.0, 300.0])
+                    .resizable(true)
+                    .show(ctx, |ui| {
+                        ui.set_enabled(enabled);
+                        self.main_ui(ui, data_source)
+                    })
+                    .and_then(|ir| ir.inner)
+                    .flatten();
+                self.open = open;
+                clicked
+            }
+        }
+    }
+}
@@ -1,10 +1,12 @@
+#[cfg(not(target_arch = "wasm32"))]
+use crate::data_source::{HilDataSource, HilTarget, NetworkDataSource};
 use crate::data_source::{SimulationDataSource, LogFileDataSource};
 use crate::file::*;
 use crate::gui::tabs::GuiTab;
 use crate::gui::Sam;
 
 use eframe::egui;
-use egui::{Align, Layout, Vec2};
+use egui::{Align, Layout, TextEdit, Vec2};
 
 pub struct MenuBarPanel {}
 
@@ -16,6 +18,16 @@ impl MenuBarPanel {
         let any = sam.data_source.as_any();
         let data_source_is_sim = any.is::<SimulationDataSource>();
         let data_source_is_log = any.is::<LogFileDataSource>();
+        #[cfg(not(target_arch = "wasm32"))]
+        let data_source_is_network = any.is::<NetworkDataSource>();
+        #[cfg(target_arch = "wasm32")]
+        let data_source_is_network = false;
+        #[cfg(not(target_arch = "wasm32"))]
+        let hil_target = any.downcast_ref::<HilDataSource>().map(|hil| hil.target());
+        #[cfg(not(target_arch = "wasm32"))]
+        let data_source_is_hil = hil_target.is_some();
+        #[cfg(target_arch = "wasm32")]
+        let data_source_is_hil = false;
 
         egui::TopBottomPanel::top("menubar").min_height(30.0).max_height(30.0).show(ctx, |ui| {
             ui.set_enabled(enabled);
@@ -48,16 +60,90 @@ impl MenuBarPanel {
                 // Toggle archive panel
                 ui.toggle_value(&mut sam.archive_window.open, "🗄 Flight Archive");
 
+                // Connect to the telemetry relay of another ground station
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.menu_button("🖧 Relay", |ui| {
+                    ui.add(
+                        TextEdit::singleline(&mut sam.settings.remote_relay_address)
+                            .hint_text("host:port or ws://host:port"),
+                    );
+                    if ui.button("Connect").clicked() {
+                        let address = sam.settings.remote_relay_address.clone();
+                        sam.data_source = Box::new(NetworkDataSource::new(ctx, address));
+                        ui.close_menu();
+                    }
+                });
+
+                // Toggle flight report window
+                ui.toggle_value(&mut sam.report_window.open, "📋 Report");
+
+                // Toggle export window
+                ui.toggle_value(&mut sam.export_window.open, "💾 Export");
+
+                // Toggle custom plot editor
+                ui.toggle_value(&mut sam.plot_editor_window.open, "🗠 Custom Plots");
+
+                // Toggle dashboard layout editor
+                ui.toggle_value(&mut sam.layout_editor_window.open, "🗔 Layouts");
+
+                // Toggle log panel
+                ui.toggle_value(&mut sam.log_panel.open, "📜 Logs");
+
                 // Toggle archive panel
                 #[cfg(all(not(target_arch = "wasm32"), not(target_os = "android")))]
                 if ui.selectable_label(data_source_is_sim, "💻 Simulate").clicked() {
                     sam.data_source = Box::new(SimulationDataSource::default());
                 }
 
+                // Rerun the current simulation in real time against a flight computer, or go back
+                #[cfg(all(not(target_arch = "wasm32"), not(target_os = "android")))]
+                if data_source_is_sim || data_source_is_hil {
+                    let toggles = [
+                        (
+                            HilTarget::Loopback,
+                            "🔁 Loopback FC",
+                            "Run the simulation in real time against a local flight computer stand-in",
+                        ),
+                        (
+                            HilTarget::Serial,
+                            "🔌 Serial FC",
+                            "Run the simulation in real time against the flight computer connected via USB",
+                        ),
+                    ];
+
+                    for (target, label, hover_text) in toggles {
+                        let selected = hil_target == Some(target);
+                        if !ui.selectable_label(selected, label).on_hover_text(hover_text).clicked() {
+                            continue;
+                        }
+
+                        let any = sam.data_source.as_any();
+                        let settings = any
+                            .downcast_ref::<SimulationDataSource>()
+                            .map(|sim| sim.settings.clone())
+                            .or(any.downcast_ref::<HilDataSource>().map(|hil| hil.settings.clone()));
+                        let Some(settings) = settings else {
+                            continue;
+                        };
+
+                        sam.data_source = if selected {
+                            Box::new(SimulationDataSource::new(settings))
+                        } else {
+                            Box::new(HilDataSource::new(ctx, &sam.settings, settings, target))
+                        };
+                    }
+                }
+
+                // Toggle Monte Carlo window, only available for simulations
+                #[cfg(all(not(target_arch = "wasm32"), not(target_os = "android")))]
+                if data_source_is_sim {
+                    ui.toggle_value(&mut sam.monte_carlo_window.open, "🎲 Monte Carlo");
+                }
+
                 // Show a button to the right to close the current log/simulation and go back to
                 // live view
                 ui.allocate_ui_with_layout(ui.available_size(), Layout::right_to_left(Align::Center), |ui| {
-                    if data_source_is_log || data_source_is_sim {
+                    if data_source_is_log || data_source_is_sim || data_source_is_network || data_source_is_hil {
                         if ui.button("❌").clicked() {
                             sam.close_data_source(ctx);
                         }
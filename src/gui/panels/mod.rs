@@ -1,7 +1,9 @@
 pub mod header;
+pub mod log;
 pub mod simulation;
 pub mod menu_bar;
 
 pub use header::*;
+pub use log::*;
 pub use simulation::*;
 pub use menu_bar::*;
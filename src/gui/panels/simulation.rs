@@ -3,7 +3,7 @@ use egui::CollapsingHeader;
 use crate::data_source::{DataSource, SimulationDataSource};
 use crate::gui::fc_settings::FcSettingsUiExt;
 use crate::gui::simulation_settings::SimulationSettingsUiExt;
-use crate::simulation::SimulationSettings;
+use crate::simulation::{SimulationSettings, SCENARIO_LIBRARY};
 
 pub struct SimulationPanel {}
 
@@ -19,6 +19,32 @@ impl SimulationPanel {
             ui.heading("Simulation");
             ui.add_space(20.0);
 
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source("scenario").selected_text("Example Scenarios").show_ui(ui, |ui| {
+                    for (name, contents) in SCENARIO_LIBRARY {
+                        if ui.selectable_label(false, name).clicked() {
+                            match SimulationSettings::from_scenario(contents) {
+                                Ok(settings) => data_source.settings = settings,
+                                Err(e) => log::error!("{}", e),
+                            }
+                        }
+                    }
+                });
+
+                #[cfg(target_arch = "x86_64")]
+                if ui.button("🗁 Load").on_hover_text("Load a scenario file").clicked() {
+                    if let Some(settings) = crate::file::open_scenario_file() {
+                        data_source.settings = settings;
+                    }
+                }
+
+                #[cfg(target_arch = "x86_64")]
+                if ui.button("💾 Save").on_hover_text("Save the current settings as a scenario file").clicked() {
+                    crate::file::save_scenario_file(&data_source.settings);
+                }
+            });
+            ui.add_space(10.0);
+
             CollapsingHeader::new("Simulation Parameters").default_open(true).show(ui, |ui| {
                 data_source.settings.ui(ui)
             });
@@ -38,6 +64,11 @@ impl SimulationPanel {
                 if ui.button("↻  Rerun").clicked() || changed {
                     data_source.reset();
                 }
+
+                if ui.button("🎲 Rerun with new seed").clicked() {
+                    data_source.settings.reseed();
+                    data_source.reset();
+                }
             });
         });
     }
@@ -2,6 +2,7 @@
 
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::Arc;
 
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant;
@@ -9,16 +10,30 @@ use std::time::Instant;
 use web_time::Instant;
 
 use eframe::egui;
+use eframe::egui::mutex::RwLock;
 use eframe::egui::PointerButton;
-use egui_plot::{AxisBools, Corner, Legend, Line, LineStyle, PlotBounds, VLine};
+use egui::{Align2, CursorIcon, Rect};
+use egui_plot::{AxisBools, Corner, Legend, Line, LineStyle, PlotBounds, PlotPoint, Points, Text, VLine};
 
+mod lod;
+
+use crate::expression::Expression;
 use crate::gui::*;
+use crate::settings::{LineDefinition, PlotDefinition};
 use crate::telemetry_ext::*;
 
-const DOWNSAMPLING_FACTOR: usize = 4;
-const MAX_DOWNSAMPLING_RUNS: usize = 2;
+use lod::{Bucket, LinePyramid, LineView, Pyramids};
+
+/// Distance from a measurement cursor within which it can be grabbed [px]
+const CURSOR_GRAB_DISTANCE: f32 = 5.0;
+const CURSOR_NAMES: [&str; 2] = ["A", "B"];
+/// Minimum distance between labeled ticks on the time and value axes [px]
+const MIN_X_TICK_SPACING: f32 = 80.0;
+const MIN_Y_TICK_SPACING: f32 = 30.0;
+/// Distance of the axis labels from the edges of the plot [px]
+const AXIS_LABEL_MARGIN: f32 = 2.0;
 
-fn plot_time(x: &Instant, data_source: &dyn DataSource) -> f64 {
+pub fn plot_time(x: &Instant, data_source: &dyn DataSource) -> f64 {
     if let Some((first_t, _first_vs)) = data_source.vehicle_states().next() {
         x.duration_since(*first_t).as_secs_f64()
     } else {
@@ -26,128 +41,210 @@ fn plot_time(x: &Instant, data_source: &dyn DataSource) -> f64 {
     }
 }
 
-fn downsample_points(data: &Vec<[f64; 2]>) -> Vec<[f64; 2]> {
-    data.chunks(DOWNSAMPLING_FACTOR * 2)
-        .map(|chunk| {
-            let (min_i, min) = chunk.iter().enumerate().min_by(|(_, x), (_, y)| x[1].total_cmp(&y[1])).unwrap();
-            let (max_i, max) = chunk.iter().enumerate().max_by(|(_, x), (_, y)| x[1].total_cmp(&y[1])).unwrap();
-            match (min_i, max_i) {
-                (i, j) if i < j => [*min, *max],
-                _ => [*max, *min],
-            }
-        })
-        .flatten()
-        .collect()
+/// The vehicle states up to the given time, or all of them if no time is given.
+pub fn states_until(data_source: &dyn DataSource, time: Option<Instant>) -> &[(Instant, VehicleState)] {
+    let states = data_source.vehicle_states().as_slice();
+    let end = time.map(|time| states.partition_point(|(t, _)| *t <= time)).unwrap_or(states.len());
+    &states[..end]
 }
 
-/// Cache for a single line.
-struct PlotCacheLine {
-    name: String,
-    color: Color32,
-    pub callback: Box<dyn FnMut(&VehicleState) -> Option<f32>>,
-    /// Increasingly downsampled plot data. The first entry is the full data, followed by
-    /// smaller and smaller vectors.
-    data: Vec<Vec<[f64; 2]>>,
-    stats: Option<(f64, f64, f64, f64)>,
-    last_bounds: Option<PlotBounds>,
-    last_view: Vec<[f64; 2]>,
+/// Screen x coordinate of the given x-axis value, for a plot drawn in the given rect.
+fn x_to_screen(x: f64, rect: Rect, bounds: &PlotBounds) -> f32 {
+    rect.left() + ((x - bounds.min()[0]) / bounds.width()) as f32 * rect.width()
 }
 
-impl PlotCacheLine {
-    pub fn new(name: &str, color: Color32, cb: impl FnMut(&VehicleState) -> Option<f32> + 'static) -> Self {
-        Self {
-            name: name.to_string(),
-            color,
-            callback: Box::new(cb),
-            data: vec![Vec::new()],
-            stats: None,
-            last_bounds: None,
-            last_view: vec![],
-        }
-    }
+/// Screen y coordinate of the given y-axis value, for a plot drawn in the given rect.
+fn y_to_screen(y: f64, rect: Rect, bounds: &PlotBounds) -> f32 {
+    rect.bottom() - ((y - bounds.min()[1]) / bounds.height()) as f32 * rect.height()
+}
 
-    fn update_cache(&mut self, data_source: &dyn DataSource, keep_first: usize) {
-        let new_data = data_source.vehicle_states()
-                .skip(keep_first)
-                .map(|(t, vs)| (plot_time(t, data_source), vs))
-                .filter_map(|(x, vs)| (self.callback)(vs).map(|y| [x, y as f64]));
+/// x-axis value at the given screen x coordinate, for a plot drawn in the given rect.
+fn screen_to_x(x: f32, rect: Rect, bounds: &PlotBounds) -> f64 {
+    bounds.min()[0] + ((x - rect.left()) / rect.width()) as f64 * bounds.width()
+}
 
-        if keep_first > 0 {
-            self.data[0].extend(new_data)
-        } else {
-            self.data[0] = new_data.collect();
-        }
+/// Legend text for the values of a line at both measurement cursors.
+fn cursor_readout(a: Option<f64>, b: Option<f64>, dt: f64) -> String {
+    let value = |v: Option<f64>| v.map(|v| format!("{:.2}", v)).unwrap_or("N/A".into());
+    let delta = a.zip(b).map(|(a, b)| b - a);
+    let slope = delta.filter(|_| dt != 0.0).map(|d| format!("{:.2}/s", d / dt)).unwrap_or("N/A".into());
+    format!("A: {}, B: {}, Δ: {}, Δ/Δt: {}", value(a), value(b), value(delta), slope)
+}
 
-        // clear the downsample caches
-        self.data.truncate(1);
-    }
+/// Distance between ticks, as 1, 2 or 5 times a power of ten, so that the
+/// given range has ticks at least `min_spacing` pixels apart.
+fn tick_step(range: f64, pixels: f32, min_spacing: f32) -> f64 {
+    let min_step = range * (min_spacing / pixels.max(1.0)) as f64;
+    let magnitude = 10f64.powf(min_step.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|f| f * magnitude)
+        .find(|step| *step >= min_step)
+        .unwrap_or(min_step)
+}
+
+/// All multiples of the step within the given range.
+fn ticks(min: f64, max: f64, step: f64) -> impl Iterator<Item = f64> {
+    let first = (min / step).ceil() as i64;
+    let last = (max / step).floor() as i64;
+    (first..=last).map(move |i| i as f64 * step)
+}
 
-    fn clear_cache(&mut self) {
-        self.data.truncate(1);
-        self.data[0].truncate(0);
+/// Number of decimals needed to tell ticks with the given step apart.
+fn tick_decimals(step: f64) -> usize {
+    (-step.log10().floor()).max(0.0) as usize
+}
+
+/// Label for a tick on the time axis, using minutes for longer views.
+fn format_time(t: f64, step: f64, minutes: bool) -> String {
+    if minutes && step >= 1.0 {
+        let seconds = t.abs().round() as u64;
+        let sign = if t < 0.0 { "-" } else { "" };
+        format!("{}{}:{:02}", sign, seconds / 60, seconds % 60)
+    } else {
+        format!("{:.*}s", tick_decimals(step), t)
     }
+}
 
-    fn cached_data_downsampled(&mut self, bounds: PlotBounds, view_width: f32) -> Vec<[f64; 2]> {
-        if self.data[0].len() == 0 {
-            return Vec::new();
-        }
+/// Label for a tick on the value axis.
+fn format_value(y: f64, step: f64, unit: Option<&str>) -> String {
+    match unit {
+        Some(unit) => format!("{:.*} {}", tick_decimals(step), y, unit),
+        None => format!("{:.*}", tick_decimals(step), y),
+    }
+}
 
-        let (xmin, xmax) = (bounds.min()[0], bounds.max()[0]);
+/// Unit given at the end of a line name, e.g. "m/s" for "Vario [m/s]".
+fn line_unit(name: &str) -> Option<&str> {
+    let (_, unit) = name.strip_suffix(']')?.rsplit_once('[')?;
+    (!unit.is_empty()).then_some(unit)
+}
 
-        let mut imin = self.data[0].partition_point(|d| d[0] < xmin);
-        let mut imax = imin + self.data[0][imin..].partition_point(|d| d[0] < xmax);
+/// Paints time and value labels along the bottom and right edges, inside
+/// of the plot, to avoid taking space from the plot grid.
+fn paint_axis_labels(ui: &egui::Ui, rect: Rect, bounds: &PlotBounds, unit: Option<&str>) {
+    let (xmin, ymin) = (bounds.min()[0], bounds.min()[1]);
+    let (xmax, ymax) = (bounds.max()[0], bounds.max()[1]);
+    let valid = |range: f64| range.is_finite() && range > 0.0;
+    if !valid(bounds.width()) || !valid(bounds.height()) {
+        return;
+    }
 
-        imin = imin.saturating_sub(1);
-        imax = usize::min(imax + 1, self.data[0].len() - 1);
+    let painter = ui.painter_at(rect);
+    let font = egui::TextStyle::Small.resolve(ui.style());
+    let color = ui.visuals().weak_text_color();
+    let background = ui.visuals().extreme_bg_color.gamma_multiply(0.7);
+    // Labels are only painted if they are entirely within the given area.
+    let label = |text: String, anchor: Align2, pos: egui::Pos2, area: Rect| {
+        let galley = painter.layout_no_wrap(text, font.clone(), color);
+        let label_rect = anchor.anchor_rect(Rect::from_min_size(pos, galley.size()));
+        if area.contains_rect(label_rect) {
+            painter.rect_filled(label_rect.expand(1.0), 2.0, background);
+            painter.galley(label_rect.min, galley);
+        }
+    };
+
+    // The bottom edge is left to the time labels.
+    let value_area = Rect::from_min_max(rect.min, egui::pos2(rect.right(), rect.bottom() - 2.0 * font.size));
+    let y_step = tick_step(ymax - ymin, rect.height(), MIN_Y_TICK_SPACING);
+    for y in ticks(ymin, ymax, y_step) {
+        let pos = egui::pos2(rect.right() - AXIS_LABEL_MARGIN, y_to_screen(y, rect, bounds));
+        label(format_value(y, y_step, unit), Align2::RIGHT_CENTER, pos, value_area);
+    }
 
-        for level in 0..=MAX_DOWNSAMPLING_RUNS {
-            if level >= self.data.len() {
-                self.data.push(downsample_points(&self.data[level-1]));
-            }
+    let x_step = tick_step(xmax - xmin, rect.width(), MIN_X_TICK_SPACING);
+    let minutes = xmin.abs().max(xmax.abs()) >= 60.0;
+    for x in ticks(xmin, xmax, x_step) {
+        let pos = egui::pos2(x_to_screen(x, rect, bounds), rect.bottom() - AXIS_LABEL_MARGIN);
+        label(format_time(x, x_step, minutes), Align2::CENTER_BOTTOM, pos, rect);
+    }
+}
 
-            if imax - imin < (2.0 * view_width) as usize {
-                return self.data[level][imin..imax].to_vec();
-            } else if level < MAX_DOWNSAMPLING_RUNS {
-                imin /= DOWNSAMPLING_FACTOR;
-                imax /= DOWNSAMPLING_FACTOR;
-            }
+/// Mesh filling the area between the minima and maxima of a line's buckets,
+/// given as (x, min, max).
+fn band_mesh(band: &[[f64; 3]], color: Color32, rect: Rect, bounds: &PlotBounds) -> egui::Mesh {
+    let mut mesh = egui::Mesh::default();
+    for (i, [x, min, max]) in band.iter().enumerate() {
+        let x = x_to_screen(*x, rect, bounds);
+        mesh.colored_vertex(egui::pos2(x, y_to_screen(*min, rect, bounds)), color);
+        mesh.colored_vertex(egui::pos2(x, y_to_screen(*max, rect, bounds)), color);
+
+        // Two triangles between this bucket and the previous one
+        if i > 0 {
+            let i = 2 * i as u32;
+            mesh.add_triangle(i - 2, i - 1, i);
+            mesh.add_triangle(i - 1, i, i + 1);
         }
+    }
+    mesh
+}
+
+/// Cache for a single line.
+struct PlotCacheLine {
+    name: String,
+    color: Color32,
+    pyramid: Arc<RwLock<LinePyramid>>,
+    /// x-axis values of all states, shared by all lines
+    times: Arc<RwLock<Vec<f64>>>,
+    stats: Option<(f64, f64, f64, f64)>,
+    /// Bounds and pyramid version of the last view
+    last_view_key: Option<(PlotBounds, u64)>,
+    /// Line and min/max band of the last view
+    last_view: (Vec<[f64; 2]>, Vec<[f64; 3]>),
+}
 
-        self.data.last().unwrap()[imin..imax].to_vec()
+impl PlotCacheLine {
+    pub fn new(name: &str, color: Color32, pyramid: Arc<RwLock<LinePyramid>>, times: Arc<RwLock<Vec<f64>>>) -> Self {
+        Self {
+            name: name.to_string(),
+            color,
+            pyramid,
+            times,
+            stats: None,
+            last_view_key: None,
+            last_view: (Vec::new(), Vec::new()),
+        }
     }
 
-    pub fn data_for_bounds(&mut self, bounds: PlotBounds, data_source: &dyn DataSource, view_width: f32) -> Vec<[f64; 2]> {
+    /// The line for the given bounds, through the samples or the means of the
+    /// buckets, and the band between the buckets' minima and maxima as
+    /// (x, min, max). The band is empty if all samples in view can be drawn.
+    pub fn data_for_bounds(&mut self, bounds: PlotBounds, view_width: f32) -> (Vec<[f64; 2]>, Vec<[f64; 3]>) {
         #[cfg(feature = "profiling")]
         puffin::profile_function!();
 
-        let len = data_source.vehicle_states().len();
-        if len == 0 || self.data.is_empty() {
-            self.last_bounds = None;
-            self.stats = None;
-            return vec![];
-        }
-
-        if self.last_bounds.map(|b| b != bounds).unwrap_or(true) {
-            self.last_view = self.cached_data_downsampled(bounds, view_width);
-            self.last_bounds = Some(bounds);
-            self.stats = None;
+        let pyramid = self.pyramid.read();
+        let key = Some((bounds, pyramid.version));
+        if self.last_view_key != key {
+            let times = self.times.read();
+            // About one bucket per pixel
+            let max_buckets = view_width as usize;
+            let view = pyramid.view(&times, bounds.min()[0], bounds.max()[0], max_buckets);
+            let buckets = view.buckets();
+
+            let line = buckets.iter().map(|b| [b.x(&times), b.mean()]).collect();
+            let band = match view {
+                LineView::Samples(_) => Vec::new(),
+                LineView::Buckets(_) => buckets.iter().map(|b| [b.x(&times), b.min as f64, b.max as f64]).collect(),
+            };
+
+            self.stats = buckets
+                .iter()
+                .copied()
+                .reduce(Bucket::merge)
+                .map(|b| (b.mean(), b.std_dev(), b.min as f64, b.max as f64));
+            self.last_view = (line, band);
+            self.last_view_key = key;
         }
 
         self.last_view.clone()
     }
 
-    pub fn stats(&mut self) -> Option<(f64, f64, f64, f64)> {
-        if self.stats.is_none() && self.last_view.len() > 0 {
-            let count = self.last_view.len() as f64;
-            let mean = self.last_view.iter().map(|i| i[1]).sum::<f64>() / count;
-            let var = self.last_view.iter().map(|i| f64::powi(i[1] - mean, 2)).sum::<f64>() / count;
-            let std_dev = f64::sqrt(var);
-            let min = self.last_view.iter().map(|i| i[1]).reduce(|a, b| f64::min(a, b)).unwrap();
-            let max = self.last_view.iter().map(|i| i[1]).reduce(|a, b| f64::max(a, b)).unwrap();
-            self.stats = Some((mean, std_dev, min, max));
-        }
-
-        self.stats
+    /// The last value at or before the given x-axis value.
+    fn value_at(&self, x: f64) -> Option<f64> {
+        let pyramid = self.pyramid.read();
+        let times = self.times.read();
+        pyramid.value_at(&times, x).map(|y| y as f64)
     }
 }
 
@@ -155,11 +252,9 @@ impl PlotCacheLine {
 /// on each draw.
 struct PlotCache {
     lines: Vec<PlotCacheLine>,
-    mode_transitions: Vec<(f64, FlightMode)>,
     reset_on_next_draw: bool,
-    /// Identifies the origin of the current data using the last time cached and the number of
-    /// states included
-    cached_state: Option<(Instant, usize)> // TODO: maybe add some sort of flight identifier?
+    /// Screen rect and bounds of the last draw, used to find the cursor under the pointer
+    last_frame: Option<(Rect, PlotBounds)>,
 }
 
 impl PlotCache {
@@ -167,96 +262,45 @@ impl PlotCache {
     fn new() -> Self {
         Self {
             lines: Vec::new(),
-            mode_transitions: Vec::new(),
             reset_on_next_draw: false,
-            cached_state: None,
+            last_frame: None,
         }
     }
 
-    fn add_line(&mut self, name: &str, color: Color32, cb: impl FnMut(&VehicleState) -> Option<f32> + 'static) {
-        self.lines.push(PlotCacheLine::new(name, color, cb));
-    }
-
-    fn update_mode_transition_cache(&mut self, data_source: &dyn DataSource, keep_first: usize) {
-        let last_mode = (keep_first > 0).then_some(self.mode_transitions.last().map(|(_,m)| *m)).unwrap_or(None);
-        let new_data = data_source.vehicle_states()
-            .skip(keep_first)
-            .filter(|(_t, vs)| vs.mode.is_some())
-            .scan(last_mode, |state, (t, vs)| {
-                if &vs.mode != state {
-                    *state = vs.mode;
-                    Some(Some((plot_time(t, data_source), vs.mode.unwrap())))
-                } else {
-                    Some(None)
-                }
-            })
-            .filter_map(|x| x);
-
-        if keep_first > 0 {
-            self.mode_transitions.extend(new_data);
-        } else {
-            self.mode_transitions = new_data.collect();
-        }
+    fn add_line(
+        &mut self,
+        name: &str,
+        color: Color32,
+        pyramid: Arc<RwLock<LinePyramid>>,
+        times: Arc<RwLock<Vec<f64>>>,
+    ) {
+        self.lines.push(PlotCacheLine::new(name, color, pyramid, times));
     }
 
-    fn update_caches_if_necessary(&mut self, data_source: &dyn DataSource) {
-        let new_len = data_source.vehicle_states().len();
-        if new_len == 0 {
-            self.mode_transitions.truncate(0);
-            self.lines.iter_mut().for_each(|l| { l.clear_cache(); });
-            self.cached_state = None;
-            return;
-        }
-
-        let (last_t, _) = data_source.vehicle_states().rev().next().unwrap().clone();
-        let cached_state = Some((last_t, new_len));
-
-        // We have already cached this exact set of vehicle states, do nothing.
-        if cached_state == self.cached_state {
-            return;
-        }
-
-        // Try to determine if the new data is simply a few more points appended to the previously
-        // plotted data, which we have cached. If so, we keep the old and simply append the new
-        // points. If not, we recalculate the cache completely.
-        let old_len = self.cached_state.map(|(_, l)| l).unwrap_or(0);
-        let mut keep_first = (new_len > old_len).then_some(old_len).unwrap_or(0);
-        if keep_first > 0 {
-            // double-check that it is actually the same set of states by looking for our previous
-            // last state in the new data
-            let (previous_last, _) = data_source.vehicle_states()
-                .rev()
-                .skip(new_len - keep_first)
-                .next()
-                .unwrap();
-            if self.cached_state.map(|(t, _)| t != *previous_last).unwrap_or(true) {
-                keep_first = 0;
-            }
-        }
-
-        self.lines.iter_mut().for_each(|l| { l.update_cache(data_source, keep_first); });
-        self.update_mode_transition_cache(data_source, keep_first);
-        self.cached_state = cached_state;
+    /// Unit shared by all lines, if any, for labeling the value axis.
+    fn unit(&self) -> Option<&str> {
+        let first = line_unit(&self.lines.first()?.name)?;
+        self.lines.iter().all(|l| line_unit(&l.name) == Some(first)).then_some(first)
     }
 
-    /// Lines to be plotted
+    /// Lines to be plotted, and the min/max bands of those with more samples
+    /// than can be drawn, with their colors
     pub fn plot_lines(
         &mut self,
         bounds: PlotBounds,
         show_stats: bool,
-        data_source: &dyn DataSource,
+        cursors: Option<[f64; 2]>,
         view_width: f32,
-    ) -> Vec<Line> {
+    ) -> (Vec<Line>, Vec<(Vec<[f64; 3]>, Color32)>) {
         #[cfg(feature = "profiling")]
         puffin::profile_function!();
 
-        self.update_caches_if_necessary(data_source);
         self.lines
             .iter_mut()
             .map(|pcl| {
-                let data = pcl.data_for_bounds(bounds, data_source, view_width);
-                let stats = show_stats.then(|| pcl.stats()).flatten();
-                let legend = if let Some((mean, std_dev, min, max)) = stats {
+                let (data, band) = pcl.data_for_bounds(bounds, view_width);
+                let stats = show_stats.then_some(pcl.stats).flatten();
+                let mut legend = if let Some((mean, std_dev, min, max)) = stats {
                     format!(
                         "{} (mean: {:.2}, std dev.: {:.2}, min: {:.2}, max: {:.2})",
                         pcl.name, mean, std_dev, min, max
@@ -264,19 +308,25 @@ impl PlotCache {
                 } else {
                     pcl.name.clone()
                 };
-                Line::new(data).name(legend).color(pcl.color)
+                if let Some([a, b]) = cursors {
+                    let readout = cursor_readout(pcl.value_at(a), pcl.value_at(b), b - a);
+                    legend = format!("{} ({})", legend, readout);
+                }
+
+                (Line::new(data).name(legend).color(pcl.color), (band, pcl.color.gamma_multiply(0.4)))
             })
-            .collect()
+            .unzip()
     }
 
-    /// Vertical mode transition lines to be plotted
-    pub fn mode_lines(&mut self, data_source: &dyn DataSource) -> Box<dyn Iterator<Item = VLine> + '_> {
-        #[cfg(feature = "profiling")]
-        puffin::profile_function!();
-
-        self.update_caches_if_necessary(data_source);
-        let iter = self.mode_transitions.iter().map(|(x, mode)| VLine::new(*x).color(mode.color()));
-        Box::new(iter)
+    /// Markers for the values of all lines at the measurement cursors
+    pub fn cursor_points(&self, cursors: [f64; 2]) -> Vec<Points> {
+        self.lines
+            .iter()
+            .map(|pcl| {
+                let points: Vec<[f64; 2]> = cursors.iter().filter_map(|x| pcl.value_at(*x).map(|y| [*x, y])).collect();
+                Points::new(points).radius(2.5).color(pcl.color)
+            })
+            .collect()
     }
 }
 
@@ -293,6 +343,18 @@ pub struct SharedPlotState {
     pub reset_on_next_draw: bool,
     pub box_dragging: bool,
     pub show_stats: bool,
+    /// x-axis value to center the plots on during the next draw
+    pub jump_to: Option<f64>,
+    /// x-axis values of the two measurement cursors, if shown
+    pub cursors: Option<[f64; 2]>,
+    /// Index of the cursor moved last, whose time is used for the other indicators
+    pub active_cursor: usize,
+    /// Index of the cursor currently being dragged
+    dragged_cursor: Option<usize>,
+    /// x-axis range of the last drawn view
+    view: Option<(f64, f64)>,
+    /// Downsampled data of all lines
+    pyramids: Pyramids,
 }
 
 impl SharedPlotState {
@@ -305,13 +367,36 @@ impl SharedPlotState {
             reset_on_next_draw: false,
             box_dragging: false,
             show_stats: false,
+            jump_to: None,
+            cursors: None,
+            active_cursor: 0,
+            dragged_cursor: None,
+            view: None,
+            pyramids: Pyramids::new(),
         }
     }
 
+    /// Shows or hides the measurement cursors. New cursors are placed at a
+    /// third and two thirds of the current view.
+    pub fn toggle_cursors(&mut self) {
+        self.cursors = match (self.cursors, self.view) {
+            (None, Some((min, max))) => Some([min + (max - min) / 3.0, min + 2.0 * (max - min) / 3.0]),
+            _ => None,
+        };
+        self.active_cursor = 0;
+        self.dragged_cursor = None;
+    }
+
     pub fn set_end(&mut self, end: Option<Instant>) {
         self.end = end.unwrap_or(self.start);
     }
 
+    /// Moves the view of all linked plots to the given x-axis value, detaching them from the edge.
+    pub fn jump_to(&mut self, x: f64) {
+        self.jump_to = Some(x);
+        self.attached_to_edge = false;
+    }
+
     pub fn process_zoom(&mut self, zoom_delta: Vec2) {
         self.view_width /= zoom_delta[0] as f64;
         // Zooming detaches the egui plot from the edge usually, so reattach
@@ -367,10 +452,69 @@ impl PlotState {
         }
     }
 
-    pub fn line(self, name: &str, color: Color32, cb: impl FnMut(&VehicleState) -> Option<f32> + 'static) -> Self {
-        self.cache.borrow_mut().add_line(name, color, cb);
+    pub fn line(
+        mut self,
+        name: &str,
+        color: Color32,
+        cb: impl FnMut(&VehicleState) -> Option<f32> + Send + 'static,
+    ) -> Self {
+        self.add_line(name, color, cb);
         self
     }
+
+    fn add_line(&mut self, name: &str, color: Color32, cb: impl FnMut(&VehicleState) -> Option<f32> + Send + 'static) {
+        let pyramid = self.shared.borrow_mut().pyramids.add_line(Box::new(cb));
+        let times = self.shared.borrow().pyramids.times.clone();
+        self.cache.borrow_mut().add_line(name, color, pyramid, times);
+    }
+
+    /// Create a plot from a user definition. Lines with invalid expressions are left out.
+    pub fn from_definition(definition: &PlotDefinition, shared: Rc<RefCell<SharedPlotState>>) -> Self {
+        let mut plot = Self::new(&definition.title, (definition.ymin, definition.ymax), shared);
+        plot.set_lines(&[], definition);
+        plot
+    }
+
+    /// Changes a plot created from the previous definition to the given one.
+    /// Lines whose expressions are unchanged keep their downsampled data, so
+    /// only new expressions have to be evaluated for all states.
+    pub fn update_definition(&mut self, previous: &PlotDefinition, definition: &PlotDefinition) {
+        self.title = definition.title.clone();
+        (self.ymin, self.ymax) = (definition.ymin, definition.ymax);
+        self.set_lines(&previous.lines, definition);
+    }
+
+    /// Replaces the lines of the plot, which were created from the given line definitions.
+    fn set_lines(&mut self, previous: &[LineDefinition], definition: &PlotDefinition) {
+        let previous_expressions = previous.iter().filter(|l| Expression::parse(&l.expression).is_ok());
+        let mut previous_lines: Vec<(&str, PlotCacheLine)> = previous_expressions
+            .map(|l| l.expression.as_str())
+            .zip(self.cache.borrow_mut().lines.drain(..))
+            .collect();
+
+        for line in definition.lines.iter() {
+            let name = if line.name.is_empty() {
+                &line.expression
+            } else {
+                &line.name
+            };
+            let [r, g, b] = line.color;
+            let color = Color32::from_rgb(r, g, b);
+
+            if let Some(i) = previous_lines.iter().position(|(e, _)| *e == line.expression) {
+                let (_, mut pcl) = previous_lines.remove(i);
+                pcl.name = name.to_string();
+                pcl.color = color;
+                self.cache.borrow_mut().lines.push(pcl);
+                continue;
+            }
+
+            match Expression::parse(&line.expression) {
+                Ok(mut expr) => self.add_line(name, color, move |vs| expr.eval(vs)),
+                Err(e) => ::log::warn!("Skipping line '{}' of plot '{}': {}", line.expression, definition.title, e),
+            }
+        }
+    }
 }
 
 pub trait PlotUiExt {
@@ -385,23 +529,38 @@ impl PlotUiExt for egui::Ui {
         let mut shared = state.shared.borrow_mut();
         let mut cache = state.cache.borrow_mut();
 
+        // Keep drawing while the downsampled data is catching up with the states.
+        if shared.pyramids.update(data_source.vehicle_states().as_slice()) {
+            self.ctx().request_repaint();
+        }
+
         let legend =
             Legend::default().text_style(egui::TextStyle::Small).background_alpha(0.5).position(Corner::LeftTop);
 
         let view_end = plot_time(&data_source.end().unwrap_or(Instant::now()), data_source);
+        // The measurement cursor under the pointer, using the plot's position during the last draw
+        let pointer = self.input(|i| i.pointer.hover_pos());
+        let hovered_cursor = shared.cursors.zip(cache.last_frame).zip(pointer).and_then(|((cursors, frame), pos)| {
+            let (rect, bounds) = frame;
+            let near = |x: &f64| (x_to_screen(*x, rect, &bounds) - pos.x).abs() < CURSOR_GRAB_DISTANCE;
+            rect.contains(pos).then(|| cursors.iter().position(near)).flatten()
+        });
+
+        if hovered_cursor.is_some() || shared.dragged_cursor.is_some() {
+            self.ctx().set_cursor_icon(CursorIcon::ResizeHorizontal);
+        }
+
         let mut plot = egui_plot::Plot::new(&state.title)
             .link_axis("plot_axis_group", true, false)
             .link_cursor("plot_cursor_group", true, false)
             .set_margin_fraction(egui::Vec2::new(0.0, 0.15))
             .allow_scroll(false) // TODO: x only
-            .allow_drag(AxisBools::new(true, false))
+            // Dragging a cursor should not move the plot.
+            .allow_drag(AxisBools::new(hovered_cursor.is_none() && shared.dragged_cursor.is_none(), false))
             .allow_zoom(AxisBools::new(true, false))
-            .show_axes([false, false]) // egui 0.23 changed axis ticks to be outside of the plot
-                                       // boundary. this uses an unacceptable amount of space, so
-                                       // for now we simply have no axis ticks.
-                                       // TODO: maybe upstream an option to move ticks inside?
-            .x_axis_position(egui_plot::VPlacement::Top)
-            .y_axis_position(egui_plot::HPlacement::Right)
+            // egui 0.23 places axis ticks outside of the plot, which takes too much
+            // space in the grid. We paint our own labels inside it instead.
+            .show_axes([false, false])
             .include_x(view_end - shared.view_width)
             .include_x(view_end)
             .auto_bounds_y()
@@ -424,18 +583,79 @@ impl PlotUiExt for egui::Ui {
         }
 
         let show_stats = shared.show_stats;
+        let cursors = shared.cursors;
+        let active_cursor = shared.active_cursor;
+        let jump_to = shared.jump_to;
+        let view_width_seconds = shared.view_width;
         let view_width = self.max_rect().width();
-        let ir = plot.show(self, move |plot_ui| {
-            let lines = cache.plot_lines(plot_ui.plot_bounds(), show_stats, data_source, view_width);
+        let mode_transitions = &shared.pyramids.mode_transitions;
+        let painter = self.painter().clone();
+        let mut bands = Vec::new();
+        let ir = plot.show(self, |plot_ui| {
+            if let Some(x) = jump_to {
+                let bounds = plot_ui.plot_bounds();
+                plot_ui.set_plot_bounds(PlotBounds::from_min_max(
+                    [x - view_width_seconds / 2.0, bounds.min()[1]],
+                    [x + view_width_seconds / 2.0, bounds.max()[1]],
+                ));
+            }
+
+            let bounds = plot_ui.plot_bounds();
+            // egui_plot can't fill arbitrary areas, so the min/max bands are painted as meshes. Their
+            // place is reserved here, above the plot's background and below the lines.
+            let band_slot = painter.add(egui::Shape::Noop);
+            let (lines, line_bands) = cache.plot_lines(bounds, show_stats, cursors, view_width);
             for l in lines.into_iter() {
                 plot_ui.line(l.width(1.2));
             }
+            bands = line_bands;
+
+            for (x, mode) in mode_transitions.iter() {
+                plot_ui.vline(VLine::new(*x).color(mode.color()).style(LineStyle::Dashed { length: 4.0 }));
+            }
 
-            for vl in cache.mode_lines(data_source).into_iter() {
-                plot_ui.vline(vl.style(LineStyle::Dashed { length: 4.0 }));
+            if let Some(cursors) = cursors {
+                for (i, x) in cursors.iter().enumerate() {
+                    let color = if i == active_cursor {
+                        Color32::WHITE
+                    } else {
+                        Color32::GRAY
+                    };
+                    plot_ui.vline(VLine::new(*x).color(color).width(1.0));
+                    let label_pos = PlotPoint::new(*x, bounds.max()[1]);
+                    plot_ui.text(Text::new(label_pos, CURSOR_NAMES[i]).color(color).anchor(Align2::LEFT_TOP));
+                }
+
+                for points in cache.cursor_points(cursors).into_iter() {
+                    plot_ui.points(points);
+                }
             }
+
+            (bounds, band_slot)
         });
 
+        let (bounds, band_slot) = ir.inner;
+        let rect = ir.response.rect;
+        let valid = bounds.width() > 0.0 && bounds.height() > 0.0;
+        let meshes = bands.iter().filter(|(band, _)| valid && !band.is_empty());
+        let meshes = meshes.map(|(band, color)| egui::Shape::mesh(band_mesh(band, *color, rect, &bounds))).collect();
+        self.painter_at(rect).set(band_slot, egui::Shape::Vec(meshes));
+        paint_axis_labels(self, rect, &bounds, cache.unit());
+        cache.last_frame = Some((rect, bounds));
+        shared.view = Some((bounds.min()[0], bounds.max()[0]));
+
+        // Grab a measurement cursor when starting to drag close to it, and move it along with the pointer.
+        if ir.response.drag_started_by(PointerButton::Primary) {
+            shared.dragged_cursor = hovered_cursor;
+        }
+
+        if let Some(i) = shared.dragged_cursor {
+            if let (Some(cursors), Some(pos)) = (shared.cursors.as_mut(), ir.response.interact_pointer_pos()) {
+                cursors[i] = screen_to_x(pos.x, rect, &bounds);
+                shared.active_cursor = i;
+            }
+        }
+
         // We have to check the interaction response to notice whether the plot
         // has been dragged or otherwise detached from the end of the data.
         if let Some(_hover_pos) = ir.response.hover_pos() {
@@ -448,10 +668,14 @@ impl PlotUiExt for egui::Ui {
             }
         };
 
-        if ir.response.dragged_by(PointerButton::Primary) {
+        if ir.response.dragged_by(PointerButton::Primary) && shared.dragged_cursor.is_none() {
             shared.attached_to_edge = false;
         }
 
+        if ir.response.drag_released {
+            shared.dragged_cursor = None;
+        }
+
         if ir.response.double_clicked_by(PointerButton::Primary) {
             shared.attached_to_edge = true;
         }
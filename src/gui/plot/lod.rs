@@ -0,0 +1,605 @@
+//! Level-of-detail pyramids for plot lines. The samples of each line are
+//! summarized into min/max buckets of increasing size, so any view of the
+//! data can be drawn from roughly one bucket per pixel, regardless of the
+//! length of the log. The pyramids of all lines in a group of linked plots
+//! are built incrementally by a single background thread.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Weak};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use eframe::egui::mutex::RwLock;
+
+use mithril::telemetry::{FlightMode, VehicleState};
+
+/// Number of entries of one level summarized by each bucket of the next
+const BUCKET_SIZE: usize = 4;
+/// Maximum number of states handed to the builder per frame, to keep
+/// cloning them cheap while catching up with a long log
+const MAX_STATES_PER_FRAME: usize = 50_000;
+/// Maximum number of chunks of states waiting to be processed by the builder
+const MAX_PENDING_CHUNKS: usize = 2;
+
+pub type LineCallback = Box<dyn FnMut(&VehicleState) -> Option<f32> + Send>;
+
+/// Summary of a range of samples of a line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bucket {
+    /// Indices of the times of the first and last sample
+    pub first: u32,
+    pub last: u32,
+    pub count: u32,
+    pub min: f32,
+    pub max: f32,
+    pub sum: f64,
+    pub sum_sq: f64,
+}
+
+impl From<(u32, f32)> for Bucket {
+    fn from((time, y): (u32, f32)) -> Self {
+        Self {
+            first: time,
+            last: time,
+            count: 1,
+            min: y,
+            max: y,
+            sum: y as f64,
+            sum_sq: (y as f64).powi(2),
+        }
+    }
+}
+
+impl Bucket {
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            first: self.first,
+            last: other.last,
+            count: self.count + other.count,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            sum: self.sum + other.sum,
+            sum_sq: self.sum_sq + other.sum_sq,
+        }
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        (self.sum_sq / self.count as f64 - self.mean().powi(2)).max(0.0).sqrt()
+    }
+
+    /// x-axis value of the center of the bucket
+    pub fn x(&self, times: &[f64]) -> f64 {
+        (times[self.first as usize] + times[self.last as usize]) / 2.0
+    }
+}
+
+/// A part of a line to be drawn, either as individual samples, or as buckets
+/// if there are more samples than can be displayed.
+pub enum LineView {
+    Samples(Vec<Bucket>),
+    Buckets(Vec<Bucket>),
+}
+
+impl LineView {
+    pub fn buckets(&self) -> &[Bucket] {
+        match self {
+            Self::Samples(b) | Self::Buckets(b) => b,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct LinePyramid {
+    /// Time index and value of each sample
+    samples: Vec<(u32, f32)>,
+    /// Increasingly coarse summaries of the samples. Each bucket of the first
+    /// level combines `BUCKET_SIZE` samples, each bucket of the following ones
+    /// `BUCKET_SIZE` buckets of the previous level, up to a single bucket.
+    levels: Vec<Vec<Bucket>>,
+    /// Incremented on each change, to let users know when to update
+    pub version: u64,
+}
+
+impl LinePyramid {
+    fn clear(&mut self) {
+        self.samples.clear();
+        self.levels.clear();
+        self.version += 1;
+    }
+
+    fn extend(&mut self, samples: &[(u32, f32)]) {
+        if samples.is_empty() {
+            return;
+        }
+
+        // Starting with the (possibly partial) bucket containing the first new
+        // sample, recalculate the buckets of each level.
+        let mut first_changed = self.samples.len() / BUCKET_SIZE;
+        self.samples.extend_from_slice(samples);
+
+        let mut below_len = self.samples.len();
+        let mut level = 0;
+        while below_len > 1 {
+            let len = below_len.div_ceil(BUCKET_SIZE);
+            let new_buckets: Vec<Bucket> = (first_changed..len)
+                .map(|i| {
+                    let range = (i * BUCKET_SIZE)..usize::min((i + 1) * BUCKET_SIZE, below_len);
+                    let bucket = match level {
+                        0 => self.samples[range].iter().map(|s| Bucket::from(*s)).reduce(Bucket::merge),
+                        _ => self.levels[level - 1][range].iter().copied().reduce(Bucket::merge),
+                    };
+                    bucket.unwrap() // ranges are never empty
+                })
+                .collect();
+
+            if self.levels.len() <= level {
+                self.levels.push(Vec::new());
+            }
+
+            self.levels[level].truncate(first_changed);
+            self.levels[level].extend(new_buckets);
+
+            first_changed /= BUCKET_SIZE;
+            below_len = len;
+            level += 1;
+        }
+
+        self.version += 1;
+    }
+
+    /// The part of the line between the given x-axis values, using the finest
+    /// level with at most `max_buckets` entries. Includes one sample or bucket
+    /// on either side, to continue the line to the edges of the view.
+    pub fn view(&self, times: &[f64], xmin: f64, xmax: f64, max_buckets: usize) -> LineView {
+        let x = |(t, _): &(u32, f32)| times[*t as usize];
+        let start = self.samples.partition_point(|s| x(s) < xmin).saturating_sub(1);
+        let end = usize::min(self.samples.partition_point(|s| x(s) <= xmax) + 1, self.samples.len());
+        if end - start <= max_buckets.max(1) {
+            return LineView::Samples(self.samples[start..end].iter().map(|s| Bucket::from(*s)).collect());
+        }
+
+        let (mut start, mut end) = (start, end);
+        for level in self.levels.iter() {
+            start /= BUCKET_SIZE;
+            end = end.div_ceil(BUCKET_SIZE);
+            if end - start <= max_buckets {
+                return LineView::Buckets(level[start..end].to_vec());
+            }
+        }
+
+        LineView::Buckets(self.levels.last().map(|l| l[start..end].to_vec()).unwrap_or_default())
+    }
+
+    /// The last value at or before the given x-axis value.
+    pub fn value_at(&self, times: &[f64], x: f64) -> Option<f32> {
+        let i = self.samples.partition_point(|(t, _)| times[*t as usize] <= x);
+        (i > 0).then(|| self.samples[i - 1].1)
+    }
+}
+
+enum Message {
+    AddLine(LineCallback, Weak<RwLock<LinePyramid>>),
+    Reset,
+    /// States starting at the given index, with their x-axis values
+    States(usize, Vec<(f64, VehicleState)>),
+}
+
+struct BuilderLine {
+    callback: LineCallback,
+    pyramid: Weak<RwLock<LinePyramid>>,
+    /// Number of states evaluated for this line
+    processed: usize,
+}
+
+/// Evaluates the lines for the received states and adds the results to
+/// their pyramids. Lines are dropped once their pyramid is no longer used.
+struct PyramidBuilder {
+    times: Arc<RwLock<Vec<f64>>>,
+    lines: Vec<BuilderLine>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl PyramidBuilder {
+    fn process(&mut self, msg: Message) {
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+
+        match msg {
+            Message::AddLine(callback, pyramid) => {
+                self.lines.push(BuilderLine {
+                    callback,
+                    pyramid,
+                    processed: 0,
+                });
+            }
+            Message::Reset => {
+                // The lines are cleared before the times they refer to.
+                for line in self.lines.iter_mut() {
+                    line.processed = 0;
+                    if let Some(pyramid) = line.pyramid.upgrade() {
+                        pyramid.write().clear();
+                    }
+                }
+                self.times.write().clear();
+            }
+            Message::States(start, states) => {
+                {
+                    let mut times = self.times.write();
+                    if (start..start + states.len()).contains(&times.len()) {
+                        let skip = times.len() - start;
+                        times.extend(states.iter().skip(skip).map(|(x, _)| *x));
+                    }
+                }
+
+                self.lines.retain(|line| line.pyramid.strong_count() > 0);
+                for line in self.lines.iter_mut() {
+                    // Lines added later need the earlier states first, which are sent again.
+                    if !(start..start + states.len()).contains(&line.processed) {
+                        continue;
+                    }
+
+                    let skip = line.processed - start;
+                    let samples: Vec<(u32, f32)> = states
+                        .iter()
+                        .enumerate()
+                        .skip(skip)
+                        .filter_map(|(i, (_, vs))| (line.callback)(vs).map(|y| ((start + i) as u32, y)))
+                        .filter(|(_, y)| y.is_finite())
+                        .collect();
+
+                    if let Some(pyramid) = line.pyramid.upgrade() {
+                        pyramid.write().extend(&samples);
+                    }
+                    line.processed = start + states.len();
+                }
+
+                self.pending.fetch_sub(1, Ordering::Release);
+            }
+        }
+    }
+}
+
+/// Feeds the vehicle states to the pyramid builder, and holds the data
+/// shared by all lines of a group of plots.
+pub struct Pyramids {
+    /// x-axis values of all states handed to the builder so far
+    pub times: Arc<RwLock<Vec<f64>>>,
+    /// Mode transitions within the states handed to the builder so far
+    pub mode_transitions: Vec<(f64, FlightMode)>,
+    /// Times of the first and last state handed to the builder, and their number
+    first_sent: Option<Instant>,
+    last_sent: Option<Instant>,
+    sent: usize,
+    /// Whether lines were added since the last update, which need all states handed over again
+    lines_added: bool,
+    /// Number of chunks of states not processed by the builder yet
+    pending: Arc<AtomicUsize>,
+    #[cfg(not(target_arch = "wasm32"))]
+    builder: Sender<Message>,
+    #[cfg(target_arch = "wasm32")]
+    builder: PyramidBuilder,
+}
+
+impl Pyramids {
+    /// Creates the pyramids, starting the builder thread.
+    pub fn new() -> Self {
+        let times = Arc::new(RwLock::new(Vec::new()));
+        let pending = Arc::new(AtomicUsize::new(0));
+        let builder = PyramidBuilder {
+            times: times.clone(),
+            lines: Vec::new(),
+            pending: pending.clone(),
+        };
+
+        // The thread ends once the sender is dropped.
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = {
+            let (sender, receiver) = std::sync::mpsc::channel::<Message>();
+            let mut builder = builder;
+            std::thread::spawn(move || receiver.iter().for_each(|msg| builder.process(msg)));
+            sender
+        };
+
+        Self {
+            times,
+            mode_transitions: Vec::new(),
+            first_sent: None,
+            last_sent: None,
+            sent: 0,
+            lines_added: false,
+            pending,
+            builder,
+        }
+    }
+
+    fn send(&mut self, msg: Message) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.builder.send(msg).is_err() {
+            log::error!("Plot data builder has stopped.");
+        }
+
+        // Without threads, the states are processed right away.
+        #[cfg(target_arch = "wasm32")]
+        self.builder.process(msg);
+    }
+
+    /// Starts handing over the states from the beginning, skipping those
+    /// already processed by the existing lines.
+    fn restart(&mut self) {
+        self.sent = 0;
+        self.last_sent = None;
+        self.mode_transitions.clear();
+    }
+
+    /// Adds a line, returning its pyramid. The line is removed once the pyramid is dropped.
+    pub fn add_line(&mut self, callback: LineCallback) -> Arc<RwLock<LinePyramid>> {
+        let pyramid = Arc::new(RwLock::new(LinePyramid::default()));
+        self.send(Message::AddLine(callback, Arc::downgrade(&pyramid)));
+        // The new line needs all previous states as well. These are handed over
+        // again on the next update, once for all lines added until then.
+        self.lines_added = true;
+        pyramid
+    }
+
+    /// Hands the next states to the builder, a limited number per frame.
+    /// Returns true while the pyramids are catching up with the states.
+    pub fn update(&mut self, states: &[(Instant, VehicleState)]) -> bool {
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+
+        // Start over if the states are not the ones we handed over previously,
+        // e.g. after switching to a different data source.
+        let first = states.first().map(|(t, _)| *t);
+        let previous_last = self.sent.checked_sub(1).and_then(|i| states.get(i)).map(|(t, _)| *t);
+        if (self.first_sent.is_some() && first != self.first_sent) || previous_last != self.last_sent {
+            self.send(Message::Reset);
+            self.restart();
+            self.first_sent = None;
+        } else if std::mem::take(&mut self.lines_added) {
+            self.restart();
+        }
+
+        if self.sent == states.len() || self.pending.load(Ordering::Acquire) >= MAX_PENDING_CHUNKS {
+            return self.pending.load(Ordering::Acquire) > 0;
+        }
+
+        let start = self.sent;
+        let end = usize::min(start + MAX_STATES_PER_FRAME, states.len());
+        let first_time = states[0].0;
+        let chunk: Vec<(f64, VehicleState)> = states[start..end]
+            .iter()
+            .map(|(t, vs)| (t.duration_since(first_time).as_secs_f64(), vs.clone()))
+            .collect();
+
+        for (x, vs) in chunk.iter() {
+            let last_mode = self.mode_transitions.last().map(|(_, mode)| *mode);
+            if let Some(mode) = vs.mode.filter(|mode| Some(*mode) != last_mode) {
+                self.mode_transitions.push((*x, mode));
+            }
+        }
+
+        self.pending.fetch_add(1, Ordering::Release);
+        self.send(Message::States(start, chunk));
+        self.first_sent = Some(first_time);
+        self.last_sent = Some(states[end - 1].0);
+        self.sent = end;
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A noisy sine wave, one sample per time index
+    fn samples(n: usize) -> Vec<(u32, f32)> {
+        (0..n).map(|i| (i as u32, (i as f32 * 0.01).sin() * 100.0 + ((i * 7919) % 13) as f32)).collect()
+    }
+
+    /// x-axis values of the samples, 1 ms apart
+    fn times(n: usize) -> Vec<f64> {
+        (0..n).map(|i| i as f64 / 1000.0).collect()
+    }
+
+    fn pyramid(samples: &[(u32, f32)]) -> LinePyramid {
+        let mut pyramid = LinePyramid::default();
+        pyramid.extend(samples);
+        pyramid
+    }
+
+    fn merged(buckets: &[Bucket]) -> Bucket {
+        buckets.iter().copied().reduce(Bucket::merge).unwrap()
+    }
+
+    #[test]
+    fn incremental_extend_matches_full_build() {
+        let samples = samples(10_000);
+        let full = pyramid(&samples);
+
+        let chunk_sizes: [&[usize]; 5] = [
+            &[1],
+            &[3, 1, 4, 1, 5, 9, 2, 6],
+            &[BUCKET_SIZE],
+            &[BUCKET_SIZE.pow(2) + 1],
+            &[777],
+        ];
+        for sizes in chunk_sizes {
+            let mut incremental = LinePyramid::default();
+            let mut rest = &samples[..];
+            for size in sizes.iter().cycle() {
+                if rest.is_empty() {
+                    break;
+                }
+                let (chunk, remaining) = rest.split_at(usize::min(*size, rest.len()));
+                incremental.extend(chunk);
+                rest = remaining;
+            }
+
+            assert_eq!(incremental.samples, full.samples);
+            assert_eq!(incremental.levels, full.levels, "chunk sizes {:?}", sizes);
+        }
+    }
+
+    #[test]
+    fn levels_summarize_all_samples() {
+        let samples = samples(1_000);
+        let pyramid = pyramid(&samples);
+
+        let mut below_len = samples.len();
+        for level in pyramid.levels.iter() {
+            assert_eq!(level.len(), below_len.div_ceil(BUCKET_SIZE));
+            assert_eq!(level.iter().map(|b| b.count as usize).sum::<usize>(), samples.len());
+            below_len = level.len();
+        }
+
+        let top = pyramid.levels.last().unwrap();
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].min, samples.iter().map(|(_, y)| *y).fold(f32::MAX, f32::min));
+        assert_eq!(top[0].max, samples.iter().map(|(_, y)| *y).fold(f32::MIN, f32::max));
+    }
+
+    #[test]
+    fn view_returns_samples_when_zoomed_in() {
+        let (samples, times) = (samples(10_000), times(10_000));
+        let pyramid = pyramid(&samples);
+
+        let LineView::Samples(view) = pyramid.view(&times, 1.0, 1.1, 1000) else {
+            panic!("expected samples");
+        };
+
+        // One additional sample on either side
+        assert_eq!(view.len(), 103);
+        assert_eq!(view.first().unwrap().first, 999);
+        assert_eq!(view.last().unwrap().first, 1101);
+        assert!(view.iter().zip(&samples[999..1102]).all(|(b, (t, y))| b.first == *t && b.min == *y));
+    }
+
+    #[test]
+    fn view_returns_buckets_when_zoomed_out() {
+        let (samples, times) = (samples(10_000), times(10_000));
+        let pyramid = pyramid(&samples);
+
+        let LineView::Buckets(view) = pyramid.view(&times, 0.0, 10.0, 100) else {
+            panic!("expected buckets");
+        };
+
+        assert!(view.len() <= 100);
+        let (all, top) = (merged(&view), pyramid.levels.last().unwrap()[0]);
+        assert_eq!((all.first, all.last, all.count), (top.first, top.last, top.count));
+        assert_eq!((all.min, all.max), (top.min, top.max));
+    }
+
+    #[test]
+    fn view_buckets_cover_the_range() {
+        let (samples, times) = (samples(10_000), times(10_000));
+        let pyramid = pyramid(&samples);
+
+        let LineView::Buckets(view) = pyramid.view(&times, 2.0, 5.0, 50) else {
+            panic!("expected buckets");
+        };
+
+        assert!(view.len() <= 50);
+        let all = merged(&view);
+        assert!(all.first <= 2000 && all.last >= 5000);
+
+        let in_range = samples[2000..=5000].iter().map(|(_, y)| *y);
+        assert!(all.min <= in_range.clone().fold(f32::MAX, f32::min));
+        assert!(all.max >= in_range.fold(f32::MIN, f32::max));
+    }
+
+    #[test]
+    fn view_of_empty_pyramid() {
+        let pyramid = LinePyramid::default();
+        assert!(pyramid.view(&[], 0.0, 1.0, 100).buckets().is_empty());
+        assert_eq!(pyramid.value_at(&[], 0.0), None);
+    }
+
+    #[test]
+    fn value_at() {
+        let (samples, times) = (samples(100), times(100));
+        let pyramid = pyramid(&samples);
+
+        assert_eq!(pyramid.value_at(&times, -1.0), None);
+        assert_eq!(pyramid.value_at(&times, 0.0105), Some(samples[10].1));
+        assert_eq!(pyramid.value_at(&times, 1.0), Some(samples[99].1));
+    }
+
+    #[test]
+    fn added_lines_get_earlier_states() {
+        let start = Instant::now();
+        let states: Vec<(Instant, VehicleState)> = (0..100)
+            .map(|i| {
+                let vs = VehicleState {
+                    time: i,
+                    ..Default::default()
+                };
+                (start + std::time::Duration::from_millis(i as u64), vs)
+            })
+            .collect();
+
+        let mut pyramids = Pyramids::new();
+        let first = pyramids.add_line(Box::new(|vs| Some(vs.time as f32)));
+        pyramids.update(&states);
+
+        // Lines added together are caught up by handing over the states once.
+        let second = pyramids.add_line(Box::new(|vs| Some(vs.time as f32 * 2.0)));
+        let third = pyramids.add_line(Box::new(|vs| Some(vs.time as f32 * 3.0)));
+        assert_eq!(pyramids.sent, states.len());
+        pyramids.update(&states);
+        assert_eq!(pyramids.sent, states.len());
+        assert!(!pyramids.lines_added);
+
+        while pyramids.update(&states) {
+            std::thread::yield_now();
+        }
+
+        for pyramid in [first, second, third] {
+            assert_eq!(pyramid.read().samples.len(), states.len());
+        }
+    }
+
+    /// Measures the work done per frame for a line of 10M samples, which has
+    /// to stay well within a frame at 60 fps. The pyramid is built in the
+    /// background and only timed for reference. Run with
+    /// `cargo test --release lod -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn view_of_10m_samples_fits_in_a_frame() {
+        const NUM_SAMPLES: usize = 10_000_000;
+        const VIEW_WIDTH: usize = 2000;
+        const NUM_VIEWS: u32 = 100;
+
+        let (samples, times) = (samples(NUM_SAMPLES), times(NUM_SAMPLES));
+
+        let start = Instant::now();
+        let mut pyramid = LinePyramid::default();
+        for chunk in samples.chunks(MAX_STATES_PER_FRAME) {
+            pyramid.extend(chunk);
+        }
+        println!("Built pyramid of {} samples in {:?}", NUM_SAMPLES, start.elapsed());
+
+        // Zooming in from the entire log to a few hundred samples
+        let end = times[NUM_SAMPLES - 1];
+        let start = Instant::now();
+        for i in 0..NUM_VIEWS {
+            let width = end / 1.15f64.powi(i as i32);
+            let view = pyramid.view(&times, (end - width) / 2.0, (end + width) / 2.0, VIEW_WIDTH);
+            assert!(view.buckets().len() <= VIEW_WIDTH);
+        }
+        let per_view = start.elapsed() / NUM_VIEWS;
+        println!("{:?} per view of {} buckets", per_view, VIEW_WIDTH);
+
+        // Leave most of the frame to drawing and the other lines.
+        assert!(per_view.as_secs_f64() < 1.0 / 60.0 / 10.0);
+    }
+}
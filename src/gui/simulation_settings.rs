@@ -1,7 +1,13 @@
 use egui::{DragValue, InnerResponse, Ui};
 
 use crate::gui::windows::archive::ARCHIVE;
+use crate::simulation::faults::*;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::simulation::monte_carlo::MonteCarloSettings;
+use crate::simulation::motor::*;
+use crate::simulation::wind::*;
 use crate::simulation::SimulationSettings;
+
 pub trait SimulationSettingsUiExt {
     fn ui(&mut self, ui: &mut Ui) -> InnerResponse<()>;
 }
@@ -47,6 +53,9 @@ impl SimulationSettingsUiExt for SimulationSettings {
                     ui.add(
                         DragValue::new(&mut self.launch_azimuth).suffix(" °").speed(1.0).clamp_range(-360.0..=359.0),
                     );
+
+                    ui.weak("Rail");
+                    ui.add(DragValue::new(&mut self.rail_length).suffix(" m").speed(0.1).clamp_range(0.0..=50.0));
                 });
                 ui.end_row();
 
@@ -83,15 +92,84 @@ impl SimulationSettingsUiExt for SimulationSettings {
                 });
                 ui.end_row();
 
-                ui.label("Thrust");
+                ui.label("Seed");
                 ui.horizontal(|ui| {
-                    ui.add(DragValue::new(&mut self.thrust).suffix(" m/s²").speed(1.0).clamp_range(10.0..=1000.0));
+                    ui.add(DragValue::new(&mut self.seed).speed(1));
+                    if ui.small_button("🎲").on_hover_text("Pick a new random seed").clicked() {
+                        self.reseed();
+                    }
+                });
+                ui.end_row();
 
-                    ui.weak("for");
-                    ui.add(DragValue::new(&mut self.thrust_duration).suffix(" ms").speed(10).clamp_range(10..=60000));
+                ui.label("Motor");
+                ui.horizontal(|ui| {
+                    let selected = self.motor.as_ref().map(|m| format!("{} {}", m.manufacturer, m.name));
+                    egui::ComboBox::from_id_source("motor")
+                        .selected_text(selected.unwrap_or("Constant Thrust".into()))
+                        .show_ui(ui, |ui| {
+                            if ui.selectable_label(self.motor.is_none(), "Constant Thrust").clicked() {
+                                self.motor = None;
+                            }
+
+                            for (name, contents) in MOTOR_LIBRARY {
+                                if ui.selectable_label(false, name).clicked() {
+                                    self.motor = Motor::parse(contents).map_err(|e| log::error!("{}", e)).ok();
+                                }
+                            }
+                        });
+
+                    #[cfg(target_arch = "x86_64")]
+                    if ui.button("🗁 Load").on_hover_text("Load a RASP (.eng) or RockSim (.rse) file").clicked() {
+                        if let Some(motor) = crate::file::open_motor_file() {
+                            self.motor = Some(motor);
+                        }
+                    }
                 });
                 ui.end_row();
 
+                if let Some(motor) = self.motor.as_mut() {
+                    ui.label("Motor Data");
+                    ui.weak(format!(
+                        "{:.1} Ns, {:.2} s burn time, {:.1} N avg. thrust",
+                        motor.total_impulse(),
+                        motor.burn_time(),
+                        motor.total_impulse() / motor.burn_time()
+                    ));
+                    ui.end_row();
+
+                    ui.label("Mass");
+                    ui.horizontal(|ui| {
+                        ui.weak("Dry");
+                        ui.add(DragValue::new(&mut self.dry_mass).suffix(" kg").speed(0.01).clamp_range(0.01..=1000.0));
+
+                        ui.weak("Motor");
+                        ui.add(
+                            DragValue::new(&mut motor.total_mass).suffix(" kg").speed(0.001).clamp_range(0.001..=500.0),
+                        );
+
+                        ui.weak("Propellant");
+                        let max_propellant = motor.total_mass;
+                        ui.add(
+                            DragValue::new(&mut motor.propellant_mass)
+                                .suffix(" kg")
+                                .speed(0.001)
+                                .clamp_range(0.0..=max_propellant),
+                        );
+                    });
+                    ui.end_row();
+                } else {
+                    ui.label("Thrust");
+                    ui.horizontal(|ui| {
+                        ui.add(DragValue::new(&mut self.thrust).suffix(" m/s²").speed(1.0).clamp_range(10.0..=1000.0));
+
+                        ui.weak("for");
+                        ui.add(
+                            DragValue::new(&mut self.thrust_duration).suffix(" ms").speed(10).clamp_range(10..=60000),
+                        );
+                    });
+                    ui.end_row();
+                }
+
                 ui.label("Drag Terms");
                 ui.horizontal(|ui| {
                     ui.weak("Flight");
@@ -105,31 +183,320 @@ impl SimulationSettingsUiExt for SimulationSettings {
                 });
                 ui.end_row();
 
-                ui.label("Barometer Anomalies");
+                ui.label("Stability");
+                ui.horizontal(|ui| {
+                    ui.weak("Static Margin");
+                    ui.add(DragValue::new(&mut self.static_margin).suffix(" m").speed(0.01).clamp_range(-1.0..=5.0));
+
+                    ui.weak("Normal Force");
+                    ui.add(
+                        DragValue::new(&mut self.normal_force_coefficient)
+                            .suffix(" Ns²/m²")
+                            .speed(0.001)
+                            .clamp_range(0.0..=10.0),
+                    );
+                });
+                ui.end_row();
+
+                ui.label("Moments of Inertia");
+                ui.horizontal(|ui| {
+                    ui.weak("Roll");
+                    ui.add(
+                        DragValue::new(&mut self.inertia_longitudinal)
+                            .suffix(" kg m²")
+                            .speed(0.001)
+                            .clamp_range(0.0001..=100.0),
+                    );
+
+                    ui.weak("Pitch/Yaw");
+                    ui.add(
+                        DragValue::new(&mut self.inertia_lateral)
+                            .suffix(" kg m²")
+                            .speed(0.01)
+                            .clamp_range(0.001..=1000.0),
+                    );
+                });
+                ui.end_row();
+
+                ui.label("Fins");
+                ui.horizontal(|ui| {
+                    ui.weak("Cant");
+                    ui.add(DragValue::new(&mut self.fin_cant).suffix(" °").speed(0.1).clamp_range(-15.0..=15.0));
+
+                    ui.weak("Radius");
+                    ui.add(DragValue::new(&mut self.fin_radius).suffix(" m").speed(0.001).clamp_range(0.001..=2.0));
+                });
+                ui.end_row();
+
+                ui.label("Wind");
                 ui.horizontal(|ui| {
-                    ui.weak("Value of");
+                    egui::ComboBox::from_id_source("wind_profile")
+                        .selected_text(format!("{:?}", self.wind.profile))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.wind.profile, WindProfile::Constant, "Constant");
+                            ui.selectable_value(&mut self.wind.profile, WindProfile::Layered, "Layered");
+                        });
+
+                    ui.add(DragValue::new(&mut self.wind.speed).suffix(" m/s").speed(0.1).clamp_range(0.0..=50.0));
+
+                    ui.weak("from");
+                    ui.add(DragValue::new(&mut self.wind.direction).suffix(" °").speed(1.0).clamp_range(0.0..=359.0));
+                });
+                ui.end_row();
+
+                if self.wind.profile == WindProfile::Layered {
+                    ui.label("Wind Layers");
+                    ui.vertical(|ui| {
+                        let mut remove = None;
+                        for (i, layer) in self.wind.layers.iter_mut().enumerate() {
+                            ui.horizontal(|ui| {
+                                ui.weak("At");
+                                ui.add(
+                                    DragValue::new(&mut layer.altitude)
+                                        .suffix(" m AGL")
+                                        .speed(10.0)
+                                        .clamp_range(1.0..=20000.0),
+                                );
+                                ui.add(
+                                    DragValue::new(&mut layer.speed).suffix(" m/s").speed(0.1).clamp_range(0.0..=100.0),
+                                );
+                                ui.weak("from");
+                                ui.add(
+                                    DragValue::new(&mut layer.direction)
+                                        .suffix(" °")
+                                        .speed(1.0)
+                                        .clamp_range(0.0..=359.0),
+                                );
+                                if ui.small_button("🗑").clicked() {
+                                    remove = Some(i);
+                                }
+                            });
+                        }
+
+                        if let Some(i) = remove {
+                            self.wind.layers.remove(i);
+                        }
+
+                        if ui.small_button("➕ Add Layer").clicked() {
+                            let last = self.wind.layers.last();
+                            let layer = WindLayer {
+                                altitude: last.map(|l| l.altitude + 500.0).unwrap_or(500.0),
+                                speed: last.map(|l| l.speed).unwrap_or(self.wind.speed),
+                                direction: last.map(|l| l.direction).unwrap_or(self.wind.direction),
+                            };
+                            self.wind.layers.push(layer);
+                        }
+                    });
+                    ui.end_row();
+                }
+
+                ui.label("Gusts");
+                ui.horizontal(|ui| {
+                    ui.weak("Std. Dev.");
                     ui.add(
-                        DragValue::new(&mut self.barometer_anomaly_value)
-                            .suffix("m")
+                        DragValue::new(&mut self.wind.gust_intensity).suffix(" m/s").speed(0.1).clamp_range(0.0..=20.0),
+                    );
+
+                    ui.weak("Time Constant");
+                    ui.add(
+                        DragValue::new(&mut self.wind.gust_time_constant)
+                            .suffix(" s")
                             .speed(0.1)
-                            .clamp_range(-10000.0..=20000.0),
+                            .clamp_range(0.1..=60.0),
+                    );
+                });
+                ui.end_row();
+
+                ui.label("GPS");
+                ui.horizontal(|ui| {
+                    ui.add(DragValue::new(&mut self.gps.update_rate).suffix(" Hz").speed(0.1).clamp_range(0.1..=50.0));
+
+                    ui.weak("Latency");
+                    ui.add(DragValue::new(&mut self.gps.latency).suffix(" ms").speed(10).clamp_range(0..=5000));
+
+                    ui.weak("HDOP");
+                    ui.add(DragValue::new(&mut self.gps.hdop).speed(0.01).clamp_range(0.5..=50.0));
+
+                    ui.weak("Std. Dev.");
+                    ui.add(
+                        DragValue::new(&mut self.gps.std_dev_position).suffix(" m").speed(0.1).clamp_range(0.0..=100.0),
+                    );
+                });
+                ui.end_row();
+
+                ui.label("GPS Fix");
+                ui.horizontal(|ui| {
+                    ui.add(DragValue::new(&mut self.gps.num_satellites).suffix(" sats").speed(0.1).clamp_range(4..=30));
+
+                    ui.weak("after");
+                    ui.add(
+                        DragValue::new(&mut self.gps.acquisition_time).suffix(" ms").speed(100).clamp_range(0..=300000),
+                    );
+
+                    ui.weak("lost above");
+                    ui.add(
+                        DragValue::new(&mut self.gps.max_acceleration)
+                            .suffix(" m/s²")
+                            .speed(1.0)
+                            .clamp_range(1.0..=1000.0),
                     );
 
-                    ui.weak("with p=");
+                    ui.weak("for");
                     ui.add(
-                        DragValue::new(&mut self.barometer_anomaly_probability)
-                            .suffix("")
-                            .speed(0.0001)
-                            .clamp_range(0.0..=1.0),
+                        DragValue::new(&mut self.gps.reacquisition_time)
+                            .suffix(" ms")
+                            .speed(100)
+                            .clamp_range(0..=60000),
                     );
+                });
+                ui.end_row();
+
+                ui.label("Telemetry Link");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.link.enabled, "Simulate LoRa");
 
-                    ui.weak("starting after");
+                    ui.weak("GCS at");
                     ui.add(
-                        DragValue::new(&mut self.barometer_anomaly_delay)
-                            .suffix("ms")
-                            .speed(1)
-                            .clamp_range(0.0..=1000000.0),
+                        DragValue::new(&mut self.link.gcs_distance)
+                            .suffix(" m")
+                            .speed(10.0)
+                            .clamp_range(0.0..=100000.0),
                     );
+
+                    ui.weak("towards");
+                    ui.add(DragValue::new(&mut self.link.gcs_bearing).suffix(" °").speed(1.0).clamp_range(0.0..=359.0));
+                });
+                ui.end_row();
+
+                if self.link.enabled {
+                    ui.label("Link Budget");
+                    ui.horizontal(|ui| {
+                        ui.weak("Gain");
+                        ui.add(
+                            DragValue::new(&mut self.link.antenna_gain)
+                                .suffix(" dBi")
+                                .speed(0.1)
+                                .clamp_range(-20.0..=30.0),
+                        );
+
+                        ui.weak("Losses");
+                        ui.add(
+                            DragValue::new(&mut self.link.additional_loss)
+                                .suffix(" dB")
+                                .speed(0.1)
+                                .clamp_range(0.0..=100.0),
+                        );
+
+                        ui.weak("Fading");
+                        ui.add(DragValue::new(&mut self.link.fading).suffix(" dB").speed(0.1).clamp_range(0.0..=20.0));
+
+                        ui.weak("Noise Floor");
+                        ui.add(
+                            DragValue::new(&mut self.link.noise_floor)
+                                .suffix(" dBm")
+                                .speed(0.1)
+                                .clamp_range(-150.0..=-50.0),
+                        );
+                    });
+                    ui.end_row();
+
+                    ui.label("Packet Loss");
+                    ui.horizontal(|ui| {
+                        ui.weak("Min. SNR");
+                        ui.add(
+                            DragValue::new(&mut self.link.min_snr).suffix(" dB").speed(0.1).clamp_range(-30.0..=10.0),
+                        );
+
+                        ui.weak("Random");
+                        ui.add(DragValue::new(&mut self.link.packet_loss).speed(0.001).clamp_range(0.0..=1.0));
+                    });
+                    ui.end_row();
+                }
+
+                ui.label("Sensor Faults");
+                ui.vertical(|ui| {
+                    let mut remove = None;
+                    for (i, fault) in self.faults.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            egui::ComboBox::from_id_source(("fault_sensor", i))
+                                .selected_text(format!("{:?}", fault.sensor))
+                                .show_ui(ui, |ui| {
+                                    for sensor in SENSORS {
+                                        ui.selectable_value(&mut fault.sensor, sensor, format!("{:?}", sensor));
+                                    }
+                                });
+
+                            egui::ComboBox::from_id_source(("fault_kind", i))
+                                .selected_text(fault.kind.name())
+                                .width(100.0)
+                                .show_ui(ui, |ui| {
+                                    for kind in FaultKind::ALL {
+                                        let selected = fault.kind.name() == kind.name();
+                                        if ui.selectable_label(selected, kind.name()).clicked() && !selected {
+                                            fault.kind = kind;
+                                        }
+                                    }
+                                });
+
+                            match &mut fault.kind {
+                                FaultKind::Dropout | FaultKind::Stuck => {}
+                                FaultKind::Saturation { limit } => {
+                                    ui.weak("Limit");
+                                    ui.add(DragValue::new(limit).speed(0.1).clamp_range(0.0..=100000.0));
+                                }
+                                FaultKind::BiasDrift { rate } => {
+                                    ui.weak("Rate");
+                                    ui.add(DragValue::new(rate).suffix("/s").speed(0.01));
+                                }
+                                FaultKind::ScaleError { factor } => {
+                                    ui.weak("Factor");
+                                    ui.add(DragValue::new(factor).speed(0.01).clamp_range(-10.0..=10.0));
+                                }
+                                FaultKind::Spike { probability, magnitude } => {
+                                    ui.weak("p=");
+                                    ui.add(DragValue::new(probability).speed(0.0001).clamp_range(0.0..=1.0));
+                                    ui.weak("Magnitude");
+                                    ui.add(DragValue::new(magnitude).speed(0.1));
+                                }
+                                FaultKind::Anomaly { probability, value } => {
+                                    ui.weak("p=");
+                                    ui.add(DragValue::new(probability).speed(0.0001).clamp_range(0.0..=1.0));
+                                    ui.weak("Value");
+                                    ui.add(DragValue::new(value).speed(0.1));
+                                }
+                            }
+
+                            let start = fault.start;
+                            ui.weak("from");
+                            ui.add(DragValue::new(&mut fault.start).suffix(" ms").speed(100).clamp_range(0..=1000000));
+
+                            let mut ends = fault.end.is_some();
+                            ui.checkbox(&mut ends, "until");
+                            if ends {
+                                let end = fault.end.get_or_insert(start + 1000);
+                                ui.add(DragValue::new(end).suffix(" ms").speed(100).clamp_range(start..=1000000));
+                            } else {
+                                fault.end = None;
+                            }
+
+                            if ui.small_button("🗑").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                    }
+
+                    if let Some(i) = remove {
+                        self.faults.remove(i);
+                    }
+
+                    if ui.small_button("➕ Add Fault").clicked() {
+                        self.faults.push(SensorFault {
+                            sensor: Sensor::Gyroscope,
+                            kind: FaultKind::Dropout,
+                            start: self.sim_start_delay,
+                            end: Some(self.sim_start_delay + 1000),
+                        });
+                    }
                 });
                 ui.end_row();
 
@@ -141,3 +508,60 @@ impl SimulationSettingsUiExt for SimulationSettings {
             })
     }
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MonteCarloSettingsUiExt {
+    fn ui(&mut self, ui: &mut Ui) -> InnerResponse<()>;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl MonteCarloSettingsUiExt for MonteCarloSettings {
+    fn ui(&mut self, ui: &mut Ui) -> InnerResponse<()> {
+        egui::Grid::new("monte_carlo_settings_grid")
+            .num_columns(2)
+            .spacing([40.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Runs");
+                ui.horizontal(|ui| {
+                    ui.add(DragValue::new(&mut self.runs).speed(1).clamp_range(1..=100000));
+
+                    ui.weak("with seeds starting at");
+                    ui.add(DragValue::new(&mut self.seed).speed(1));
+                });
+                ui.end_row();
+
+                let d = &mut self.dispersion;
+
+                ui.label("Launch Std. Dev.");
+                ui.horizontal(|ui| {
+                    ui.weak("Angle");
+                    ui.add(DragValue::new(&mut d.launch_angle).suffix(" °").speed(0.1).clamp_range(0.0..=45.0));
+
+                    ui.weak("Azimuth");
+                    ui.add(DragValue::new(&mut d.launch_azimuth).suffix(" °").speed(0.5).clamp_range(0.0..=180.0));
+                });
+                ui.end_row();
+
+                ui.label("Vehicle Std. Dev.");
+                ui.horizontal(|ui| {
+                    ui.weak("Thrust");
+                    ui.add(DragValue::new(&mut d.thrust).speed(0.001).clamp_range(0.0..=1.0));
+
+                    ui.weak("Drag");
+                    ui.add(DragValue::new(&mut d.drag).speed(0.001).clamp_range(0.0..=1.0));
+                });
+                ui.end_row();
+
+                ui.label("Wind Std. Dev.");
+                ui.horizontal(|ui| {
+                    ui.weak("Speed");
+                    ui.add(DragValue::new(&mut d.wind_speed).suffix(" m/s").speed(0.1).clamp_range(0.0..=20.0));
+
+                    ui.weak("Direction");
+                    ui.add(DragValue::new(&mut d.wind_direction).suffix(" °").speed(0.5).clamp_range(0.0..=180.0));
+                });
+                ui.end_row();
+            })
+    }
+}
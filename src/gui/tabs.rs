@@ -1,7 +1,9 @@
 mod configure;
+mod launch;
 mod plot;
 
 pub use configure::*;
+pub use launch::*;
 pub use plot::*;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
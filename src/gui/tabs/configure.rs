@@ -1,4 +1,4 @@
-use egui::{Align, Button, Color32, Layout, RichText, TextEdit};
+use egui::{Align, Button, Color32, DragValue, Layout, RichText, TextEdit};
 
 use log::*;
 
@@ -140,6 +140,32 @@ impl ConfigureTab {
                             })
                         });
                         ui.end_row();
+
+                        #[cfg(not(target_arch = "wasm32"))]
+                        {
+                            ui.label("Telemetry relay");
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut settings.relay.enabled, "Enabled");
+                                ui.weak("TCP");
+                                ui.add(DragValue::new(&mut settings.relay.tcp_port));
+                                ui.weak("WebSocket");
+                                ui.add(DragValue::new(&mut settings.relay.websocket_port));
+                            });
+                            ui.end_row();
+
+                            ui.label("Relay uplink");
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut settings.relay.allow_uplink, "Allowed from");
+                                ui.add_enabled(
+                                    settings.relay.allow_uplink,
+                                    TextEdit::singleline(&mut settings.relay.uplink_allow_list)
+                                        .hint_text("all clients")
+                                        .desired_width(ui.available_width()),
+                                )
+                                .on_hover_text("Comma-separated list of IP addresses");
+                            });
+                            ui.end_row();
+                        }
                     });
 
                 ui.add_space(20.0);
@@ -0,0 +1,493 @@
+//! Launch tab, containing a pre-flight checklist that is evaluated from the
+//! latest telemetry, and a GO/NO-GO board summarizing it.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use egui::{Align, Color32, DragValue, Layout, RichText, Vec2};
+
+use log::*;
+
+use mithril::telemetry::*;
+
+use crate::data_source::DataSource;
+use crate::settings::{AppSettings, LaunchChecklistSettings};
+
+const GO_COLOR: Color32 = Color32::from_rgb(0x98, 0x97, 0x1a);
+const NO_GO_COLOR: Color32 = Color32::from_rgb(0xcc, 0x24, 0x1d);
+const NO_DATA_COLOR: Color32 = Color32::from_rgb(0xfa, 0xbd, 0x2f);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CheckStatus {
+    Go,
+    NoGo,
+    NoData,
+    /// The last value is too old to be trusted
+    Stale,
+}
+
+impl CheckStatus {
+    fn from_bool(ok: bool) -> Self {
+        if ok {
+            Self::Go
+        } else {
+            Self::NoGo
+        }
+    }
+
+    fn color(&self) -> Color32 {
+        match self {
+            Self::Go => GO_COLOR,
+            Self::NoGo | Self::Stale => NO_GO_COLOR,
+            Self::NoData => NO_DATA_COLOR,
+        }
+    }
+
+    fn text(&self) -> &'static str {
+        match self {
+            Self::Go => "GO",
+            Self::NoGo => "NO-GO",
+            Self::NoData => "NO DATA",
+            Self::Stale => "STALE",
+        }
+    }
+}
+
+/// A single, evaluated checklist item.
+struct ChecklistItem {
+    label: &'static str,
+    requirement: String,
+    value: Option<String>,
+    status: CheckStatus,
+}
+
+impl ChecklistItem {
+    fn numeric(
+        label: &'static str,
+        requirement: String,
+        value: Option<f32>,
+        decimals: usize,
+        nominal: impl Fn(f32) -> bool,
+    ) -> Self {
+        Self {
+            label,
+            requirement,
+            value: value.map(|v| format!("{0:.1$}", v, decimals)),
+            status: value.map(|v| CheckStatus::from_bool(nominal(v))).unwrap_or(CheckStatus::NoData),
+        }
+    }
+
+    /// Marks the item as stale if its value is older than the given maximum age [s].
+    fn max_age(mut self, age: Option<f32>, max_age: f32) -> Self {
+        if let Some(age) = age.filter(|age| *age > max_age) {
+            self.status = CheckStatus::Stale;
+            self.value = self.value.map(|v| format!("{} ({:.0}s ago)", v, age));
+        }
+
+        self
+    }
+}
+
+pub struct LaunchTab {}
+
+impl LaunchTab {
+    pub fn init() -> Self {
+        Self {}
+    }
+
+    /// Returns the last known value for the given callback, and its age [s].
+    /// Live values keep aging after the link is lost, while replayed values
+    /// are aged relative to the end of the data.
+    fn current<T>(data_source: &dyn DataSource, callback: impl Fn(&VehicleState) -> Option<T>) -> Option<(T, f32)> {
+        let now = if data_source.is_live() {
+            Instant::now()
+        } else {
+            data_source.end().unwrap_or(Instant::now())
+        };
+        data_source
+            .vehicle_states()
+            .rev()
+            .find_map(|(t, vs)| callback(vs).map(|v| (v, now.saturating_duration_since(*t).as_secs_f32())))
+    }
+
+    /// Evaluates all enabled checklist items using the latest vehicle states.
+    fn checklist(data_source: &mut dyn DataSource, settings: &AppSettings) -> Vec<ChecklistItem> {
+        let config = &settings.launch_checklist;
+        let max_age = config.max_data_age;
+        let mut items = Vec::new();
+
+        if config.require_gps_fix {
+            let fix = Self::current(data_source, |vs| vs.gps_fix);
+            let item = ChecklistItem {
+                label: "GPS Fix",
+                requirement: "fix".to_string(),
+                value: fix.as_ref().map(|(f, _)| format!("{:?}", f)),
+                status: fix
+                    .as_ref()
+                    .map(|(f, _)| CheckStatus::from_bool(!matches!(f, GPSFixType::NoFix)))
+                    .unwrap_or(CheckStatus::NoData),
+            };
+            items.push(item.max_age(fix.map(|(_, age)| age), max_age));
+        }
+
+        if config.check_satellites {
+            let sats = Self::current(data_source, |vs| vs.num_satellites.map(|n| n as f32));
+            let min = config.min_satellites as f32;
+            let item =
+                ChecklistItem::numeric("# Satellites", format!("≥ {}", min), sats.map(|(n, _)| n), 0, |n| n >= min);
+            items.push(item.max_age(sats.map(|(_, age)| age), max_age));
+        }
+
+        if config.check_hdop {
+            let hdop = Self::current(data_source, |vs| vs.hdop.map(|h| h as f32 / 100.0));
+            let max = config.max_hdop;
+            let item = ChecklistItem::numeric("HDOP", format!("≤ {:.2}", max), hdop.map(|(h, _)| h), 2, |h| h <= max);
+            items.push(item.max_age(hdop.map(|(_, age)| age), max_age));
+        }
+
+        if config.check_battery_voltage {
+            let voltage = Self::current(data_source, |vs| vs.battery_voltage.map(|v| v as f32 / 1000.0));
+            let min = config.min_battery_voltage;
+            let requirement = format!("≥ {:.2}", min);
+            let value = voltage.map(|(v, _)| v);
+            let item = ChecklistItem::numeric("Battery Voltage [V]", requirement, value, 2, |v| v >= min);
+            items.push(item.max_age(voltage.map(|(_, age)| age), max_age));
+        }
+
+        if config.check_arm_voltage {
+            let voltage = Self::current(data_source, |vs| vs.arm_voltage.map(|v| v as f32 / 1000.0));
+            let min = config.min_arm_voltage;
+            let requirement = format!("≥ {:.2}", min);
+            let value = voltage.map(|(v, _)| v);
+            let item = ChecklistItem::numeric("Arm Voltage [V]", requirement, value, 2, |v| v >= min);
+            items.push(item.max_age(voltage.map(|(_, age)| age), max_age));
+        }
+
+        if config.check_flash_space {
+            let flash_pointer = Self::current(data_source, |vs| vs.flash_pointer);
+            let free = flash_pointer.map(|(fp, _)| FLASH_SIZE.saturating_sub(fp) as f32 / 1024.0 / 1024.0);
+            let min = config.min_flash_space;
+            let item = ChecklistItem::numeric("Free Flash [MiB]", format!("≥ {:.2}", min), free, 2, |f| f >= min);
+            items.push(item.max_age(flash_pointer.map(|(_, age)| age), max_age));
+        }
+
+        if config.check_link_quality {
+            let min = config.min_link_quality;
+            let link_quality = data_source.link_quality();
+            items.push(ChecklistItem::numeric("Link Quality [%]", format!("≥ {:.0}", min), link_quality, 1, |lq| {
+                lq >= min
+            }));
+        }
+
+        if config.require_matching_lora_settings {
+            let gcs = &settings.lora;
+            let matching = data_source.fc_settings().map(|fc| {
+                fc.lora.channels == gcs.channels
+                    && fc.lora.binding_phrase == gcs.binding_phrase
+                    && fc.lora.authentication_key == gcs.authentication_key
+            });
+            items.push(ChecklistItem {
+                label: "FC LoRa Settings",
+                requirement: "match GCS".to_string(),
+                value: matching.map(|m| if m { "matching" } else { "mismatch" }.to_string()),
+                status: matching.map(CheckStatus::from_bool).unwrap_or(CheckStatus::NoData),
+            });
+        }
+
+        items
+    }
+
+    /// The overall status is only GO if every single item is GO.
+    fn overall_status(items: &[ChecklistItem]) -> CheckStatus {
+        if items.iter().any(|i| matches!(i.status, CheckStatus::NoGo | CheckStatus::Stale)) {
+            CheckStatus::NoGo
+        } else if items.iter().any(|i| i.status == CheckStatus::NoData) {
+            CheckStatus::NoData
+        } else {
+            CheckStatus::Go
+        }
+    }
+
+    fn checklist_ui(ui: &mut egui::Ui, items: &[ChecklistItem]) {
+        egui::Grid::new("launch_checklist_grid")
+            .num_columns(4)
+            .spacing([40.0, 8.0])
+            .striped(true)
+            .show(ui, |ui| {
+                ui.weak("Item");
+                ui.weak("Value");
+                ui.weak("Requirement");
+                ui.weak("Status");
+                ui.end_row();
+
+                for item in items {
+                    ui.label(item.label);
+                    ui.label(RichText::new(item.value.clone().unwrap_or("N/A".to_string())).strong().monospace());
+                    ui.weak(&item.requirement);
+                    ui.label(RichText::new(item.status.text()).strong().monospace().color(item.status.color()));
+                    ui.end_row();
+                }
+            });
+    }
+
+    fn configuration_ui(ui: &mut egui::Ui, config: &mut LaunchChecklistSettings) {
+        egui::Grid::new("launch_checklist_config_grid")
+            .num_columns(2)
+            .spacing([40.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                ui.checkbox(&mut config.require_gps_fix, "GPS Fix");
+                ui.weak("required");
+                ui.end_row();
+
+                ui.checkbox(&mut config.check_satellites, "# Satellites");
+                ui.horizontal(|ui| {
+                    ui.weak("at least");
+                    ui.add(DragValue::new(&mut config.min_satellites).speed(1).clamp_range(0..=30));
+                });
+                ui.end_row();
+
+                ui.checkbox(&mut config.check_hdop, "HDOP");
+                ui.horizontal(|ui| {
+                    ui.weak("at most");
+                    ui.add(DragValue::new(&mut config.max_hdop).speed(0.01).clamp_range(0.0..=99.0));
+                });
+                ui.end_row();
+
+                ui.checkbox(&mut config.check_battery_voltage, "Battery Voltage");
+                ui.horizontal(|ui| {
+                    ui.weak("at least");
+                    ui.add(
+                        DragValue::new(&mut config.min_battery_voltage)
+                            .suffix(" V")
+                            .speed(0.01)
+                            .clamp_range(0.0..=20.0),
+                    );
+                });
+                ui.end_row();
+
+                ui.checkbox(&mut config.check_arm_voltage, "Arm Voltage");
+                ui.horizontal(|ui| {
+                    ui.weak("at least");
+                    ui.add(
+                        DragValue::new(&mut config.min_arm_voltage).suffix(" V").speed(0.01).clamp_range(0.0..=20.0),
+                    );
+                });
+                ui.end_row();
+
+                ui.checkbox(&mut config.check_flash_space, "Free Flash");
+                ui.horizontal(|ui| {
+                    ui.weak("at least");
+                    ui.add(
+                        DragValue::new(&mut config.min_flash_space).suffix(" MiB").speed(0.1).clamp_range(0.0..=1024.0),
+                    );
+                });
+                ui.end_row();
+
+                ui.checkbox(&mut config.check_link_quality, "Link Quality");
+                ui.horizontal(|ui| {
+                    ui.weak("at least");
+                    ui.add(
+                        DragValue::new(&mut config.min_link_quality).suffix(" %").speed(1.0).clamp_range(0.0..=100.0),
+                    );
+                });
+                ui.end_row();
+
+                ui.checkbox(&mut config.require_matching_lora_settings, "FC LoRa Settings");
+                ui.weak("must match GCS settings");
+                ui.end_row();
+
+                ui.label("Max. Data Age");
+                ui.horizontal(|ui| {
+                    ui.weak("values older than");
+                    ui.add(DragValue::new(&mut config.max_data_age).suffix(" s").speed(0.1).clamp_range(0.5..=600.0));
+                    ui.weak("are NO-GO");
+                });
+                ui.end_row();
+            });
+    }
+
+    fn go_no_go_board(ui: &mut egui::Ui, status: CheckStatus, items: &[ChecklistItem]) {
+        let num_go = items.iter().filter(|i| i.status == CheckStatus::Go).count();
+
+        let (text, color) = match status {
+            CheckStatus::Go => ("GO", GO_COLOR),
+            _ => ("NO-GO", NO_GO_COLOR),
+        };
+
+        let size = Vec2::new(ui.available_width(), 150.0);
+        let (rect, _response) = ui.allocate_exact_size(size, egui::Sense::hover());
+        ui.painter().rect_filled(rect, 10.0, color);
+        ui.painter().text(
+            rect.center(),
+            egui::Align2::CENTER_CENTER,
+            text,
+            egui::FontId::proportional(72.0),
+            Color32::from_rgb(0x28, 0x28, 0x28),
+        );
+
+        ui.add_space(10.0);
+        ui.vertical_centered(|ui| {
+            ui.label(format!("{} of {} checks passed", num_go, items.len()));
+            if status == CheckStatus::NoData {
+                ui.colored_label(NO_DATA_COLOR, "Some items have not been received yet.");
+            }
+        });
+    }
+
+    pub fn main_ui(&mut self, ui: &mut egui::Ui, data_source: &mut dyn DataSource, settings: &mut AppSettings) {
+        let items = Self::checklist(data_source, settings);
+        let status = Self::overall_status(&items);
+
+        ui.horizontal(|ui| {
+            ui.set_width(ui.available_width());
+            ui.set_height(ui.available_height());
+
+            ui.vertical(|ui| {
+                ui.set_width(ui.available_width() / 2.0);
+                ui.set_height(ui.available_height());
+
+                ui.add_space(10.0);
+                ui.heading("Pre-Flight Checklist");
+                ui.add_space(10.0);
+
+                Self::checklist_ui(ui, &items);
+
+                ui.add_space(20.0);
+
+                egui::CollapsingHeader::new("Checklist Configuration").default_open(false).show(ui, |ui| {
+                    Self::configuration_ui(ui, &mut settings.launch_checklist);
+
+                    ui.add_space(10.0);
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        if ui.button("💾 Save Settings").clicked() {
+                            if let Err(e) = settings.save() {
+                                error!("Failed to save settings: {:?}", e);
+                            }
+                        }
+
+                        if ui.button("Reset").clicked() {
+                            settings.launch_checklist = LaunchChecklistSettings::default();
+                        }
+                    });
+                });
+            });
+
+            ui.separator();
+
+            ui.vertical(|ui| {
+                ui.set_width(ui.available_width());
+
+                ui.add_space(10.0);
+                ui.heading("Launch Status");
+                ui.add_space(10.0);
+
+                Self::go_no_go_board(ui, status, &items);
+            });
+        });
+    }
+
+    pub fn bottom_bar_ui(&mut self, ui: &mut egui::Ui, data_source: &mut dyn DataSource, settings: &AppSettings) {
+        let items = Self::checklist(data_source, settings);
+        let status = Self::overall_status(&items);
+        ui.label(RichText::new(status.text()).strong().monospace().color(status.color()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+    use std::slice::Iter;
+    use std::sync::mpsc::SendError;
+    use std::time::Duration;
+
+    use mithril::settings::Settings;
+
+    use super::*;
+
+    /// A live data source whose link was lost a while ago. Like the serial
+    /// data source, its data ends 10s after the last message.
+    struct DeadLink {
+        vehicle_states: Vec<(Instant, VehicleState)>,
+    }
+
+    impl DeadLink {
+        fn new(silent_for: Duration) -> Self {
+            let vs = VehicleState {
+                gps_fix: Some(GPSFixType::AutonomousFix),
+                num_satellites: Some(12),
+                ..Default::default()
+            };
+
+            Self {
+                vehicle_states: vec![(Instant::now() - silent_for, vs)],
+            }
+        }
+    }
+
+    impl DataSource for DeadLink {
+        fn update(&mut self, _ctx: &egui::Context) {}
+
+        fn vehicle_states<'a>(&'a self) -> Iter<'_, (Instant, VehicleState)> {
+            self.vehicle_states.iter()
+        }
+
+        fn fc_settings<'a>(&'a mut self) -> Option<&'a Settings> {
+            None
+        }
+
+        fn fc_settings_mut<'a>(&'a mut self) -> Option<&'a mut Settings> {
+            None
+        }
+
+        fn reset(&mut self) {}
+
+        fn send(&mut self, _msg: UplinkMessage) -> Result<(), SendError<UplinkMessage>> {
+            Ok(())
+        }
+
+        fn send_command(&mut self, _cmd: Command) -> Result<(), SendError<UplinkMessage>> {
+            Ok(())
+        }
+
+        fn end(&self) -> Option<Instant> {
+            self.vehicle_states.last().map(|(t, _)| *t + Duration::from_secs(10))
+        }
+
+        fn is_live(&self) -> bool {
+            true
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn dead_link_values_keep_aging() {
+        let data_source = DeadLink::new(Duration::from_secs(60));
+        let (satellites, age) = LaunchTab::current(&data_source, |vs| vs.num_satellites).unwrap();
+        assert_eq!(satellites, 12);
+        assert!(age >= 60.0, "age {} should not stop at the end of the data", age);
+    }
+
+    #[test]
+    fn dead_link_is_stale() {
+        let mut data_source = DeadLink::new(Duration::from_secs(60));
+        let mut settings = AppSettings::default();
+        settings.launch_checklist.max_data_age = 30.0;
+
+        let items = LaunchTab::checklist(&mut data_source, &settings);
+        for label in ["GPS Fix", "# Satellites"] {
+            let item = items.iter().find(|i| i.label == label).unwrap();
+            assert_eq!(item.status, CheckStatus::Stale, "{}", label);
+        }
+        assert_eq!(LaunchTab::overall_status(&items), CheckStatus::NoGo);
+    }
+}
@@ -1,5 +1,11 @@
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
 
 use egui::Color32;
 use egui::Rect;
@@ -11,7 +17,7 @@ use nalgebra::UnitQuaternion;
 use nalgebra::Vector3;
 
 use crate::data_source::DataSource;
-use crate::settings::AppSettings;
+use crate::settings::{AppSettings, DashboardLayout, DashboardPlot, PlotDefinition};
 
 use crate::gui::map::*;
 use crate::gui::maxi_grid::*;
@@ -30,44 +36,31 @@ const BR: Color32 = Color32::from_rgb(0x61, 0x48, 0x1c);
 const P: Color32 = Color32::from_rgb(0xb1, 0x62, 0x86);
 const C: Color32 = Color32::from_rgb(0x68, 0x9d, 0x6a);
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum SelectedPlot {
-    Orientation,
-    VerticalSpeed,
-    Altitude,
-    Gyroscope,
-    Accelerometers,
-    Magnetometer,
-    Pressures,
-    Temperatures,
-    Power,
-    Runtime,
-    Signal,
-    Map,
-}
-
-impl std::fmt::Display for SelectedPlot {
+impl std::fmt::Display for DashboardPlot {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            SelectedPlot::Orientation    => write!(f, "Orientation"),
-            SelectedPlot::VerticalSpeed  => write!(f, "Vertical Speed & Accel."),
-            SelectedPlot::Altitude       => write!(f, "Altitude"),
-            SelectedPlot::Gyroscope      => write!(f, "Gyroscope"),
-            SelectedPlot::Accelerometers => write!(f, "Accelerometers"),
-            SelectedPlot::Magnetometer   => write!(f, "Magnetometer"),
-            SelectedPlot::Pressures      => write!(f, "Pressures"),
-            SelectedPlot::Temperatures   => write!(f, "Temperatures"),
-            SelectedPlot::Power          => write!(f, "Power"),
-            SelectedPlot::Runtime        => write!(f, "Runtime"),
-            SelectedPlot::Signal         => write!(f, "Signal"),
-            SelectedPlot::Map            => write!(f, "Map"),
+            DashboardPlot::Orientation    => write!(f, "Orientation"),
+            DashboardPlot::VerticalSpeed  => write!(f, "Vertical Speed & Accel."),
+            DashboardPlot::Altitude       => write!(f, "Altitude (ASL)"),
+            DashboardPlot::Gyroscope      => write!(f, "Gyroscope"),
+            DashboardPlot::Accelerometers => write!(f, "Accelerometers"),
+            DashboardPlot::Magnetometer   => write!(f, "Magnetometer"),
+            DashboardPlot::Pressures      => write!(f, "Pressures"),
+            DashboardPlot::Temperatures   => write!(f, "Temperatures"),
+            DashboardPlot::Power          => write!(f, "Power"),
+            DashboardPlot::Runtime        => write!(f, "Runtime"),
+            DashboardPlot::Signal         => write!(f, "Signal"),
+            DashboardPlot::Map            => write!(f, "Position"),
+            DashboardPlot::Custom(id)     => write!(f, "Custom Plot #{}", id),
         }
     }
 }
 
 pub struct PlotTab {
     maxi_grid_state: MaxiGridState,
-    dropdown_selected_plot: SelectedPlot,
+    dropdown_selected_plot: DashboardPlot,
+    layouts: Vec<DashboardLayout>,
+    active_layout: usize,
 
     shared_plot: Rc<RefCell<SharedPlotState>>,
     orientation_plot: PlotState,
@@ -81,6 +74,9 @@ pub struct PlotTab {
     power_plot: PlotState,
     runtime_plot: PlotState,
     signal_plot: PlotState,
+    /// User-defined plots from the settings, with their definitions
+    custom_plot_definitions: Vec<PlotDefinition>,
+    custom_plots: Vec<PlotState>,
 
     map: MapState,
 }
@@ -153,11 +149,15 @@ impl PlotTab {
             .line("HDOP", R, |vs| vs.hdop.map(|x| x as f32 / 100.0))
             .line("# Satellites", G, |vs| vs.num_satellites.map(|x| x as f32));
 
+        let custom_plots = Self::custom_plots(settings, &shared_plot);
+
         let map = MapState::new(settings.mapbox_access_token.clone());
 
         Self {
             maxi_grid_state: MaxiGridState::default(),
-            dropdown_selected_plot: SelectedPlot::Orientation,
+            dropdown_selected_plot: DashboardPlot::Orientation,
+            layouts: Self::layouts(settings),
+            active_layout: 0,
             shared_plot,
             orientation_plot,
             vertical_speed_plot,
@@ -170,10 +170,28 @@ impl PlotTab {
             power_plot,
             runtime_plot,
             signal_plot,
+            custom_plot_definitions: settings.custom_plots.clone(),
+            custom_plots,
             map,
         }
     }
 
+    fn custom_plots(settings: &AppSettings, shared_plot: &Rc<RefCell<SharedPlotState>>) -> Vec<PlotState> {
+        settings
+            .custom_plots
+            .iter()
+            .map(|def| PlotState::from_definition(def, shared_plot.clone()))
+            .collect()
+    }
+
+    fn layouts(settings: &AppSettings) -> Vec<DashboardLayout> {
+        if settings.dashboard_layouts.is_empty() {
+            DashboardLayout::defaults()
+        } else {
+            settings.dashboard_layouts.clone()
+        }
+    }
+
     fn plot_gizmo(
         &mut self,
         ui: &mut egui::Ui,
@@ -229,11 +247,13 @@ impl PlotTab {
         viewport.set_width(ui.available_width());
         viewport.set_height(ui.available_height());
 
-        let orientation = data_source.vehicle_states()
+        // While the measurement cursors are shown, show the orientation at the active one.
+        let states = states_until(data_source, self.cursor_time(data_source));
+        let orientation = states.iter()
             .rev()
             .find_map(|(_, vs)| vs.orientation)
             .unwrap_or(UnitQuaternion::new(Vector3::new(0.0, 0.0, 0.0)));
-        let true_orientation = data_source.vehicle_states()
+        let true_orientation = states.iter()
             .rev()
             .find_map(|(_, vs)| vs.true_orientation);
 
@@ -246,6 +266,29 @@ impl PlotTab {
         self.plot_gizmo(ui, viewport, orientation, (R, G, B));
     }
 
+    fn plot_ui(&mut self, ui: &mut egui::Ui, plot: &DashboardPlot, data_source: &mut dyn DataSource) {
+        match plot {
+            DashboardPlot::Orientation    => self.plot_orientation(ui, data_source),
+            DashboardPlot::VerticalSpeed  => ui.plot_telemetry(&self.vertical_speed_plot, data_source),
+            DashboardPlot::Altitude       => ui.plot_telemetry(&self.altitude_plot, data_source),
+            DashboardPlot::Gyroscope      => ui.plot_telemetry(&self.gyroscope_plot, data_source),
+            DashboardPlot::Accelerometers => ui.plot_telemetry(&self.accelerometer_plot, data_source),
+            DashboardPlot::Magnetometer   => ui.plot_telemetry(&self.magnetometer_plot, data_source),
+            DashboardPlot::Pressures      => ui.plot_telemetry(&self.barometer_plot, data_source),
+            DashboardPlot::Temperatures   => ui.plot_telemetry(&self.temperature_plot, data_source),
+            DashboardPlot::Power          => ui.plot_telemetry(&self.power_plot, data_source),
+            DashboardPlot::Runtime        => ui.plot_telemetry(&self.runtime_plot, data_source),
+            DashboardPlot::Signal         => ui.plot_telemetry(&self.signal_plot, data_source),
+            DashboardPlot::Map            => ui.map(&self.map, data_source),
+            DashboardPlot::Custom(id)     => match self.custom_plot_definitions.iter().position(|p| p.id == *id) {
+                Some(i) => ui.plot_telemetry(&self.custom_plots[i], data_source),
+                None => {
+                    ui.weak("This custom plot has been deleted.");
+                }
+            },
+        }
+    }
+
     pub fn main_ui(&mut self, ui: &mut egui::Ui, data_source: &mut dyn DataSource) {
         #[cfg(feature = "profiling")]
         puffin::profile_function!();
@@ -253,69 +296,136 @@ impl PlotTab {
         self.shared_plot.borrow_mut().set_end(data_source.end());
 
         if ui.available_width() > 1000.0 {
-            MaxiGrid::new((4, 3), ui, self.maxi_grid_state.clone())
-                .cell("Orientation", |ui| self.plot_orientation(ui, data_source))
-                .cell("Vert. Speed & Accel", |ui| ui.plot_telemetry(&self.vertical_speed_plot, data_source))
-                .cell("Altitude (ASL)", |ui| ui.plot_telemetry(&self.altitude_plot, data_source))
-                .cell("Position", |ui| ui.map(&self.map, data_source))
-                .cell("Gyroscope", |ui| ui.plot_telemetry(&self.gyroscope_plot, data_source))
-                .cell("Accelerometers", |ui| ui.plot_telemetry(&self.accelerometer_plot, data_source))
-                .cell("Magnetometer", |ui| ui.plot_telemetry(&self.magnetometer_plot, data_source))
-                .cell("Pressures", |ui| ui.plot_telemetry(&self.barometer_plot, data_source))
-                .cell("Temperature", |ui| ui.plot_telemetry(&self.temperature_plot, data_source))
-                .cell("Power", |ui| ui.plot_telemetry(&self.power_plot, data_source))
-                .cell("Runtime", |ui| ui.plot_telemetry(&self.runtime_plot, data_source))
-                .cell("Signal", |ui| ui.plot_telemetry(&self.signal_plot, data_source));
+            let layout = self.layouts[self.active_layout].clone();
+            let grid = MaxiGrid::new((layout.columns, layout.rows), ui, self.maxi_grid_state.clone());
+            layout.placement().into_iter().fold(grid, |grid, (i, position)| {
+                let cell = &layout.cells[i];
+                let title = cell.plot.title(&self.custom_plot_definitions);
+                grid.spanning_cell(&title, position, cell.span(), |ui| self.plot_ui(ui, &cell.plot, data_source))
+            });
         } else {
             ui.vertical(|ui| {
                 ui.horizontal(|ui| {
                     ui.spacing_mut().combo_width = ui.available_width();
                     egui::ComboBox::from_id_source("plot_selector")
-                        .selected_text(format!("{}", self.dropdown_selected_plot))
+                        .selected_text(self.dropdown_selected_plot.title(&self.custom_plot_definitions))
                         .show_ui(ui, |ui| {
                             ui.set_width(ui.available_width());
-                            for p in [
-                                SelectedPlot::Orientation,
-                                SelectedPlot::VerticalSpeed,
-                                SelectedPlot::Altitude,
-                                SelectedPlot::Gyroscope,
-                                SelectedPlot::Accelerometers,
-                                SelectedPlot::Magnetometer,
-                                SelectedPlot::Pressures,
-                                SelectedPlot::Temperatures,
-                                SelectedPlot::Power,
-                                SelectedPlot::Runtime,
-                                SelectedPlot::Signal,
-                                SelectedPlot::Map
-                            ] {
-                                ui.selectable_value(&mut self.dropdown_selected_plot, p, format!("{}", p));
+                            for p in DashboardPlot::BUILT_IN {
+                                let text = p.to_string();
+                                ui.selectable_value(&mut self.dropdown_selected_plot, p, text);
+                            }
+
+                            if !self.custom_plot_definitions.is_empty() {
+                                ui.separator();
+                            }
+
+                            for plot in self.custom_plot_definitions.iter() {
+                                let p = DashboardPlot::Custom(plot.id);
+                                ui.selectable_value(&mut self.dropdown_selected_plot, p, plot.title.as_str());
                             }
                         });
                 });
 
-                match self.dropdown_selected_plot {
-                    SelectedPlot::Orientation    => self.plot_orientation(ui, data_source),
-                    SelectedPlot::VerticalSpeed  => ui.plot_telemetry(&self.vertical_speed_plot, data_source),
-                    SelectedPlot::Altitude       => ui.plot_telemetry(&self.altitude_plot, data_source),
-                    SelectedPlot::Gyroscope      => ui.plot_telemetry(&self.gyroscope_plot, data_source),
-                    SelectedPlot::Accelerometers => ui.plot_telemetry(&self.accelerometer_plot, data_source),
-                    SelectedPlot::Magnetometer   => ui.plot_telemetry(&self.magnetometer_plot, data_source),
-                    SelectedPlot::Pressures      => ui.plot_telemetry(&self.barometer_plot, data_source),
-                    SelectedPlot::Temperatures   => ui.plot_telemetry(&self.temperature_plot, data_source),
-                    SelectedPlot::Power          => ui.plot_telemetry(&self.power_plot, data_source),
-                    SelectedPlot::Runtime        => ui.plot_telemetry(&self.runtime_plot, data_source),
-                    SelectedPlot::Signal         => ui.plot_telemetry(&self.signal_plot, data_source),
-                    SelectedPlot::Map            => ui.map(&self.map, data_source),
-                }
+                let plot = self.dropdown_selected_plot.clone();
+                self.plot_ui(ui, &plot, data_source);
             });
         }
+
+        // Any requested jumps have been applied by the plots drawn above.
+        self.shared_plot.borrow_mut().jump_to = None;
+    }
+
+    /// Moves all plots to the given flight computer time, for instance that of a log message.
+    pub fn jump_to_fc_time(&mut self, time: u32, data_source: &dyn DataSource) {
+        // GCS messages use the runtime of the ground station, so ignore these
+        let state = data_source.vehicle_states().find(|(_t, vs)| vs.gcs_lora_rssi.is_none() && vs.time >= time);
+        if let Some((t, _vs)) = state {
+            self.shared_plot.borrow_mut().jump_to(plot_time(t, data_source));
+        }
+    }
+
+    /// Time of the active measurement cursor, if the cursors are shown.
+    pub fn cursor_time(&self, data_source: &dyn DataSource) -> Option<Instant> {
+        let shared = self.shared_plot.borrow();
+        let x = shared.cursors?[shared.active_cursor];
+        let (start, _) = data_source.vehicle_states().next()?;
+        Some(*start + Duration::from_secs_f64(x.max(0.0)))
+    }
+
+    /// Switches to the next dashboard layout, wrapping around after the last one.
+    pub fn next_layout(&mut self) {
+        self.select_layout((self.active_layout + 1) % self.layouts.len());
+    }
+
+    fn select_layout(&mut self, index: usize) {
+        if index != self.active_layout {
+            self.active_layout = index;
+            self.maxi_grid_state = MaxiGridState::default();
+        }
     }
 
     pub fn bottom_bar_ui(&mut self, ui: &mut egui::Ui, _data_source: &mut dyn DataSource) {
         ui.toggle_button(&mut self.shared_plot.borrow_mut().show_stats, "📈 Show Stats", "📉 Hide Stats");
+
+        let mut shared = self.shared_plot.borrow_mut();
+        let text = if shared.cursors.is_some() { "📏 Hide Cursors" } else { "📏 Show Cursors" };
+        if ui.button(text).clicked() {
+            shared.toggle_cursors();
+        }
+
+        if let Some([a, b]) = shared.cursors {
+            ui.weak(format!("A: {:.3}s, B: {:.3}s, Δt: {:.3}s", a, b, b - a));
+        }
+        drop(shared);
+
+        let mut selected = self.active_layout;
+        egui::ComboBox::from_id_source("dashboard_layout_selector")
+            .selected_text(&self.layouts[self.active_layout].name)
+            .show_ui(ui, |ui| {
+                for (i, layout) in self.layouts.iter().enumerate() {
+                    ui.selectable_value(&mut selected, i, &layout.name);
+                }
+            })
+            .response
+            .on_hover_text("Dashboard layout, switch with F4");
+        self.select_layout(selected);
+    }
+
+    /// Updates the custom plots whose definitions were changed, keeping the others.
+    pub fn apply_custom_plots(&mut self, settings: &AppSettings) {
+        let mut previous: Vec<(PlotDefinition, PlotState)> =
+            self.custom_plot_definitions.drain(..).zip(self.custom_plots.drain(..)).collect();
+        for definition in settings.custom_plots.iter() {
+            let plot = match previous.iter().position(|(d, _)| d.id == definition.id) {
+                Some(i) => {
+                    let (previous_definition, mut plot) = previous.swap_remove(i);
+                    if previous_definition != *definition {
+                        plot.update_definition(&previous_definition, definition);
+                    }
+                    plot
+                }
+                None => PlotState::from_definition(definition, self.shared_plot.clone()),
+            };
+            self.custom_plots.push(plot);
+        }
+        self.custom_plot_definitions = settings.custom_plots.clone();
+    }
+
+    /// Switches to the given dashboard layouts, leaving the plots themselves untouched.
+    pub fn apply_layouts(&mut self, settings: &AppSettings) {
+        let layouts = Self::layouts(settings);
+        if layouts != self.layouts {
+            // The maximized cell might not exist anymore.
+            self.maxi_grid_state = MaxiGridState::default();
+            self.active_layout = self.active_layout.min(layouts.len() - 1);
+            self.layouts = layouts;
+        }
     }
 
     pub fn apply_settings(&mut self, settings: &AppSettings) {
         self.map.set_access_token(settings.mapbox_access_token.clone());
+        self.apply_custom_plots(settings);
+        self.apply_layouts(settings);
     }
 }
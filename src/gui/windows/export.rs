@@ -0,0 +1,144 @@
+//! A window for exporting the vehicle states of the current data source to
+//! formats usable by other tools.
+
+use chrono::Utc;
+use eframe::egui;
+use egui::{Align, Layout};
+
+use crate::data_source::{DataSource, SimulationDataSource};
+use crate::export::csv::*;
+use crate::export::Track;
+
+pub struct ExportWindow {
+    pub open: bool,
+    /// Which of the available fields to export, in the order of [FIELDS]
+    selected: Vec<bool>,
+    time_base: TimeBase,
+    resample: bool,
+    resample_rate: f64,
+}
+
+impl Default for ExportWindow {
+    fn default() -> Self {
+        Self {
+            open: false,
+            selected: vec![true; FIELDS.len()],
+            time_base: TimeBase::ReceiveTime,
+            resample: false,
+            resample_rate: 10.0,
+        }
+    }
+}
+
+impl ExportWindow {
+    /// Seed of the simulation the data comes from, if any.
+    fn simulation_seed(data_source: &dyn DataSource) -> Option<u64> {
+        data_source.as_any().downcast_ref::<SimulationDataSource>().map(|sim| sim.settings.seed)
+    }
+
+    fn options(&self, data_source: &dyn DataSource) -> CsvOptions {
+        let seed = Self::simulation_seed(data_source);
+        let columns = FIELDS.iter().zip(self.selected.iter()).filter(|(_, s)| **s).map(|(f, _)| f.name.to_string());
+
+        CsvOptions {
+            columns: columns.collect(),
+            time_base: self.time_base,
+            resample_rate: self.resample.then_some(self.resample_rate),
+            metadata: seed.map(|seed| ("seed".into(), seed.to_string())).into_iter().collect(),
+        }
+    }
+
+    /// The GPS track of the current data. Since we usually export data we
+    /// just received, we assume the data ends now.
+    fn track(data_source: &dyn DataSource) -> Track {
+        let name = match Self::simulation_seed(data_source) {
+            Some(seed) => format!("Simulation (Seed {})", seed),
+            None => "Sting FC Track".into(),
+        };
+        let mut track = Track::from_states(name, data_source.vehicle_states().map(|(_, vs)| vs));
+        if let Some((first, last)) = track.time_range() {
+            track.start_time = Utc::now() - chrono::Duration::milliseconds(((last - first) * 1000.0) as i64);
+        }
+        track
+    }
+
+    fn columns_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.weak("Columns");
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                if ui.small_button("None").clicked() {
+                    self.selected.iter_mut().for_each(|s| *s = false);
+                }
+                if ui.small_button("All").clicked() {
+                    self.selected.iter_mut().for_each(|s| *s = true);
+                }
+            });
+        });
+
+        egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+            egui::Grid::new("csv_export_columns").num_columns(3).show(ui, |ui| {
+                for (i, (field, selected)) in FIELDS.iter().zip(self.selected.iter_mut()).enumerate() {
+                    ui.checkbox(selected, field.column_name());
+                    if i % 3 == 2 {
+                        ui.end_row();
+                    }
+                }
+            });
+        });
+    }
+
+    pub fn show_if_open(&mut self, ctx: &egui::Context, data_source: &dyn DataSource) {
+        if !self.open {
+            return;
+        }
+
+        // avoids mutably borrowing self
+        let mut open = self.open;
+
+        egui::Window::new("Export").open(&mut open).min_width(500.0).resizable(false).show(ctx, |ui| {
+            self.columns_ui(ui);
+            ui.separator();
+
+            egui::Grid::new("csv_export_options").num_columns(2).spacing([30.0, 4.0]).show(ui, |ui| {
+                ui.label("Time Base");
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.time_base, TimeBase::ReceiveTime, "Receive Time");
+                    ui.selectable_value(&mut self.time_base, TimeBase::FcTime, "FC Time");
+                });
+                ui.end_row();
+
+                ui.checkbox(&mut self.resample, "Resample");
+                ui.add_enabled(
+                    self.resample,
+                    egui::DragValue::new(&mut self.resample_rate).suffix(" Hz").clamp_range(0.1..=1000.0).speed(0.1),
+                );
+                ui.end_row();
+            });
+
+            ui.add_space(10.0);
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                let rows = data_source.vehicle_states().len();
+
+                #[cfg(target_arch = "x86_64")]
+                {
+                    if ui.add_enabled(rows > 0, egui::Button::new("🌍 Save GPX…")).clicked() {
+                        crate::file::save_gpx_file(&Self::track(data_source));
+                    }
+
+                    if ui.add_enabled(rows > 0, egui::Button::new("🌍 Save KML…")).clicked() {
+                        crate::file::save_kml_file(&Self::track(data_source));
+                    }
+
+                    let enabled = rows > 0 && self.selected.iter().any(|s| *s);
+                    if ui.add_enabled(enabled, egui::Button::new("💾 Save CSV…")).clicked() {
+                        crate::file::save_csv_file(data_source, &self.options(data_source));
+                    }
+                }
+
+                ui.weak(format!("{} samples", rows));
+            });
+        });
+
+        self.open = open;
+    }
+}
@@ -0,0 +1,212 @@
+//! A window for arranging the plots of the plot tab into named layouts.
+
+use eframe::egui;
+use egui::{Align, Align2, Color32, Layout, Rect, Stroke, TextStyle, Vec2};
+
+use crate::settings::{AppSettings, DashboardCell, DashboardLayout, DashboardPlot, PlotDefinition};
+
+/// Largest number of columns and rows of a layout
+const MAX_GRID_SIZE: usize = 8;
+
+enum CellAction {
+    Swap(usize, usize),
+    Remove(usize),
+}
+
+#[derive(Default)]
+pub struct LayoutEditorWindow {
+    pub open: bool,
+    /// Index of the layout being edited
+    selected: usize,
+}
+
+impl LayoutEditorWindow {
+    /// Draws the cells of the layout as they will be arranged in the plot tab.
+    fn preview_ui(ui: &mut egui::Ui, layout: &DashboardLayout, custom_plots: &[PlotDefinition]) {
+        let (response, painter) = ui.allocate_painter(Vec2::new(ui.available_width(), 150.0), egui::Sense::hover());
+        let rect = response.rect;
+        let cell_size = rect.size() / Vec2::new(layout.columns as f32, layout.rows as f32);
+        let stroke = Stroke::new(1.0, ui.visuals().weak_text_color());
+
+        painter.rect_stroke(rect, 0.0, stroke);
+        for (i, (col, row)) in layout.placement() {
+            let cell = &layout.cells[i];
+            let (w, h) = cell.span();
+            let min = rect.min + cell_size * Vec2::new(col as f32, row as f32);
+            let cell_rect = Rect::from_min_size(min, cell_size * Vec2::new(w as f32, h as f32)).shrink(2.0);
+
+            painter.rect_filled(cell_rect, 2.0, ui.visuals().faint_bg_color);
+            painter.rect_stroke(cell_rect, 2.0, stroke);
+            painter.text(
+                cell_rect.center(),
+                Align2::CENTER_CENTER,
+                cell.plot.title(custom_plots),
+                TextStyle::Small.resolve(ui.style()),
+                ui.visuals().text_color(),
+            );
+        }
+    }
+
+    fn plot_selector_ui(ui: &mut egui::Ui, id: usize, plot: &mut DashboardPlot, custom_plots: &[PlotDefinition]) {
+        egui::ComboBox::from_id_source(("dashboard_cell_plot", id))
+            .selected_text(plot.title(custom_plots))
+            .show_ui(ui, |ui| {
+                for p in DashboardPlot::BUILT_IN {
+                    let text = p.to_string();
+                    ui.selectable_value(plot, p, text);
+                }
+
+                if !custom_plots.is_empty() {
+                    ui.separator();
+                }
+
+                for custom_plot in custom_plots {
+                    ui.selectable_value(plot, DashboardPlot::Custom(custom_plot.id), custom_plot.title.as_str());
+                }
+            });
+    }
+
+    fn layout_ui(ui: &mut egui::Ui, layout: &mut DashboardLayout, custom_plots: &[PlotDefinition]) {
+        egui::Grid::new("dashboard_layout_properties").num_columns(2).spacing([10.0, 4.0]).show(ui, |ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut layout.name);
+            ui.end_row();
+
+            ui.label("Grid Size");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut layout.columns).clamp_range(1..=MAX_GRID_SIZE).suffix(" columns"));
+                ui.add(egui::DragValue::new(&mut layout.rows).clamp_range(1..=MAX_GRID_SIZE).suffix(" rows"));
+            });
+            ui.end_row();
+        });
+
+        ui.add_space(10.0);
+        Self::preview_ui(ui, layout, custom_plots);
+        ui.add_space(10.0);
+
+        let placed: Vec<usize> = layout.placement().into_iter().map(|(i, _)| i).collect();
+        let num_cells = layout.cells.len();
+        let (columns, rows) = (layout.columns, layout.rows);
+
+        let mut action = None;
+        egui::Grid::new("dashboard_layout_cells")
+            .num_columns(5)
+            .spacing([10.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                ui.weak("Plot");
+                ui.weak("Columns");
+                ui.weak("Rows");
+                ui.weak("");
+                ui.weak("");
+                ui.end_row();
+
+                for (i, cell) in layout.cells.iter_mut().enumerate() {
+                    Self::plot_selector_ui(ui, i, &mut cell.plot, custom_plots);
+                    ui.add(egui::DragValue::new(&mut cell.column_span).clamp_range(1..=columns));
+                    ui.add(egui::DragValue::new(&mut cell.row_span).clamp_range(1..=rows));
+
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(i > 0, egui::Button::new("⏶").small()).clicked() {
+                            action = Some(CellAction::Swap(i, i - 1));
+                        }
+                        if ui.add_enabled(i + 1 < num_cells, egui::Button::new("⏷").small()).clicked() {
+                            action = Some(CellAction::Swap(i, i + 1));
+                        }
+                        if ui.small_button("🗑").clicked() {
+                            action = Some(CellAction::Remove(i));
+                        }
+                    });
+
+                    if !placed.contains(&i) {
+                        ui.colored_label(Color32::RED, "⚠").on_hover_text("This cell does not fit into the grid.");
+                    }
+                    ui.end_row();
+                }
+            });
+
+        match action {
+            Some(CellAction::Swap(i, j)) => layout.cells.swap(i, j),
+            Some(CellAction::Remove(i)) => {
+                layout.cells.remove(i);
+            }
+            None => {}
+        }
+
+        if ui.button("➕ Add Cell").clicked() {
+            layout.cells.push(DashboardCell::default());
+        }
+    }
+
+    /// Shows the window, returning true if any layouts were changed.
+    pub fn show_if_open(&mut self, ctx: &egui::Context, settings: &mut AppSettings) -> bool {
+        if !self.open {
+            return false;
+        }
+
+        let previous = settings.dashboard_layouts.clone();
+        let custom_plots = settings.custom_plots.clone();
+        let layouts = &mut settings.dashboard_layouts;
+        if layouts.is_empty() {
+            *layouts = DashboardLayout::defaults();
+        }
+        self.selected = self.selected.min(layouts.len() - 1);
+
+        // avoids mutably borrowing self
+        let mut open = self.open;
+        let mut save = false;
+
+        egui::Window::new("Dashboard Layouts").open(&mut open).min_width(500.0).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source("dashboard_layout_editor_selector")
+                    .selected_text(layouts[self.selected].name.as_str())
+                    .show_ui(ui, |ui| {
+                        for (i, layout) in layouts.iter().enumerate() {
+                            ui.selectable_value(&mut self.selected, i, layout.name.as_str());
+                        }
+                    });
+
+                if ui.button("➕ New").clicked() {
+                    layouts.push(DashboardLayout::default());
+                    self.selected = layouts.len() - 1;
+                }
+
+                if ui.button("🗐 Duplicate").clicked() {
+                    let mut layout = layouts[self.selected].clone();
+                    layout.name = format!("{} (Copy)", layout.name);
+                    layouts.push(layout);
+                    self.selected = layouts.len() - 1;
+                }
+
+                if ui.add_enabled(layouts.len() > 1, egui::Button::new("🗑 Delete")).clicked() {
+                    layouts.remove(self.selected);
+                    self.selected = self.selected.saturating_sub(1);
+                }
+            });
+
+            ui.separator();
+            Self::layout_ui(ui, &mut layouts[self.selected], &custom_plots);
+            ui.separator();
+
+            ui.add_space(10.0);
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                save = ui.button("💾 Save Settings").clicked();
+
+                if ui.button("Reset").clicked() {
+                    *layouts = DashboardLayout::defaults();
+                    self.selected = 0;
+                }
+            });
+        });
+
+        self.open = open;
+
+        if save {
+            if let Err(e) = settings.save() {
+                log::error!("Failed to save settings: {:?}", e);
+            }
+        }
+
+        settings.dashboard_layouts != previous
+    }
+}
@@ -1,3 +1,15 @@
 pub mod archive;
+pub mod export;
+pub mod layout_editor;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod monte_carlo;
+pub mod plot_editor;
+pub mod report;
 
 pub use archive::*;
+pub use export::*;
+pub use layout_editor::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use monte_carlo::*;
+pub use plot_editor::*;
+pub use report::*;
@@ -0,0 +1,140 @@
+//! A window for running Monte Carlo dispersion analyses of the current
+//! simulation settings and inspecting the results.
+
+use eframe::egui;
+use egui::{Align, Color32, Layout, ProgressBar, RichText};
+use egui_plot::{Bar, BarChart, Plot};
+
+use crate::data_source::SimulationDataSource;
+use crate::gui::simulation_settings::MonteCarloSettingsUiExt;
+use crate::simulation::monte_carlo::*;
+
+/// Confidence level of the landing ellipse shown in the results and on the map
+pub const LANDING_ELLIPSE_CONFIDENCE: f64 = 0.95;
+
+const HISTOGRAM_BINS: usize = 20;
+
+#[derive(Default)]
+pub struct MonteCarloWindow {
+    pub open: bool,
+}
+
+impl MonteCarloWindow {
+    fn controls_ui(ui: &mut egui::Ui, data_source: &mut SimulationDataSource) {
+        ui.horizontal(|ui| {
+            let running = data_source.monte_carlo.as_ref().map(|mc| !mc.done()).unwrap_or(false);
+            if running {
+                if ui.button("⏹ Cancel").clicked() {
+                    data_source.monte_carlo.as_mut().unwrap().cancel();
+                }
+            } else if ui.button("▶ Start").clicked() {
+                data_source.start_monte_carlo();
+            }
+
+            if let Some(mc) = data_source.monte_carlo.as_ref() {
+                let (done, total) = mc.progress();
+                let progress = ProgressBar::new(done as f32 / total as f32).text(format!("{}/{} runs", done, total));
+                ui.add(progress);
+            }
+        });
+    }
+
+    fn statistics_ui(ui: &mut egui::Ui, mc: &MonteCarlo) {
+        let fmt = |values: &[f32], unit: &str| {
+            mean_std_dev(values)
+                .map(|(mean, std_dev)| format!("{:.1} ± {:.1} {}", mean, std_dev, unit))
+                .unwrap_or("N/A".into())
+        };
+
+        let ellipse = mc.results.landing_ellipse(&mc.settings, LANDING_ELLIPSE_CONFIDENCE);
+        let landed = mc.results.runs.iter().filter(|r| r.landed).count();
+
+        egui::Grid::new("monte_carlo_statistics")
+            .num_columns(2)
+            .spacing([30.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Apogee");
+                ui.label(RichText::new(fmt(&mc.results.apogees(), "m ASL")).monospace());
+                ui.end_row();
+
+                ui.label("Max. Velocity");
+                ui.label(RichText::new(fmt(&mc.results.max_velocities(), "m/s")).monospace());
+                ui.end_row();
+
+                ui.label("Landing Distance");
+                ui.label(RichText::new(fmt(&mc.results.landing_distances(&mc.settings), "m")).monospace());
+                ui.end_row();
+
+                ui.label(format!("Landing Ellipse ({:.0}%)", LANDING_ELLIPSE_CONFIDENCE * 100.0));
+                let axes = ellipse.map(|e| format!("{:.0} m × {:.0} m", 2.0 * e.semi_major, 2.0 * e.semi_minor));
+                ui.label(RichText::new(axes.unwrap_or("N/A".into())).monospace());
+                ui.end_row();
+
+                ui.label("Landed");
+                ui.label(RichText::new(format!("{}/{}", landed, mc.results.runs.len())).monospace());
+                ui.end_row();
+            });
+    }
+
+    fn histogram_ui(ui: &mut egui::Ui, title: &str, values: &[f32], color: Color32) {
+        let bins = histogram(values, HISTOGRAM_BINS);
+        let width = bins.get(1).map(|(b1, _)| b1 - bins[0].0).unwrap_or(1.0) as f64;
+        let bars = bins.into_iter().map(|(x, n)| Bar::new(x as f64, n as f64).width(width)).collect();
+
+        ui.vertical(|ui| {
+            ui.weak(title);
+            Plot::new(title)
+                .height(150.0)
+                .allow_scroll(false)
+                .allow_drag(false)
+                .show(ui, |plot_ui| plot_ui.bar_chart(BarChart::new(bars).color(color)));
+        });
+    }
+
+    fn export_ui(ui: &mut egui::Ui, mc: &MonteCarlo) {
+        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+            if ui.button("🗐 Copy as JSON").clicked() {
+                let ellipse = mc.results.landing_ellipse(&mc.settings, LANDING_ELLIPSE_CONFIDENCE);
+                let json = serde_json::json!({ "landing_ellipse": ellipse, "runs": mc.results.runs });
+                ui.output_mut(|o| o.copied_text = serde_json::to_string_pretty(&json).unwrap_or_default());
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            if ui.button("💾 Save CSV…").clicked() {
+                crate::file::save_monte_carlo_file(&mc.results);
+            }
+        });
+    }
+
+    pub fn show_if_open(&mut self, ctx: &egui::Context, data_source: &mut SimulationDataSource) {
+        if !self.open {
+            return;
+        }
+
+        egui::Window::new("Monte Carlo Analysis").open(&mut self.open).min_width(600.0).show(ctx, |ui| {
+            data_source.monte_carlo_settings.ui(ui);
+            ui.add_space(10.0);
+            Self::controls_ui(ui, data_source);
+
+            let Some(mc) = data_source.monte_carlo.as_ref() else {
+                return;
+            };
+
+            ui.separator();
+            Self::statistics_ui(ui, mc);
+            ui.add_space(10.0);
+
+            ui.columns(3, |columns| {
+                Self::histogram_ui(&mut columns[0], "Apogee [m ASL]", &mc.results.apogees(), Color32::LIGHT_BLUE);
+                let velocities = mc.results.max_velocities();
+                Self::histogram_ui(&mut columns[1], "Max. Velocity [m/s]", &velocities, Color32::LIGHT_RED);
+                let distances = mc.results.landing_distances(&mc.settings);
+                Self::histogram_ui(&mut columns[2], "Landing Distance [m]", &distances, Color32::LIGHT_GREEN);
+            });
+
+            ui.add_space(10.0);
+            Self::export_ui(ui, mc);
+        });
+    }
+}
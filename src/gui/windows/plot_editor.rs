@@ -0,0 +1,287 @@
+//! A window for defining custom plots, with lines given by expressions over
+//! the vehicle state.
+
+use eframe::egui;
+use egui::{Align, Color32, Layout};
+
+use crate::expression::*;
+use crate::settings::{AppSettings, LineDefinition, PlotDefinition};
+
+/// Colors assigned to new lines, in order.
+const LINE_COLORS: [[u8; 3]; 8] = [
+    [0x83, 0xa5, 0x98],
+    [0xfb, 0x49, 0x34],
+    [0xb8, 0xbb, 0x26],
+    [0xfa, 0xbd, 0x2f],
+    [0xb1, 0x62, 0x86],
+    [0x68, 0x9d, 0x6a],
+    [0xd6, 0x5d, 0x0e],
+    [0x45, 0x85, 0x88],
+];
+
+#[derive(Default)]
+pub struct PlotEditorWindow {
+    pub open: bool,
+    /// Index of the plot being edited
+    selected: usize,
+    /// Whether the definitions were changed since the plots were last rebuilt
+    changed: bool,
+}
+
+impl PlotEditorWindow {
+    fn add_line(plot: &mut PlotDefinition, expression: String, name: String) {
+        plot.lines.push(LineDefinition {
+            name,
+            expression,
+            color: LINE_COLORS[plot.lines.len() % LINE_COLORS.len()],
+        });
+    }
+
+    fn limit_ui(ui: &mut egui::Ui, label: &str, limit: &mut Option<f32>) {
+        let mut enabled = limit.is_some();
+        ui.checkbox(&mut enabled, label);
+        match (enabled, limit.as_mut()) {
+            (true, Some(value)) => {
+                ui.add(egui::DragValue::new(value).speed(0.1));
+            }
+            (true, None) => *limit = Some(0.0),
+            (false, _) => *limit = None,
+        }
+    }
+
+    /// A dropdown adding a line for one of the available channels.
+    fn channel_picker_ui(ui: &mut egui::Ui, plot: &mut PlotDefinition) {
+        let mut picked = None;
+        egui::ComboBox::from_id_source("custom_plot_channel")
+            .selected_text("➕ Add Channel")
+            .show_ui(ui, |ui| {
+                for channel in CHANNELS {
+                    let expressions = if channel.is_vector() {
+                        ["x", "y", "z"].iter().map(|c| format!("{}.{}", channel.name, c)).collect()
+                    } else {
+                        vec![channel.name.to_string()]
+                    };
+
+                    for expression in expressions {
+                        if ui.selectable_label(false, &expression).clicked() {
+                            picked = Some((expression, channel.unit));
+                        }
+                    }
+
+                    if channel.is_vector() && ui.selectable_label(false, format!("norm({})", channel.name)).clicked() {
+                        picked = Some((format!("norm({})", channel.name), channel.unit));
+                    }
+                }
+            });
+
+        if let Some((expression, unit)) = picked {
+            let name = if unit.is_empty() {
+                expression.clone()
+            } else {
+                format!("{} [{}]", expression, unit)
+            };
+            Self::add_line(plot, expression, name);
+        }
+    }
+
+    /// Shows the editor for a single plot. Returns true while a text field is
+    /// being edited, unless it holds a valid expression.
+    fn plot_ui(ui: &mut egui::Ui, plot: &mut PlotDefinition) -> bool {
+        let mut editing = false;
+        egui::Grid::new("custom_plot_properties").num_columns(2).spacing([10.0, 4.0]).show(ui, |ui| {
+            ui.label("Title");
+            editing |= ui.text_edit_singleline(&mut plot.title).has_focus();
+            ui.end_row();
+
+            ui.label("Y Limits");
+            ui.horizontal(|ui| {
+                Self::limit_ui(ui, "Min", &mut plot.ymin);
+                Self::limit_ui(ui, "Max", &mut plot.ymax);
+            });
+            ui.end_row();
+        });
+
+        ui.add_space(10.0);
+
+        let mut remove = None;
+        egui::Grid::new("custom_plot_lines")
+            .num_columns(5)
+            .spacing([10.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                ui.weak("");
+                ui.weak("Name");
+                ui.weak("Expression");
+                ui.weak("");
+                ui.weak("");
+                ui.end_row();
+
+                for (i, line) in plot.lines.iter_mut().enumerate() {
+                    ui.color_edit_button_srgb(&mut line.color);
+                    let name = ui.add(
+                        egui::TextEdit::singleline(&mut line.name)
+                            .hint_text(line.expression.as_str())
+                            .desired_width(150.0),
+                    );
+                    let expression =
+                        ui.add(egui::TextEdit::singleline(&mut line.expression).code_editor().desired_width(250.0));
+                    let valid = match Expression::parse(&line.expression) {
+                        Ok(_) => {
+                            ui.colored_label(Color32::GREEN, "✔");
+                            true
+                        }
+                        Err(e) => {
+                            ui.colored_label(Color32::RED, "⚠").on_hover_text(e);
+                            false
+                        }
+                    };
+                    editing |= name.has_focus() || (expression.has_focus() && !valid);
+                    if ui.small_button("🗑").clicked() {
+                        remove = Some(i);
+                    }
+                    ui.end_row();
+                }
+            });
+
+        if let Some(i) = remove {
+            plot.lines.remove(i);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("➕ Add Line").clicked() {
+                Self::add_line(plot, String::new(), String::new());
+            }
+
+            Self::channel_picker_ui(ui, plot);
+        });
+
+        editing
+    }
+
+    fn help_ui(ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Expressions").default_open(false).show(ui, |ui| {
+            ui.label(
+                "Lines are given by expressions such as 'altitude_asl - altitude_ground_asl' or \
+                'norm(accelerometer1)', combining channels and numbers with + - * / and the functions below. \
+                Components of vectors are accessed using .x, .y and .z.",
+            );
+            ui.add_space(5.0);
+
+            egui::Grid::new("custom_plot_help")
+                .num_columns(2)
+                .spacing([30.0, 2.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    for (function, description) in FUNCTIONS {
+                        ui.monospace(*function);
+                        ui.label(*description);
+                        ui.end_row();
+                    }
+
+                    for channel in CHANNELS {
+                        ui.monospace(channel.name);
+                        let kind = if channel.is_vector() { "Vector" } else { "Scalar" };
+                        ui.weak(if channel.unit.is_empty() {
+                            kind.to_string()
+                        } else {
+                            format!("{} [{}]", kind, channel.unit)
+                        });
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+
+    /// Shows the window, returning true if any plot definitions were changed.
+    /// Changes made by typing are only returned once the text field loses
+    /// focus, or once an expression is valid, to avoid rebuilding the plots
+    /// on every keystroke.
+    pub fn show_if_open(&mut self, ctx: &egui::Context, settings: &mut AppSettings) -> bool {
+        if !self.open {
+            return false;
+        }
+
+        let previous = settings.custom_plots.clone();
+        let plots = &mut settings.custom_plots;
+        self.selected = self.selected.min(plots.len().saturating_sub(1));
+
+        // avoids mutably borrowing self
+        let mut open = self.open;
+        let mut save = false;
+        let mut editing = false;
+
+        egui::Window::new("Custom Plots").open(&mut open).min_width(600.0).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let selected_text = plots.get(self.selected).map(|p| p.title.clone()).unwrap_or("None".into());
+                egui::ComboBox::from_id_source("custom_plot_selector")
+                    .selected_text(selected_text)
+                    .show_ui(ui, |ui| {
+                        for (i, plot) in plots.iter().enumerate() {
+                            ui.selectable_value(&mut self.selected, i, plot.title.as_str());
+                        }
+                    });
+
+                if ui.button("➕ New").clicked() {
+                    let id = PlotDefinition::unused_id(plots);
+                    plots.push(PlotDefinition {
+                        id,
+                        ..Default::default()
+                    });
+                    self.selected = plots.len() - 1;
+                }
+
+                if ui.add_enabled(!plots.is_empty(), egui::Button::new("🗑 Delete")).clicked() {
+                    plots.remove(self.selected);
+                    self.selected = self.selected.saturating_sub(1);
+                }
+
+                #[cfg(target_arch = "x86_64")]
+                if ui.button("🗁 Import").clicked() {
+                    if let Some(mut plot) = crate::file::open_plot_file() {
+                        plot.id = PlotDefinition::unused_id(plots);
+                        plots.push(plot);
+                        self.selected = plots.len() - 1;
+                    }
+                }
+
+                #[cfg(target_arch = "x86_64")]
+                if ui.add_enabled(!plots.is_empty(), egui::Button::new("💾 Export")).clicked() {
+                    crate::file::save_plot_file(&plots[self.selected]);
+                }
+            });
+
+            ui.separator();
+
+            match plots.get_mut(self.selected) {
+                Some(plot) => editing = Self::plot_ui(ui, plot),
+                None => {
+                    ui.weak("No custom plots defined yet.");
+                }
+            }
+
+            ui.separator();
+            Self::help_ui(ui);
+
+            ui.add_space(10.0);
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                save = ui.button("💾 Save Settings").clicked();
+            });
+        });
+
+        self.open = open;
+
+        if save {
+            if let Err(e) = settings.save() {
+                log::error!("Failed to save settings: {:?}", e);
+            }
+        }
+
+        self.changed |= settings.custom_plots != previous;
+        if self.changed && !(editing && self.open) {
+            self.changed = false;
+            return true;
+        }
+
+        false
+    }
+}
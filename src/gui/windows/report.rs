@@ -0,0 +1,107 @@
+//! A window showing an automatically generated summary of the current flight.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use eframe::egui;
+use egui::{Align, Layout, RichText};
+
+use crate::analysis::*;
+use crate::data_source::DataSource;
+
+#[derive(Default)]
+pub struct ReportWindow {
+    pub open: bool,
+    /// Summary of the data last analyzed, identified by the number of states and the last time
+    cached: Option<((usize, Option<Instant>), FlightSummary)>,
+}
+
+impl ReportWindow {
+    /// Returns the summary for the current data, only analyzing the data again if it changed.
+    fn summary(&mut self, data_source: &dyn DataSource) -> &FlightSummary {
+        let key = (data_source.vehicle_states().len(), data_source.vehicle_states().last().map(|(t, _)| *t));
+        if self.cached.as_ref().map(|(k, _)| *k != key).unwrap_or(true) {
+            let summary = FlightSummary::from_states(data_source.vehicle_states().map(|(_t, vs)| vs));
+            self.cached = Some((key, summary));
+        }
+
+        &self.cached.as_ref().unwrap().1
+    }
+
+    fn events_ui(ui: &mut egui::Ui, summary: &FlightSummary) {
+        egui::Grid::new("flight_report_events")
+            .num_columns(4)
+            .spacing([30.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                ui.weak("Event");
+                ui.weak("T+ [s]");
+                ui.weak("Altitude [m AGL]");
+                ui.weak("Position");
+                ui.end_row();
+
+                for (name, event) in summary.events() {
+                    let t = summary.time_since_liftoff(event).map(|t| format!("{:.2}", t));
+                    let agl = event.altitude_asl.zip(summary.ground_altitude_asl).map(|(a, g)| format!("{:.1}", a - g));
+                    let position = event.latitude.zip(event.longitude);
+                    let position = position.map(|(lat, lng)| format!("{:.5},{:.5}", lat, lng));
+
+                    ui.label(name);
+                    ui.label(RichText::new(t.unwrap_or("N/A".into())).monospace());
+                    ui.label(RichText::new(agl.unwrap_or("N/A".into())).monospace());
+                    ui.label(RichText::new(position.unwrap_or("N/A".into())).monospace());
+                    ui.end_row();
+                }
+            });
+    }
+
+    fn figures_ui(ui: &mut egui::Ui, summary: &FlightSummary) {
+        egui::Grid::new("flight_report_figures")
+            .num_columns(2)
+            .spacing([30.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                for (name, value) in summary.figures() {
+                    ui.label(name);
+                    ui.label(RichText::new(value.unwrap_or("N/A".into())).strong().monospace());
+                    ui.end_row();
+                }
+            });
+    }
+
+    pub fn show_if_open(&mut self, ctx: &egui::Context, data_source: &dyn DataSource) {
+        if !self.open {
+            return;
+        }
+
+        // avoids mutably borrowing self
+        let mut open = self.open;
+        let summary = self.summary(data_source).clone();
+
+        egui::Window::new("Flight Report")
+            .open(&mut open)
+            .min_width(500.0)
+            .resizable(false)
+            .show(ctx, |ui| {
+                Self::events_ui(ui, &summary);
+                ui.separator();
+                Self::figures_ui(ui, &summary);
+
+                ui.add_space(10.0);
+                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                    if ui.button("🗐 Copy as JSON").clicked() {
+                        let json = serde_json::to_string_pretty(&summary).unwrap_or_default();
+                        ui.output_mut(|o| o.copied_text = json);
+                    }
+
+                    if ui.button("🗐 Copy as Markdown").clicked() {
+                        ui.output_mut(|o| o.copied_text = summary.to_markdown());
+                    }
+                });
+            });
+
+        self.open = open;
+    }
+}
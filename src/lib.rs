@@ -4,8 +4,14 @@
 // allow dead code here to avoid having to place lots of conditions throughout
 // the application to silence warnings.
 #[allow(dead_code)]
+mod analysis;
+#[allow(dead_code)]
 #[allow(unused_variables)]
 pub mod data_source;
+#[allow(dead_code)]
+mod export;
+#[allow(dead_code)]
+mod expression;
 #[allow(unused_imports)]
 mod file;
 #[allow(dead_code)]
@@ -1,11 +1,15 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
+use std::collections::VecDeque;
 use std::fs::File;
-use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
+use std::io::{BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use chrono::{DateTime, FixedOffset, Utc};
 use clap::{Parser, Subcommand};
 use colored::Colorize;
 use crc::{Crc, CRC_16_IBM_SDLC};
@@ -13,14 +17,26 @@ use log::*;
 
 use mithril::telemetry::*;
 
+mod analysis;
 mod data_source;
+mod export;
+mod expression;
 mod file;
 mod gui;
 mod settings;
 mod simulation;
 mod telemetry_ext;
 
+use crate::analysis::FlightSummary;
+use crate::data_source::log_file::LogParser;
 use crate::data_source::serial::{self, *};
+use crate::data_source::{DataSource, LogFileDataSource};
+use crate::export::csv::*;
+use crate::export::gpx::write_gpx;
+use crate::export::kml::write_kml;
+use crate::export::Track;
+use crate::settings::AppSettings;
+use crate::simulation::{SimulationSettings, SimulationState};
 
 #[derive(Debug, Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -29,11 +45,34 @@ struct Cli {
     command: Option<CliCommand>,
 }
 
+/// Output format of the `report` subcommand
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum ReportFormat {
+    Markdown,
+    Json,
+}
+
+/// Output format of the `bin2kml`/`bin2gpx` subcommands
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TrackFormat {
+    Kml,
+    Gpx,
+}
+
+/// Time base of the `bin2csv` subcommand
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum CsvTimeBase {
+    Fc,
+    Receive,
+}
+
 #[derive(Debug, Clone, Subcommand)]
 enum CliCommand {
     /// Launch the main gui [default]
     Gui {
         log_path: Option<PathBuf>,
+        #[clap(short = 's', long, help = "Start a simulation of the given scenario file")]
+        scenario: Option<PathBuf>,
     },
     /// Attach to FC and tail logs
     /// TODO: embassy rewrite will remove USB logging, so this can be removed
@@ -41,6 +80,15 @@ enum CliCommand {
         #[clap(short = 'v')]
         verbose: bool,
     },
+    /// Record telemetry to disk without the GUI, e.g. on a headless computer at the pad
+    Record {
+        #[clap(short = 'o', help = "Directory to store logs in. Default: current directory")]
+        output: Option<PathBuf>,
+        #[clap(short = 'r', default_value_t = 64, help = "Start a new log file after this many MiB, 0 to disable")]
+        rotate_size: u64,
+        #[clap(short = 'q', help = "Don't print the live status line")]
+        quiet: bool,
+    },
     /// Dump the contents of the FC's flash to a file
     /// TODO: rewrite flash dumping stuff after embassy rewrite
     DumpFlash {
@@ -64,12 +112,57 @@ enum CliCommand {
         input: Option<PathBuf>,
         output: Option<PathBuf>,
     },
+    /// Convert a binary flash/telem log to a CSV file with one row per sample
+    #[clap(name = "bin2csv")]
+    Bin2Csv {
+        input: Option<PathBuf>,
+        output: Option<PathBuf>,
+        #[clap(short = 'c', long, value_delimiter = ',', help = "Columns to export, comma-separated. Default: all")]
+        columns: Vec<String>,
+        #[clap(short = 't', long, value_enum, default_value = "receive", help = "Source of the time column")]
+        time_base: CsvTimeBase,
+        #[clap(short = 'r', long, help = "Resample to a fixed rate [Hz], holding the last known values")]
+        resample: Option<f64>,
+        #[clap(short = 'l', long, help = "List all available columns and exit")]
+        list_columns: bool,
+    },
+    /// Convert a binary flash/telem log to a KML file for Google Earth
     #[clap(name = "bin2kml")]
     Bin2Kml {
         input: Option<PathBuf>,
         output: Option<PathBuf>,
         #[clap(short = 'n', help = "Name of the track in the KML file. Default: Sting FC Track")]
         name: Option<String>,
+        #[clap(short = 's', long, help = "Time of the first track point (RFC 3339). Default: derived from file")]
+        start_time: Option<DateTime<FixedOffset>>,
+    },
+    /// Convert a binary flash/telem log to a GPX file
+    #[clap(name = "bin2gpx")]
+    Bin2Gpx {
+        input: Option<PathBuf>,
+        output: Option<PathBuf>,
+        #[clap(short = 'n', help = "Name of the track in the GPX file. Default: Sting FC Track")]
+        name: Option<String>,
+        #[clap(short = 's', long, help = "Time of the first track point (RFC 3339). Default: derived from file")]
+        start_time: Option<DateTime<FixedOffset>>,
+    },
+    /// Detect flight events in a flash/telem log and print a summary of the flight
+    Report {
+        input: Option<PathBuf>,
+        output: Option<PathBuf>,
+        #[clap(short = 'f', long, value_enum, default_value = "markdown", help = "Output format")]
+        format: ReportFormat,
+    },
+    /// Run a simulation without the GUI, write the received telemetry to a log and print a flight summary
+    Simulate {
+        #[clap(short = 's', long, help = "Scenario file. Default: the default simulation settings")]
+        scenario: Option<PathBuf>,
+        #[clap(short = 'o', long, help = "Log file, JSON if *.json, binary otherwise")]
+        out: Option<PathBuf>,
+        #[clap(long, help = "Seed to use instead of the scenario's")]
+        seed: Option<u64>,
+        #[clap(short = 'f', long, value_enum, default_value = "markdown", help = "Summary format")]
+        format: ReportFormat,
     },
     /// Convert a JSON file to a binary flash/telem log
     #[clap(name = "json2bin")]
@@ -101,7 +194,7 @@ fn logcat(verbose: bool) -> Result<(), Box<dyn std::error::Error>> {
     let (downlink_tx, downlink_rx) = channel::<DownlinkMessage>();
     let (_uplink_tx, uplink_rx) = channel::<UplinkMessage>();
     let (serial_status_tx, serial_status_rx) = channel::<(SerialStatus, Option<String>)>();
-    spawn_downlink_monitor(None, serial_status_tx, downlink_tx, uplink_rx, true);
+    spawn_downlink_monitor(None, serial_status_tx, downlink_tx, uplink_rx, None, true);
 
     loop {
         for (status, port) in serial_status_rx.try_iter() {
@@ -140,6 +233,125 @@ fn logcat(verbose: bool) -> Result<(), Box<dyn std::error::Error>> {
     }
 }
 
+/// Comes up with a new, unique path for a recorded telemetry log in the given directory.
+fn new_recording_path(dir: &Path) -> PathBuf {
+    let now: chrono::DateTime<chrono::Utc> = std::time::SystemTime::now().into();
+    dir.join(format!("sam_log_{}.log", now.format("%Y-%m-%dT%H%M%S%.3f")))
+}
+
+fn create_recording(dir: &Path) -> Result<(PathBuf, BufWriter<File>), std::io::Error> {
+    let path = new_recording_path(dir);
+    let file = File::create(&path)?;
+    eprintln!("{} to {}.", "Recording".bright_green().bold(), path.display());
+    Ok((path, BufWriter::new(file)))
+}
+
+fn record(output: Option<PathBuf>, rotate_size: u64, quiet: bool) -> Result<(), Box<dyn std::error::Error>> {
+    const STATUS_INTERVAL: Duration = Duration::from_millis(250);
+
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || r.store(false, Ordering::Relaxed))?;
+
+    // Without the right LoRa settings, the ground station won't receive anything.
+    let lora_settings = AppSettings::load().ok().unwrap_or(AppSettings::default()).lora;
+
+    let (downlink_tx, downlink_rx) = channel::<DownlinkMessage>();
+    let (uplink_tx, uplink_rx) = channel::<UplinkMessage>();
+    let (serial_status_tx, serial_status_rx) = channel::<(SerialStatus, Option<String>)>();
+    spawn_downlink_monitor(None, serial_status_tx, downlink_tx, uplink_rx, None, true);
+
+    let dir = output.unwrap_or(".".into());
+    std::fs::create_dir_all(&dir)?;
+    let (mut path, mut writer) = create_recording(&dir)?;
+    let mut file_size: u64 = 0;
+
+    let mut num_messages: usize = 0;
+    let mut message_receipt_times: VecDeque<Instant> = VecDeque::new();
+    let mut mode: Option<FlightMode> = None;
+    let mut altitude: Option<f32> = None;
+    let mut rssi: Option<f32> = None;
+    let mut data_rate: Option<TelemetryDataRate> = None;
+    let mut last_status = Instant::now();
+
+    while running.load(Ordering::Relaxed) {
+        for (status, port) in serial_status_rx.try_iter() {
+            match (status, port) {
+                (SerialStatus::Connected, Some(p)) => {
+                    eprintln!("\r{} to {}.", "Connected".bright_green().bold(), p);
+                    uplink_tx.send(UplinkMessage::ApplyLoRaSettings(lora_settings.clone()))?;
+                }
+                (SerialStatus::Error, Some(p)) => {
+                    eprintln!("\r{} to {}.", "Connection lost".bright_red().bold(), p)
+                }
+                _ => {}
+            }
+        }
+
+        let first = match downlink_rx.recv_timeout(STATUS_INTERVAL) {
+            Ok(msg) => Some(msg),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        for msg in first.into_iter().chain(downlink_rx.try_iter()) {
+            let serialized = msg.serialize().unwrap_or_default();
+            writer.write_all(&serialized)?;
+            file_size += serialized.len() as u64;
+            num_messages += 1;
+
+            if let DownlinkMessage::TelemetryGCS(..) = msg {
+            } else {
+                message_receipt_times.push_back(Instant::now());
+            }
+
+            let vs: VehicleState = msg.into();
+            mode = vs.mode.or(mode);
+            altitude = vs.altitude_asl.or(altitude);
+            rssi = vs.gcs_lora_rssi.map(|x| x as f32 / -2.0).or(rssi);
+            data_rate = vs.data_rate.or(data_rate);
+        }
+
+        if rotate_size > 0 && file_size >= rotate_size * 1024 * 1024 {
+            writer.flush()?;
+            (path, writer) = create_recording(&dir)?;
+            file_size = 0;
+        }
+
+        if last_status.elapsed() < STATUS_INTERVAL {
+            continue;
+        }
+
+        // Flush regularly, in case we lose power.
+        writer.flush()?;
+        last_status = Instant::now();
+        message_receipt_times.retain(|t| t.elapsed() < Duration::from_millis(1000));
+
+        if !quiet {
+            let lq = link_quality(data_rate, message_receipt_times.len());
+            let mode = format!("{:<14}", mode.map(|m| format!("{:?}", m)).unwrap_or("N/A".into()));
+            let altitude = altitude.map(|a| format!("{:.1}m", a)).unwrap_or("N/A".into());
+            let rssi = rssi.map(|r| format!("{:.1}dBm", r)).unwrap_or("N/A".into());
+            eprint!(
+                "\r{} alt {:>9}  rssi {:>10}  lq {:>3.0}%  msgs {:>8}  {:.2}MiB  ",
+                mode.bold(),
+                altitude,
+                rssi,
+                lq,
+                num_messages,
+                file_size as f32 / 1024.0 / 1024.0
+            );
+            std::io::stderr().flush()?;
+        }
+    }
+
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    eprintln!("\n{} {} messages, last file: {}", "Stopped.".bright_yellow().bold(), num_messages, path.display());
+
+    Ok(())
+}
+
 fn read_flash_chunk(
     uplink_tx: &Sender<UplinkMessage>,
     downlink_rx: &Receiver<DownlinkMessage>,
@@ -180,7 +392,7 @@ fn dump_flash(path: PathBuf, force: bool, raw: bool, start: Option<u32>) -> Resu
     let (downlink_tx, downlink_rx) = channel::<DownlinkMessage>();
     let (uplink_tx, uplink_rx) = channel::<UplinkMessage>();
     let (serial_status_tx, _serial_status_rx) = channel::<(SerialStatus, Option<String>)>();
-    spawn_downlink_monitor(None, serial_status_tx, downlink_tx, uplink_rx, false);
+    spawn_downlink_monitor(None, serial_status_tx, downlink_tx, uplink_rx, None, false);
 
     let flash_size = FLASH_SIZE;
 
@@ -317,87 +529,185 @@ fn bin2json(input: Option<PathBuf>, output: Option<PathBuf>) -> Result<(), Box<d
     let mut buffer = Vec::new();
     input.read_to_end(&mut buffer)?;
 
-    let serialized: Vec<_> = buffer
+    let msgs: Vec<_> = buffer
         .split_mut(|b| *b == 0x00)
         .filter_map(|b| postcard::from_bytes_cobs::<DownlinkMessage>(b).ok())
-        .map(|msg| serde_json::to_string(&msg).unwrap())
         .collect();
 
-    output.write(b"[\n")?;
-    output.write(&serialized.join(",\n").into_bytes())?;
-    output.write(b"\n]\n")?;
+    write_json_log(&mut output, &msgs)
+}
+
+/// Writes messages as a JSON array, with one message per line.
+fn write_json_log(output: &mut impl Write, msgs: &[DownlinkMessage]) -> Result<(), Box<dyn std::error::Error>> {
+    let serialized = msgs.iter().map(serde_json::to_string).collect::<Result<Vec<_>, _>>()?;
+
+    output.write_all(b"[\n")?;
+    output.write_all(serialized.join(",\n").as_bytes())?;
+    output.write_all(b"\n]\n")?;
+
+    Ok(())
+}
+
+fn bin2csv(
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    columns: Vec<String>,
+    time_base: CsvTimeBase,
+    resample_rate: Option<f64>,
+    list_columns: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if list_columns {
+        for field in FIELDS {
+            println!("{:<25} {}", field.name, field.unit);
+        }
+        return Ok(());
+    }
+
+    let mut input = open_file_or_stdin(input)?;
+    let output = create_file_or_stdout(output)?;
+
+    let mut buffer = Vec::new();
+    input.read_to_end(&mut buffer)?;
+
+    let options = CsvOptions {
+        columns,
+        time_base: match time_base {
+            CsvTimeBase::Fc => TimeBase::FcTime,
+            CsvTimeBase::Receive => TimeBase::ReceiveTime,
+        },
+        resample_rate: resample_rate.filter(|r| *r > 0.0),
+        metadata: Vec::new(),
+    };
+
+    let data_source = LogFileDataSource::from_bytes(None, buffer, false);
+    write_csv(BufWriter::new(output), data_source.vehicle_states(), &options)?;
 
     Ok(())
 }
 
-fn bin2kml(
+fn bin2track(
     input: Option<PathBuf>,
     output: Option<PathBuf>,
     name: Option<String>,
+    start_time: Option<DateTime<FixedOffset>>,
+    format: TrackFormat,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    // Logs are written until the end of the flight, so without a given start
+    // time we assume the last message was written at the modification time.
+    let modified = input.as_ref().and_then(|p| std::fs::metadata(p).ok()).and_then(|m| m.modified().ok());
+
+    let states = read_vehicle_states(input)?;
+    let mut output = BufWriter::new(create_file_or_stdout(output)?);
+
+    let mut track = Track::from_states(name.unwrap_or("Sting FC Track".into()), states.iter());
+    track.start_time = match (start_time, modified, track.time_range()) {
+        (Some(start_time), _, _) => start_time.with_timezone(&Utc),
+        (None, Some(modified), Some((first, last))) => {
+            DateTime::<Utc>::from(modified) - chrono::Duration::milliseconds(((last - first) * 1000.0) as i64)
+        }
+        _ => Utc::now(),
+    };
+
+    match format {
+        TrackFormat::Kml => write_kml(&mut output, &track)?,
+        TrackFormat::Gpx => write_gpx(&mut output, &track)?,
+    }
+
+    Ok(())
+}
+
+/// Reads all vehicle states from a binary or JSON log, skipping corrupted messages.
+fn read_vehicle_states(input: Option<PathBuf>) -> Result<Vec<VehicleState>, Box<dyn std::error::Error>> {
     let mut input = open_file_or_stdin(input)?;
-    let mut output = create_file_or_stdout(output)?;
 
     let mut buffer = Vec::new();
     input.read_to_end(&mut buffer)?;
 
-    let coordinates: Vec<String> = buffer
-        .split_mut(|b| *b == 0x00)
-        .filter_map(|b| postcard::from_bytes_cobs::<DownlinkMessage>(b).ok())
+    let mut parser = LogParser::new(None);
+    let msgs = parser.push(&buffer);
+    parser.finish();
+
+    if parser.stats.bad_frames > 0 {
+        warn!("Skipped {} corrupted messages ({} bytes).", parser.stats.bad_frames, parser.stats.skipped_bytes);
+    }
+
+    let states = msgs
+        .into_iter()
+        .filter(|msg| !matches!(msg, DownlinkMessage::Log(..) | DownlinkMessage::Settings(..)))
         .map(|msg| msg.into())
-        .filter(|vs: &VehicleState| vs.longitude.is_some() && vs.latitude.is_some() && vs.altitude_gps_asl.is_some())
-        .map(|vs| (vs.longitude.unwrap(), vs.latitude.unwrap(), vs.altitude_gps_asl.unwrap()))
-        .map(|(ln, lt, alt)| format!("     {},{},{}", ln, lt, alt + 100.0))
         .collect();
 
-    let name = name.unwrap_or("Sting FC Track".into());
-
-    let xml = format!(
-        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
-<kml xmlns=\"http://www.opengis.net/kml/2.2\">
- <Document>
-  <name>{name}</name>
-  <description><![CDATA[{name}
-{len} Trackpoints + 0 Placemarks]]></description>
-  <Style id=\"TrackStyle\">
-   <LineStyle>
-    <color>ff00ff00</color>
-    <width>3</width>
-   </LineStyle>
-   <PolyStyle>
-    <color>7f0000ff</color>
-   </PolyStyle>
-   <BalloonStyle>
-    <text></text>
-   </BalloonStyle>
-  </Style>
-
-  <Placemark id=\"sting_fc\">
-   <name>Sting FC</name>
-   <description></description>
-   <styleUrl>#TrackStyle</styleUrl>
-   <LineString>
-    <extrude>0</extrude>
-    <tessellate>0</tessellate>
-    <altitudeMode>clampToGround</altitudeMode>
-    <coordinates>
-     {coords}
-    </coordinates>
-   </LineString>
-  </Placemark>
-
- </Document>
-</kml>",
-        name = name,
-        len = coordinates.len(),
-        coords = coordinates.join("\n")
-    );
+    Ok(states)
+}
 
-    output.write(xml.as_bytes())?;
+fn report(
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    format: ReportFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let states = read_vehicle_states(input)?;
+    let mut output = create_file_or_stdout(output)?;
+
+    write_summary(&mut output, &FlightSummary::from_states(states.iter()), format)
+}
+
+fn write_summary(
+    output: &mut impl Write,
+    summary: &FlightSummary,
+    format: ReportFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        ReportFormat::Markdown => output.write_all(summary.to_markdown().as_bytes())?,
+        ReportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *output, summary)?;
+            output.write_all(b"\n")?;
+        }
+    }
 
     Ok(())
 }
 
+fn simulate(
+    scenario: Option<PathBuf>,
+    out: Option<PathBuf>,
+    seed: Option<u64>,
+    format: ReportFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut settings = match scenario {
+        Some(path) => SimulationSettings::from_scenario(&std::fs::read_to_string(path)?)?,
+        None => SimulationSettings::default(),
+    };
+    settings.seed = seed.unwrap_or(settings.seed);
+
+    let start = Instant::now();
+    let mut state = SimulationState::initialize(&settings);
+    let mut msgs = Vec::new();
+    while !state.tick() {
+        msgs.extend(state.telemetry_messages());
+    }
+    info!("Simulated {} messages with seed {} in {:.2?}.", msgs.len(), settings.seed, start.elapsed());
+
+    if let Some(path) = out {
+        let json = path.extension().map(|ext| ext == "json").unwrap_or(false);
+        let mut output = BufWriter::new(File::create(path)?);
+        if json {
+            write_json_log(&mut output, &msgs)?;
+        } else {
+            for msg in &msgs {
+                output.write_all(&msg.serialize().unwrap_or_default())?;
+            }
+        }
+        output.flush()?;
+    }
+
+    let states: Vec<VehicleState> = msgs.into_iter().map(|msg| msg.into()).collect();
+    let summary = FlightSummary {
+        simulation_seed: Some(settings.seed),
+        ..FlightSummary::from_states(states.iter())
+    };
+    write_summary(&mut std::io::stdout().lock(), &summary, format)
+}
+
 fn json2bin(input: Option<PathBuf>, output: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
     let input = open_file_or_stdin(input)?;
     let mut output = create_file_or_stdout(output)?;
@@ -414,9 +724,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::Builder::new().filter_level(LevelFilter::Info).parse_default_env().init();
 
     let args = Cli::parse();
-    match args.command.unwrap_or(CliCommand::Gui { log_path: None }) {
-        CliCommand::Gui { log_path } => gui::main(log_path),
+    match args.command.unwrap_or(CliCommand::Gui {
+        log_path: None,
+        scenario: None,
+    }) {
+        CliCommand::Gui { log_path, scenario } => gui::main(log_path, scenario),
         CliCommand::Logcat { verbose } => logcat(verbose),
+        CliCommand::Record {
+            output,
+            rotate_size,
+            quiet,
+        } => record(output, rotate_size, quiet),
         CliCommand::DumpFlash {
             path,
             force,
@@ -425,7 +743,33 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         } => dump_flash(path, force, raw, start),
         CliCommand::ExtractFlashLogs { path } => extract_flash_logs(path),
         CliCommand::Bin2Json { input, output } => bin2json(input, output),
-        CliCommand::Bin2Kml { input, output, name } => bin2kml(input, output, name),
+        CliCommand::Bin2Csv {
+            input,
+            output,
+            columns,
+            time_base,
+            resample,
+            list_columns,
+        } => bin2csv(input, output, columns, time_base, resample, list_columns),
+        CliCommand::Bin2Kml {
+            input,
+            output,
+            name,
+            start_time,
+        } => bin2track(input, output, name, start_time, TrackFormat::Kml),
+        CliCommand::Bin2Gpx {
+            input,
+            output,
+            name,
+            start_time,
+        } => bin2track(input, output, name, start_time, TrackFormat::Gpx),
+        CliCommand::Report { input, output, format } => report(input, output, format),
+        CliCommand::Simulate {
+            scenario,
+            out,
+            seed,
+            format,
+        } => simulate(scenario, out, seed, format),
         CliCommand::Json2Bin { input, output } => json2bin(input, output),
         CliCommand::Reboot => reboot(false),
         CliCommand::Bootloader => reboot(true),
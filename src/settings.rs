@@ -1,13 +1,354 @@
 use std::fs::File;
+use std::net::IpAddr;
 
 use serde::{Deserialize, Serialize};
 
 use mithril::settings::LoRaSettings;
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppSettings {
     pub mapbox_access_token: String,
     pub lora: LoRaSettings,
+    #[serde(default)]
+    pub launch_checklist: LaunchChecklistSettings,
+    #[serde(default)]
+    pub relay: RelaySettings,
+    /// Address of the relay we last connected to as a client
+    #[serde(default)]
+    pub remote_relay_address: String,
+    #[serde(default)]
+    pub custom_plots: Vec<PlotDefinition>,
+    #[serde(default = "DashboardLayout::defaults")]
+    pub dashboard_layouts: Vec<DashboardLayout>,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            mapbox_access_token: String::new(),
+            lora: LoRaSettings::default(),
+            launch_checklist: LaunchChecklistSettings::default(),
+            relay: RelaySettings::default(),
+            remote_relay_address: String::new(),
+            custom_plots: Vec::new(),
+            dashboard_layouts: DashboardLayout::defaults(),
+        }
+    }
+}
+
+/// Configuration of the pre-flight checklist shown in the launch tab. Each
+/// item can be disabled, in which case it is ignored for the GO/NO-GO decision.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LaunchChecklistSettings {
+    pub require_gps_fix: bool,
+    pub check_satellites: bool,
+    pub min_satellites: u8,
+    pub check_hdop: bool,
+    pub max_hdop: f32,
+    pub check_battery_voltage: bool,
+    pub min_battery_voltage: f32,
+    pub check_arm_voltage: bool,
+    pub min_arm_voltage: f32,
+    pub check_flash_space: bool,
+    pub min_flash_space: f32,
+    pub check_link_quality: bool,
+    pub min_link_quality: f32,
+    pub require_matching_lora_settings: bool,
+    /// Values older than this are considered stale [s]
+    pub max_data_age: f32,
+}
+
+impl Default for LaunchChecklistSettings {
+    fn default() -> Self {
+        Self {
+            require_gps_fix: true,
+            check_satellites: true,
+            min_satellites: 6,
+            check_hdop: true,
+            max_hdop: 2.5,
+            check_battery_voltage: true,
+            min_battery_voltage: 7.6,
+            check_arm_voltage: true,
+            min_arm_voltage: 7.6,
+            check_flash_space: true,
+            min_flash_space: 4.0,
+            check_link_quality: true,
+            min_link_quality: 80.0,
+            require_matching_lora_settings: true,
+            max_data_age: 5.0,
+        }
+    }
+}
+
+/// Settings for relaying the telemetry received by this ground station to
+/// other GCS instances on the network.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelaySettings {
+    pub enabled: bool,
+    pub tcp_port: u16,
+    pub websocket_port: u16,
+    /// Forward uplink messages received from relay clients to the vehicle.
+    pub allow_uplink: bool,
+    /// Comma-separated list of addresses allowed to send uplink messages.
+    /// If empty, all clients are allowed, as long as `allow_uplink` is set.
+    pub uplink_allow_list: String,
+}
+
+impl Default for RelaySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tcp_port: 18400,
+            websocket_port: 18401,
+            allow_uplink: false,
+            uplink_allow_list: String::new(),
+        }
+    }
+}
+
+impl RelaySettings {
+    /// Whether uplink messages from the given client address should be forwarded.
+    pub fn uplink_allowed(&self, addr: IpAddr) -> bool {
+        let allow_list: Vec<&str> =
+            self.uplink_allow_list.split(',').map(|a| a.trim()).filter(|a| !a.is_empty()).collect();
+        let allowed = allow_list.is_empty() || allow_list.iter().any(|a| a.parse::<IpAddr>().ok() == Some(addr));
+        self.allow_uplink && allowed
+    }
+}
+
+/// A user-defined plot. Each line is given by an expression over the vehicle
+/// state, see [crate::expression].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlotDefinition {
+    /// Referenced by dashboard layouts, so the plot can be renamed
+    pub id: u64,
+    pub title: String,
+    pub lines: Vec<LineDefinition>,
+    pub ymin: Option<f32>,
+    pub ymax: Option<f32>,
+}
+
+impl Default for PlotDefinition {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            title: "Custom Plot".to_string(),
+            lines: Vec::new(),
+            ymin: None,
+            ymax: None,
+        }
+    }
+}
+
+impl PlotDefinition {
+    /// An id not used by any of the given plots.
+    pub fn unused_id(plots: &[PlotDefinition]) -> u64 {
+        plots.iter().map(|p| p.id + 1).max().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LineDefinition {
+    pub name: String,
+    pub expression: String,
+    pub color: [u8; 3],
+}
+
+impl Default for LineDefinition {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            expression: String::new(),
+            color: [0x83, 0xa5, 0x98],
+        }
+    }
+}
+
+/// Contents of a cell of the plot tab's grid. Custom plots are referenced by id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DashboardPlot {
+    Orientation,
+    VerticalSpeed,
+    Altitude,
+    Gyroscope,
+    Accelerometers,
+    Magnetometer,
+    Pressures,
+    Temperatures,
+    Power,
+    Runtime,
+    Signal,
+    Map,
+    Custom(u64),
+}
+
+impl DashboardPlot {
+    pub const BUILT_IN: [DashboardPlot; 12] = [
+        DashboardPlot::Orientation,
+        DashboardPlot::VerticalSpeed,
+        DashboardPlot::Altitude,
+        DashboardPlot::Map,
+        DashboardPlot::Gyroscope,
+        DashboardPlot::Accelerometers,
+        DashboardPlot::Magnetometer,
+        DashboardPlot::Pressures,
+        DashboardPlot::Temperatures,
+        DashboardPlot::Power,
+        DashboardPlot::Runtime,
+        DashboardPlot::Signal,
+    ];
+
+    /// Title of the plot, looking up custom plots by id.
+    pub fn title(&self, custom_plots: &[PlotDefinition]) -> String {
+        match self {
+            DashboardPlot::Custom(id) => custom_plots.iter().find(|p| p.id == *id).map(|p| p.title.clone()),
+            _ => None,
+        }
+        .unwrap_or_else(|| self.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DashboardCell {
+    pub plot: DashboardPlot,
+    pub column_span: usize,
+    pub row_span: usize,
+}
+
+impl Default for DashboardCell {
+    fn default() -> Self {
+        Self {
+            plot: DashboardPlot::Orientation,
+            column_span: 1,
+            row_span: 1,
+        }
+    }
+}
+
+impl DashboardCell {
+    fn new(plot: DashboardPlot, column_span: usize, row_span: usize) -> Self {
+        Self {
+            plot,
+            column_span,
+            row_span,
+        }
+    }
+
+    /// Number of columns and rows covered by the cell, at least one each.
+    pub fn span(&self) -> (usize, usize) {
+        (self.column_span.max(1), self.row_span.max(1))
+    }
+}
+
+/// A named arrangement of plots in the plot tab. Cells are placed in order,
+/// row by row, each at the first position it fits in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DashboardLayout {
+    pub name: String,
+    pub columns: usize,
+    pub rows: usize,
+    pub cells: Vec<DashboardCell>,
+}
+
+impl Default for DashboardLayout {
+    fn default() -> Self {
+        Self {
+            name: "Layout".to_string(),
+            columns: 2,
+            rows: 2,
+            cells: Vec::new(),
+        }
+    }
+}
+
+impl DashboardLayout {
+    /// The layouts available before the user defines their own.
+    pub fn defaults() -> Vec<Self> {
+        use DashboardPlot::*;
+
+        let layout = |name: &str, (columns, rows), cells: Vec<(DashboardPlot, usize, usize)>| Self {
+            name: name.to_string(),
+            columns,
+            rows,
+            cells: cells.into_iter().map(|(plot, c, r)| DashboardCell::new(plot, c, r)).collect(),
+        };
+
+        vec![
+            Self {
+                name: "Default".to_string(),
+                columns: 4,
+                rows: 3,
+                cells: DashboardPlot::BUILT_IN.into_iter().map(|p| DashboardCell::new(p, 1, 1)).collect(),
+            },
+            layout(
+                "Pad",
+                (3, 2),
+                vec![
+                    (Map, 1, 2),
+                    (Power, 1, 1),
+                    (Signal, 1, 1),
+                    (Orientation, 1, 1),
+                    (Temperatures, 1, 1),
+                ],
+            ),
+            layout(
+                "Ascent",
+                (3, 2),
+                vec![
+                    (Altitude, 2, 1),
+                    (VerticalSpeed, 1, 1),
+                    (Orientation, 1, 1),
+                    (Accelerometers, 1, 1),
+                    (Signal, 1, 1),
+                ],
+            ),
+            layout("Recovery", (2, 2), vec![(Map, 1, 2), (Altitude, 1, 1), (Signal, 1, 1)]),
+            layout(
+                "Bench test",
+                (4, 2),
+                vec![
+                    (Gyroscope, 1, 1),
+                    (Accelerometers, 1, 1),
+                    (Magnetometer, 1, 1),
+                    (Pressures, 1, 1),
+                    (Temperatures, 1, 1),
+                    (Power, 1, 1),
+                    (Runtime, 1, 1),
+                    (Signal, 1, 1),
+                ],
+            ),
+        ]
+    }
+
+    /// Assigns each cell a position in the grid, given as the column and row
+    /// of its top left corner. Cells that don't fit anymore are left out.
+    pub fn placement(&self) -> Vec<(usize, (usize, usize))> {
+        let mut occupied = vec![vec![false; self.columns]; self.rows];
+        let mut placed = Vec::new();
+
+        for (i, cell) in self.cells.iter().enumerate() {
+            let (w, h) = cell.span();
+            let position = (0..self.rows)
+                .flat_map(|row| (0..self.columns).map(move |col| (col, row)))
+                .filter(|(col, row)| col + w <= self.columns && row + h <= self.rows)
+                .find(|(col, row)| (*row..(row + h)).all(|r| (*col..(col + w)).all(|c| !occupied[r][c])));
+
+            if let Some((col, row)) = position {
+                for occupied_row in occupied.iter_mut().skip(row).take(h) {
+                    occupied_row[col..(col + w)].fill(true);
+                }
+                placed.push((i, (col, row)));
+            }
+        }
+
+        placed
+    }
 }
 
 impl AppSettings {
@@ -25,7 +366,8 @@ impl AppSettings {
         }
 
         let f = File::open(config_dir.join("config.json"))?;
-        let config = serde_json::from_reader(f)?;
+        let mut config: Self = serde_json::from_reader(f)?;
+        config.deduplicate_plot_ids();
         Ok(config)
     }
 
@@ -46,4 +388,13 @@ impl AppSettings {
         serde_json::to_writer_pretty(f, self)?;
         Ok(())
     }
+
+    /// Gives custom plots sharing an id, e.g. in a hand-edited config, new ones.
+    fn deduplicate_plot_ids(&mut self) {
+        for i in 1..self.custom_plots.len() {
+            if self.custom_plots[..i].iter().any(|p| p.id == self.custom_plots[i].id) {
+                self.custom_plots[i].id = PlotDefinition::unused_id(&self.custom_plots);
+            }
+        }
+    }
 }
@@ -3,8 +3,10 @@ use std::f32::consts::PI;
 
 use nalgebra::{UnitQuaternion, Vector3};
 use rand::distributions::Distribution;
-use rand::RngCore;
+#[cfg(not(target_arch = "wasm32"))]
+use rand::Rng as _;
 use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
 
 use mithril::settings::*;
 use mithril::state_estimation::*;
@@ -12,44 +14,99 @@ use mithril::telemetry::*;
 
 use crate::gui::windows::archive::ARCHIVE;
 
+pub mod atmosphere;
+pub mod faults;
+pub mod gps;
+pub mod link;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod monte_carlo;
+pub mod motor;
+pub mod wind;
+
+use atmosphere::*;
+use faults::{FaultInjector, Sensor, SensorFault};
+use gps::*;
+use link::*;
+use motor::*;
+use wind::*;
+
 #[cfg(not(target_arch = "wasm32"))]
 type Rng = rand::rngs::StdRng;
 #[cfg(target_arch = "wasm32")]
 type Rng = rand::rngs::SmallRng;
 
 const GRAVITY: f32 = 9.80665;
+/// Rotational damping while hanging under a parachute [N m s]
+const RECOVERY_ROTATION_DAMPING: f32 = 0.5;
 
 pub const SIMULATION_TICK_MS: u32 = 1;
 pub const PLOT_STEP_MS: u32 = 50;
 
-#[derive(Clone, Debug, PartialEq)]
+/// Example scenarios bundled with the application, as (name, JSON contents).
+pub const SCENARIO_LIBRARY: [(&str, &str); 3] = [
+    ("AeroTech H128W Test Flight", include_str!("../assets/scenarios/h128w_test_flight.json")),
+    ("Windy Day", include_str!("../assets/scenarios/windy_day.json")),
+    ("Barometer Failure", include_str!("../assets/scenarios/barometer_failure.json")),
+];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SimulationSettings {
     pub replication_log_index: Option<usize>, // TODO: represent this better than a simple index
+    /// Seed for all random processes, making runs with the same settings reproducible
+    pub seed: u64,
 
     pub altitude_ground: f32,
     pub launch_angle: f32,
     pub launch_azimuth: f32,
     pub launch_latitude: f64,
     pub launch_longitude: f64,
+    /// Height above ground at which the vehicle leaves the launch rail [m]
+    pub rail_length: f32,
 
     pub sim_duration: u32,
     pub sim_start_delay: u32,
+    /// Duration of the constant thrust, if no motor is given [ms]
     pub thrust_duration: u32,
+    /// Constant thrust acceleration, if no motor is given [m/s²]
     pub thrust: f32,
+    /// Motor with a thrust curve, replacing the constant thrust
+    pub motor: Option<Motor>,
+    /// Mass of the vehicle without the motor [kg]
+    pub dry_mass: f32,
 
     pub drag_flight: f32,
     pub drag_drogue: f32,
     pub drag_main: f32,
 
+    /// Moment of inertia around the longitudinal (roll) axis [kg m²]
+    pub inertia_longitudinal: f32,
+    /// Moment of inertia around the lateral (pitch/yaw) axes [kg m²]
+    pub inertia_lateral: f32,
+    /// Distance of the center of pressure behind the center of gravity [m]
+    pub static_margin: f32,
+    /// Normal force per squared airspeed and angle of attack at sea level
+    /// density, i.e. C_Nα * A_ref * ρ₀ / 2 [N s²/m²]
+    pub normal_force_coefficient: f32,
+    /// Fin cant angle [°]
+    pub fin_cant: f32,
+    /// Distance of the fins' center of pressure from the longitudinal axis [m]
+    pub fin_radius: f32,
+
+    pub wind: WindSettings,
+    pub gps: GpsSettings,
+    pub link: LinkSettings,
+
     pub std_dev_gyroscope: f32,
     pub std_dev_accelerometer1: f32,
     pub std_dev_accelerometer2: f32,
     pub std_dev_magnetometer: f32,
+    /// Barometer pressure noise [hPa]
     pub std_dev_barometer: f32,
+    /// Barometer temperature noise [°C]
+    pub std_dev_temperature: f32,
 
-    pub barometer_anomaly_probability: f32,
-    pub barometer_anomaly_value: f32,
-    pub barometer_anomaly_delay: u32,
+    pub faults: Vec<SensorFault>,
 
     pub fc_settings: Settings,
 }
@@ -58,37 +115,157 @@ impl Default for SimulationSettings {
     fn default() -> Self {
         Self {
             replication_log_index: None,
+            seed: 0,
 
             altitude_ground: 150.0,
             launch_angle: 5.0,
             launch_azimuth: 60.0,
             launch_latitude: 49.861445,
             launch_longitude: 8.68519,
+            rail_length: 6.0,
 
             sim_duration: 240_000,
             sim_start_delay: 10_000,
             thrust_duration: 2_000,
             thrust: 120.0,
+            motor: None,
+            dry_mass: 3.0,
 
             drag_flight: 0.01,
             drag_drogue: 1.0,
             drag_main: 5.0,
 
+            inertia_longitudinal: 0.01,
+            inertia_lateral: 0.6,
+            static_margin: 0.15,
+            normal_force_coefficient: 0.05,
+            fin_cant: 0.0,
+            fin_radius: 0.08,
+
+            wind: WindSettings::default(),
+            gps: GpsSettings::default(),
+            link: LinkSettings::default(),
+
             std_dev_gyroscope: 0.07,
             std_dev_accelerometer1: 0.05,
             std_dev_accelerometer2: 0.7,
             std_dev_magnetometer: 0.05,
-            std_dev_barometer: 0.5,
+            std_dev_barometer: 0.06,
+            std_dev_temperature: 0.05,
 
-            barometer_anomaly_probability: 0.00001,
-            barometer_anomaly_value: 0.0,
-            barometer_anomaly_delay: 0,
+            faults: vec![SensorFault::barometer_anomaly(0.00001, 0.0, 0)],
 
             fc_settings: Settings::default(),
         }
     }
 }
 
+impl SimulationSettings {
+    /// Parses a scenario file. Settings missing from the file keep their
+    /// default values.
+    pub fn from_scenario(contents: &str) -> Result<Self, String> {
+        let invalid = |e: serde_json::Error| format!("Invalid scenario: {}", e);
+        let mut settings: Self = serde_json::from_str(contents).map_err(invalid)?;
+
+        // Older files configure the barometer anomaly directly instead of in the fault list.
+        let legacy: LegacyBarometerAnomaly = serde_json::from_str(contents).map_err(invalid)?;
+        if let Some(fault) = legacy.fault() {
+            if legacy.faults.is_none() {
+                settings.faults.clear();
+            }
+            settings.faults.push(fault);
+        }
+
+        settings.validated().map_err(|e| format!("Invalid scenario: {}", e))
+    }
+
+    /// Rejects values the simulation can't run with, such as negative
+    /// standard deviations or masses, which would only panic or produce NaN
+    /// states once the simulation runs. Ranges match those of the settings UI.
+    fn validated(mut self) -> Result<Self, String> {
+        let std_devs = [
+            ("std_dev_gyroscope", self.std_dev_gyroscope),
+            ("std_dev_accelerometer1", self.std_dev_accelerometer1),
+            ("std_dev_accelerometer2", self.std_dev_accelerometer2),
+            ("std_dev_magnetometer", self.std_dev_magnetometer),
+            ("std_dev_barometer", self.std_dev_barometer),
+            ("std_dev_temperature", self.std_dev_temperature),
+            ("gps.std_dev_position", self.gps.std_dev_position),
+            ("gps.hdop", self.gps.hdop),
+            ("link.fading", self.link.fading),
+        ];
+        if let Some((name, value)) = std_devs.iter().find(|(_, v)| !(v.is_finite() && *v >= 0.0)) {
+            return Err(format!("{} must be a non-negative number, got {}", name, value));
+        }
+
+        let ranges = [
+            ("dry_mass", self.dry_mass, 0.01..=1000.0),
+            ("inertia_longitudinal", self.inertia_longitudinal, 0.0001..=100.0),
+            ("inertia_lateral", self.inertia_lateral, 0.001..=1000.0),
+            ("wind.gust_intensity", self.wind.gust_intensity, 0.0..=20.0),
+        ];
+        if let Some((name, value, range)) = ranges.iter().find(|(_, v, r)| !r.contains(v)) {
+            return Err(format!("{} must be between {} and {}, got {}", name, range.start(), range.end(), value));
+        }
+
+        self.faults = self
+            .faults
+            .into_iter()
+            .enumerate()
+            .map(|(i, f)| f.validated().map_err(|e| format!("faults[{}]: {}", i, e)))
+            .collect::<Result<_, _>>()?;
+        self.motor = self.motor.map(|m| m.validated().map_err(|e| format!("motor: {}", e))).transpose()?;
+        Ok(self)
+    }
+
+    /// Picks a new seed, e.g. to rerun the same scenario with different noise.
+    pub fn reseed(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.seed = Rng::from_entropy().gen_range(0..1_000_000);
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.seed = self.seed.wrapping_add(1);
+        }
+    }
+
+    /// Duration of the powered flight [ms]
+    pub fn burn_time(&self) -> u32 {
+        match &self.motor {
+            Some(motor) => (motor.burn_time() * 1000.0) as u32,
+            None => self.thrust_duration,
+        }
+    }
+}
+
+/// The barometer anomaly settings of scenario files from before sensor faults
+/// were configurable.
+#[derive(Deserialize)]
+struct LegacyBarometerAnomaly {
+    barometer_anomaly_probability: Option<f32>,
+    barometer_anomaly_value: Option<f32>,
+    barometer_anomaly_delay: Option<u32>,
+    faults: Option<serde::de::IgnoredAny>,
+}
+
+impl LegacyBarometerAnomaly {
+    fn fault(&self) -> Option<SensorFault> {
+        if self.barometer_anomaly_probability.is_none()
+            && self.barometer_anomaly_value.is_none()
+            && self.barometer_anomaly_delay.is_none()
+        {
+            return None;
+        }
+
+        Some(SensorFault::barometer_anomaly(
+            self.barometer_anomaly_probability.unwrap_or(0.00001),
+            self.barometer_anomaly_value.unwrap_or(0.0),
+            self.barometer_anomaly_delay.unwrap_or(0),
+        ))
+    }
+}
+
 #[derive(Debug)]
 pub struct SimulationState {
     pub(crate) rng: Rng,
@@ -102,16 +279,26 @@ pub struct SimulationState {
     pub(crate) altitude: f32,
     pub(crate) altitude_ground: f32,
     pub(crate) orientation: UnitQuaternion<f32>,
+    /// Angular velocity in the body frame [rad/s]
     pub(crate) angular_velocity: Vector3<f32>,
     pub(crate) acceleration: Vector3<f32>,
     pub(crate) velocity: Vector3<f32>,
+    pub(crate) mass: f32,
+    pub(crate) left_rail: bool,
+    pub(crate) gusts: Gusts,
 
     pub(crate) gyroscope: Option<Vector3<f32>>,
     pub(crate) accelerometer1: Option<Vector3<f32>>,
     pub(crate) accelerometer2: Option<Vector3<f32>>,
     pub(crate) magnetometer: Option<Vector3<f32>>,
     pub(crate) pressure_baro: Option<f32>,
+    pub(crate) temperature_baro: Option<f32>,
     pub(crate) altitude_baro: Option<f32>,
+    pub(crate) gps_receiver: Gps,
+    /// Latest output of the GPS receiver
+    pub(crate) gps: Option<GpsReading>,
+    pub(crate) fault_injector: FaultInjector,
+    pub(crate) link: Link,
 
     pub(crate) state_estimator: StateEstimator,
     pub(crate) mode: FlightMode,
@@ -175,6 +362,7 @@ impl SimulationState {
         let mut accelerometer2 = None;
         let mut magnetometer = None;
         let mut pressure_baro = None;
+        let mut temperature_baro = None;
         let mut altitude_baro = None;
         if let Some(first) = remaining_replication_states.pop_front() {
             time = first.time;
@@ -184,15 +372,16 @@ impl SimulationState {
             accelerometer2 = first.accelerometer2;
             magnetometer = first.magnetometer;
             pressure_baro = first.pressure_baro;
-            altitude_baro = pressure_baro.map(|p| 44307.694 * (1.0 - (p / 1013.25).powf(0.190284)));
+            temperature_baro = first.temperature_baro;
+            altitude_baro = pressure_baro.map(pressure_to_altitude);
             state_estimator.altitude_ground = altitude_baro.unwrap();
         }
 
+        let mass = settings.dry_mass + settings.motor.as_ref().map(|m| m.total_mass).unwrap_or(0.0);
+        let link = Link::new(settings.fc_settings.default_data_rate);
+
         Self {
-            #[cfg(not(target_arch = "wasm32"))]
-            rng: Rng::from_entropy(),
-            #[cfg(target_arch = "wasm32")]
-            rng: Rng::from_seed([0x42; 16]),
+            rng: Rng::seed_from_u64(settings.seed),
             settings,
             state_estimator,
 
@@ -207,18 +396,34 @@ impl SimulationState {
             angular_velocity: Vector3::new(0.0, 0.0, 0.0),
             acceleration: Vector3::new(0.0, 0.0, 0.0),
             velocity: Vector3::new(0.0, 0.0, 0.0),
+            mass,
+            left_rail: false,
+            gusts: Gusts::default(),
 
             gyroscope,
             accelerometer1,
             accelerometer2,
             magnetometer,
             pressure_baro,
+            temperature_baro,
             altitude_baro,
+            gps_receiver: Gps::default(),
+            gps: None,
+            fault_injector: FaultInjector::default(),
+            link,
 
             remaining_replication_states,
         }
     }
 
+    /// Advances the gusts and returns the current wind velocity at the
+    /// vehicle in the local ENU frame [m/s]
+    fn update_wind(&mut self) -> Vector3<f32> {
+        let dt = (SIMULATION_TICK_MS as f32) / 1000.0;
+        let gusts = self.gusts.update(&self.settings.wind, dt, &mut self.rng);
+        self.settings.wind.mean_wind(self.altitude - self.altitude_ground) + gusts
+    }
+
     fn sample_noise(&mut self, std_dev: f32) -> f32 {
         rand_distr::Normal::new(0.0, std_dev).unwrap().sample(&mut self.rng)
     }
@@ -232,7 +437,8 @@ impl SimulationState {
     }
 
     fn sample_gyroscope(&mut self) -> Option<Vector3<f32>> {
-        Some(self.angular_velocity + self.sample_noise_vector(self.settings.std_dev_gyroscope))
+        let angular_velocity = self.angular_velocity.map(|w| w.to_degrees());
+        Some(angular_velocity + self.sample_noise_vector(self.settings.std_dev_gyroscope))
     }
 
     fn local_acceleration(&self) -> Vector3<f32> {
@@ -256,10 +462,91 @@ impl SimulationState {
         Some(observed + self.sample_noise_vector(self.settings.std_dev_magnetometer))
     }
 
-    fn sample_barometer(&mut self) -> Option<f32> {
-        Some(self.altitude + self.sample_noise(self.settings.std_dev_barometer))
-        // TODO: simulate barometer more accurately? for instance, simulate
-        // noisy and rounded pressure and temp readings and run temp-comp?
+    fn sample_barometer(&mut self) -> (Option<f32>, Option<f32>) {
+        let mut pressure = [pressure(self.altitude) + self.sample_noise(self.settings.std_dev_barometer)];
+        let temperature = temperature(self.altitude) - 273.15 + self.sample_noise(self.settings.std_dev_temperature);
+        let faults = &self.settings.faults;
+        if !self.fault_injector.apply(faults, Sensor::Barometer, self.time, &mut pressure, &mut self.rng) {
+            return (None, None);
+        }
+
+        (Some(pressure[0]), Some(temperature))
+    }
+
+    /// Returns the latest GPS reading, which is only updated at the
+    /// receiver's update rate.
+    fn sample_gps(&mut self) -> Option<GpsReading> {
+        let position = (self.latitude, self.longitude, self.altitude);
+        let acceleration = self.acceleration.magnitude();
+        let settings = &self.settings.gps;
+        let Some(mut reading) = self.gps_receiver.update(settings, self.time, position, acceleration, &mut self.rng)
+        else {
+            return self.gps;
+        };
+
+        let Some((latitude, longitude, altitude)) = reading.position else {
+            return Some(reading);
+        };
+
+        // Faults are applied to the position relative to the launch site.
+        let (latitude0, longitude0) = (self.settings.launch_latitude, self.settings.launch_longitude);
+        let meters_per_degree_longitude = 111_320.0 * f64::cos(latitude0.to_radians());
+        let east = (longitude - longitude0) * meters_per_degree_longitude;
+        let north = (latitude - latitude0) * 111_320.0;
+
+        let mut position = [east as f32, north as f32, altitude];
+        let faults = &self.settings.faults;
+        if !self.fault_injector.apply(faults, Sensor::Gps, self.time, &mut position, &mut self.rng) {
+            return None;
+        }
+
+        let [east, north, altitude] = position;
+        let latitude = latitude0 + (north as f64) / 111_320.0;
+        let longitude = longitude0 + (east as f64) / meters_per_degree_longitude;
+        reading.position = Some((latitude, longitude, altitude));
+        Some(reading)
+    }
+
+    /// Applies the configured faults of the given sensor to a reading.
+    fn inject_faults(&mut self, sensor: Sensor, reading: Option<Vector3<f32>>) -> Option<Vector3<f32>> {
+        let mut reading = reading?;
+        let values = reading.as_mut_slice();
+        let keep = self.fault_injector.apply(&self.settings.faults, sensor, self.time, values, &mut self.rng);
+        keep.then_some(reading)
+    }
+
+    /// Distance between the vehicle and the ground station [m]
+    fn gcs_distance(&self) -> f32 {
+        let link = &self.settings.link;
+        let bearing = link.gcs_bearing.to_radians();
+        let meters_per_degree_longitude = 111_320.0 * f64::cos(self.settings.launch_latitude.to_radians());
+        let east = ((self.longitude - self.settings.launch_longitude) * meters_per_degree_longitude) as f32;
+        let north = ((self.latitude - self.settings.launch_latitude) * 111_320.0) as f32;
+        let offset = Vector3::new(
+            east - link.gcs_distance * bearing.sin(),
+            north - link.gcs_distance * bearing.cos(),
+            self.altitude - self.altitude_ground,
+        );
+        offset.magnitude()
+    }
+
+    /// Returns the messages received by the ground station in this tick, if
+    /// the downlink is simulated.
+    pub fn downlink_messages(&mut self) -> Vec<DownlinkMessage> {
+        let vs = VehicleState::from(&*self);
+        let distance = self.gcs_distance();
+        self.link.transmit(&self.settings.link, &vs, distance, &mut self.rng)
+    }
+
+    /// Returns the telemetry messages sent by the FC in this tick, as received
+    /// by the ground station. Without a simulated downlink, none are lost.
+    pub fn telemetry_messages(&mut self) -> Vec<DownlinkMessage> {
+        if self.settings.link.enabled {
+            return self.downlink_messages();
+        }
+
+        let vs = VehicleState::from(&*self);
+        scheduled_message(&vs, self.settings.fc_settings.default_data_rate).into_iter().collect()
     }
 
     pub fn plottable(&self) -> bool {
@@ -309,8 +596,9 @@ impl SimulationState {
             self.accelerometer1 = next.accelerometer1;
             self.accelerometer2 = next.accelerometer2;
             self.magnetometer = next.magnetometer;
-            // TODO: reuse this somehow
-            self.altitude_baro = next.pressure_baro.map(|p| 44307.694 * (1.0 - (p / 1013.25).powf(0.190284)));
+            self.pressure_baro = next.pressure_baro;
+            self.temperature_baro = next.temperature_baro.or(self.temperature_baro);
+            self.altitude_baro = next.pressure_baro.map(pressure_to_altitude);
             self.remaining_replication_states.pop_front();
         } else {
             // TODO: linear interpolation is probably not the best choice here.
@@ -334,12 +622,56 @@ impl SimulationState {
                 self.pressure_baro.unwrap()
                     + (next.pressure_baro.unwrap() - self.pressure_baro.unwrap()) / delta_time as f32,
             );
-            self.altitude_baro = self.pressure_baro.map(|p| 44307.694 * (1.0 - (p / 1013.25).powf(0.190284)));
+            self.altitude_baro = self.pressure_baro.map(pressure_to_altitude);
         }
 
         false
     }
 
+    /// Advances the rotation of the vehicle, driven by the aerodynamic
+    /// moments resulting from the given airspeed in the local ENU frame.
+    fn advance_rotation(&mut self, airspeed: Vector3<f32>, relative_density: f32) {
+        // The launch rail keeps the vehicle from rotating until it leaves it.
+        self.left_rail |= self.altitude - self.altitude_ground >= self.settings.rail_length;
+        if !self.left_rail {
+            self.angular_velocity = Vector3::new(0.0, 0.0, 0.0);
+            return;
+        }
+
+        let settings = &self.settings;
+        let airspeed = self.orientation.inverse_transform_vector(&airspeed);
+        let torque = match self.mode {
+            FlightMode::RecoveryDrogue | FlightMode::RecoveryMain | FlightMode::Landed => {
+                -RECOVERY_ROTATION_DAMPING * self.angular_velocity
+            }
+            _ => {
+                // The normal force acts on the center of pressure. Using the airflow there
+                // (including the vehicle's own rotation) gives us a restoring moment that
+                // turns the vehicle into the airflow, as well as aerodynamic damping.
+                let cp = Vector3::new(0.0, 0.0, -settings.static_margin);
+                let flow = airspeed + self.angular_velocity.cross(&cp);
+                let k = settings.normal_force_coefficient * relative_density;
+                let normal_force = -k * flow.magnitude() * Vector3::new(flow.x, flow.y, 0.0);
+
+                // Canted fins see an additional angle of attack, reduced by the roll rate.
+                let speed = airspeed.z.max(0.0);
+                let fin_radius = settings.fin_radius;
+                let fin_flow = speed * settings.fin_cant.to_radians().tan() - self.angular_velocity.z * fin_radius;
+                let roll = k * speed * fin_flow * fin_radius;
+
+                cp.cross(&normal_force) + Vector3::new(0.0, 0.0, roll)
+            }
+        };
+
+        let inertia = Vector3::new(settings.inertia_lateral, settings.inertia_lateral, settings.inertia_longitudinal);
+        let gyroscopic = self.angular_velocity.cross(&self.angular_velocity.component_mul(&inertia));
+        let angular_acceleration = (torque - gyroscopic).component_div(&inertia);
+
+        let dt = (SIMULATION_TICK_MS as f32) / 1000.0;
+        self.angular_velocity += angular_acceleration * dt;
+        self.orientation *= UnitQuaternion::from_scaled_axis(self.angular_velocity * dt);
+    }
+
     fn advance_simulation(&mut self) -> bool {
         if self.time > self.settings.sim_duration {
             return true;
@@ -351,60 +683,67 @@ impl SimulationState {
         }
 
         // advance true state of the vehicle
-        self.acceleration = if self.time >= self.settings.sim_start_delay
-            && self.time < self.settings.sim_start_delay + self.settings.thrust_duration
-        {
-            self.orientation.transform_vector(&Vector3::new(0.0, 0.0, self.settings.thrust))
-        } else {
-            Vector3::new(0.0, 0.0, -GRAVITY)
+        let since_ignition = self.time.checked_sub(self.settings.sim_start_delay);
+        self.acceleration = match (&self.settings.motor, since_ignition) {
+            (Some(motor), Some(t)) => {
+                let t = (t as f32) / 1000.0;
+                self.mass = self.settings.dry_mass + motor.mass(t);
+                let thrust = Vector3::new(0.0, 0.0, motor.thrust(t) / self.mass);
+                self.orientation.transform_vector(&thrust) + Vector3::new(0.0, 0.0, -GRAVITY)
+            }
+            (None, Some(t)) if t < self.settings.thrust_duration => {
+                self.orientation.transform_vector(&Vector3::new(0.0, 0.0, self.settings.thrust))
+            }
+            _ => Vector3::new(0.0, 0.0, -GRAVITY),
         };
 
-        let velocity_dir = self.velocity.normalize();
+        // Drag acts against the velocity relative to the surrounding air, and
+        // the drag terms are given for sea level density.
+        let airspeed = self.velocity - self.update_wind();
+        let relative_density = density(self.altitude) / DENSITY_SEA_LEVEL;
+        let velocity_dir = airspeed.normalize();
         if !f32::is_nan(velocity_dir.x) {
             use FlightMode::*;
             let drag = match self.mode {
                 Idle | HardwareArmed | Armed | Flight => self.settings.drag_flight,
                 RecoveryDrogue => self.settings.drag_drogue,
                 RecoveryMain | Landed => self.settings.drag_main,
-            };
+            } * relative_density;
             self.acceleration +=
-                velocity_dir * -drag * self.velocity.magnitude().powi(2) * (SIMULATION_TICK_MS as f32) / 1000.0;
+                velocity_dir * -drag * airspeed.magnitude().powi(2) * (SIMULATION_TICK_MS as f32) / 1000.0;
         }
 
-        // TODO: roll
-
         let scaled_acceleration = self.acceleration * (SIMULATION_TICK_MS as f32) / 1000.0;
         let scaled_velocity = self.velocity * (SIMULATION_TICK_MS as f32) / 1000.0;
-        let old_velocity = self.velocity.clone();
 
         self.velocity += scaled_acceleration;
         self.latitude += (scaled_velocity.y as f64) / 111_320.0;
         self.longitude += (scaled_velocity.x as f64) / (400_750.0 * f64::cos(self.latitude.to_radians()) / 3.6);
         self.altitude += scaled_velocity.z;
 
-        let rotation = UnitQuaternion::rotation_between(&old_velocity, &self.velocity).unwrap();
-        let (roll, pitch, yaw) = rotation.euler_angles();
-        self.angular_velocity = Vector3::new(pitch, yaw, roll) * 1000.0 / (SIMULATION_TICK_MS as f32);
-        self.orientation *= rotation;
+        self.advance_rotation(airspeed, relative_density);
 
         if self.velocity.z < 0.0 && self.altitude <= self.altitude_ground {
             self.acceleration = Vector3::new(0.0, 0.0, 0.0);
             self.altitude = self.altitude_ground;
             self.velocity = Vector3::new(0.0, 0.0, 0.0);
+            self.angular_velocity = Vector3::new(0.0, 0.0, 0.0);
         }
 
         // sample our sensors
-        self.gyroscope = self.sample_gyroscope();
-        self.accelerometer1 = self.sample_accelerometer1();
-        self.accelerometer2 = self.sample_accelerometer2();
-        self.magnetometer = self.sample_magnetometer();
-        self.altitude_baro = self.sample_barometer();
-
-        if self.time > self.settings.barometer_anomaly_delay
-            && self.rng.next_u32() < (self.settings.barometer_anomaly_probability * u32::MAX as f32) as u32
-        {
-            self.altitude_baro = Some(self.settings.barometer_anomaly_value);
-        }
+        let gyroscope = self.sample_gyroscope();
+        self.gyroscope = self.inject_faults(Sensor::Gyroscope, gyroscope);
+        let accelerometer1 = self.sample_accelerometer1();
+        self.accelerometer1 = self.inject_faults(Sensor::Accelerometer1, accelerometer1);
+        let accelerometer2 = self.sample_accelerometer2();
+        self.accelerometer2 = self.inject_faults(Sensor::Accelerometer2, accelerometer2);
+        let magnetometer = self.sample_magnetometer();
+        self.magnetometer = self.inject_faults(Sensor::Magnetometer, magnetometer);
+        (self.pressure_baro, self.temperature_baro) = self.sample_barometer();
+        self.gps = self.sample_gps();
+
+        // The state estimator expects altitudes, which the FC calculates from the pressure.
+        self.altitude_baro = self.pressure_baro.map(pressure_to_altitude);
 
         false
     }
@@ -476,6 +815,7 @@ impl From<&SimulationState> for VehicleState {
                 accelerometer2: ss.accelerometer2,
                 magnetometer: ss.magnetometer,
                 pressure_baro: ss.pressure_baro,
+                temperature_baro: ss.temperature_baro,
                 ..Default::default()
             }
         } else {
@@ -493,19 +833,21 @@ impl From<&SimulationState> for VehicleState {
                 altitude_baro: ss.altitude_baro,
                 altitude_ground_asl: Some(ss.altitude_ground),
                 apogee_asl: Some(ss.state_estimator.altitude_max),
-                altitude_gps_asl: Some(ss.altitude), // TODO
-                latitude: Some(ss.latitude as f32),
-                longitude: Some(ss.longitude as f32),
+                altitude_gps_asl: ss.gps.and_then(|gps| gps.position).map(|(_, _, altitude)| altitude),
+                latitude: ss.gps.and_then(|gps| gps.position).map(|(latitude, _, _)| latitude as f32),
+                longitude: ss.gps.and_then(|gps| gps.position).map(|(_, longitude, _)| longitude as f32),
                 vertical_speed: Some(ss.state_estimator.vertical_speed()),
                 vertical_accel: ss.state_estimator.acceleration_world_raw().map(|acc| acc.z),
                 vertical_accel_filtered: Some(ss.state_estimator.vertical_acceleration()),
-                gps_fix: Some(GPSFixType::AutonomousFix),
-                num_satellites: Some(6),
-                hdop: Some(150),
+                gps_fix: ss.gps.map(|gps| gps.fix),
+                num_satellites: ss.gps.map(|gps| gps.num_satellites),
+                hdop: ss.gps.map(|gps| (gps.hdop * 100.0) as u16),
                 gyroscope: ss.gyroscope,
                 accelerometer1: ss.accelerometer1,
                 accelerometer2: ss.accelerometer2,
                 magnetometer: ss.magnetometer,
+                pressure_baro: ss.pressure_baro,
+                temperature_baro: ss.temperature_baro,
                 battery_voltage: Some(8400),
                 arm_voltage: Some(if ss.time >= (ss.settings.sim_start_delay - 5000) {
                     8400
@@ -529,3 +871,134 @@ impl From<&SimulationState> for VehicleState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::faults::*;
+    use super::*;
+
+    #[test]
+    fn legacy_barometer_anomaly_becomes_fault() {
+        let scenario = r#"{
+            "barometer_anomaly_probability": 0.001,
+            "barometer_anomaly_value": 500.0,
+            "barometer_anomaly_delay": 2000
+        }"#;
+        let settings = SimulationSettings::from_scenario(scenario).unwrap();
+
+        assert_eq!(settings.faults, vec![SensorFault::barometer_anomaly(0.001, 500.0, 2000)]);
+        assert!(matches!(settings.faults[0].kind, FaultKind::Anomaly { value, .. } if value == pressure(500.0)));
+    }
+
+    #[test]
+    fn legacy_barometer_anomaly_added_to_fault_list() {
+        let scenario = r#"{
+            "barometer_anomaly_delay": 2000,
+            "faults": [{ "sensor": "Gps", "kind": "Dropout", "start": 0, "end": null }]
+        }"#;
+        let settings = SimulationSettings::from_scenario(scenario).unwrap();
+
+        assert_eq!(settings.faults.len(), 2);
+        assert_eq!(settings.faults[1], SensorFault::barometer_anomaly(0.00001, 0.0, 2000));
+    }
+
+    #[test]
+    fn bundled_scenarios_are_valid() {
+        for (name, contents) in SCENARIO_LIBRARY {
+            assert!(SimulationSettings::from_scenario(contents).is_ok(), "{}", name);
+        }
+    }
+
+    fn scenario_error(scenario: &str) -> String {
+        SimulationSettings::from_scenario(scenario).unwrap_err()
+    }
+
+    #[test]
+    fn negative_saturation_limit_rejected() {
+        let error = scenario_error(
+            r#"{
+                "faults": [{ "sensor": "Gyroscope", "kind": { "Saturation": { "limit": -1.0 } }, "start": 0, "end": null }]
+            }"#,
+        );
+        assert!(error.contains("faults[0]: limit"), "{}", error);
+    }
+
+    #[test]
+    fn spike_probability_out_of_range_rejected() {
+        let error = scenario_error(
+            r#"{
+                "faults": [{
+                    "sensor": "Barometer",
+                    "kind": { "Spike": { "probability": 1.5, "magnitude": 20.0 } },
+                    "start": 0,
+                    "end": null
+                }]
+            }"#,
+        );
+        assert!(error.contains("faults[0]: probability"), "{}", error);
+    }
+
+    #[test]
+    fn negative_gust_intensity_rejected() {
+        let error = scenario_error(r#"{ "wind": { "gust_intensity": -2.0 } }"#);
+        assert!(error.contains("wind.gust_intensity"), "{}", error);
+    }
+
+    #[test]
+    fn zero_dry_mass_rejected() {
+        let error = scenario_error(r#"{ "dry_mass": 0.0 }"#);
+        assert!(error.contains("dry_mass"), "{}", error);
+    }
+
+    #[test]
+    fn negative_inertia_rejected() {
+        let error = scenario_error(r#"{ "inertia_longitudinal": -0.01 }"#);
+        assert!(error.contains("inertia_longitudinal"), "{}", error);
+
+        let error = scenario_error(r#"{ "inertia_lateral": 0.0 }"#);
+        assert!(error.contains("inertia_lateral"), "{}", error);
+    }
+
+    #[test]
+    fn nan_values_rejected() {
+        // JSON has no NaN, but settings can still end up with one before being validated.
+        let nan_settings = [
+            SimulationSettings {
+                dry_mass: f32::NAN,
+                ..Default::default()
+            },
+            SimulationSettings {
+                wind: WindSettings {
+                    gust_intensity: f32::NAN,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            SimulationSettings {
+                faults: vec![SensorFault {
+                    sensor: Sensor::Barometer,
+                    kind: FaultKind::Spike {
+                        probability: f32::NAN,
+                        magnitude: 20.0,
+                    },
+                    start: 0,
+                    end: None,
+                }],
+                ..Default::default()
+            },
+            SimulationSettings {
+                faults: vec![SensorFault {
+                    sensor: Sensor::Gyroscope,
+                    kind: FaultKind::Saturation { limit: f32::NAN },
+                    start: 0,
+                    end: None,
+                }],
+                ..Default::default()
+            },
+        ];
+
+        for settings in nan_settings {
+            assert!(settings.validated().is_err());
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! International Standard Atmosphere (ISA), for the troposphere and the lower
+//! stratosphere, which is as high as we are going to fly for a while.
+
+/// Sea level pressure [hPa]
+pub const PRESSURE_SEA_LEVEL: f32 = 1013.25;
+/// Sea level temperature [K]
+pub const TEMPERATURE_SEA_LEVEL: f32 = 288.15;
+/// Sea level density [kg/m³]
+pub const DENSITY_SEA_LEVEL: f32 = 1.225;
+
+/// Temperature lapse rate in the troposphere [K/m]
+const LAPSE_RATE: f32 = 0.0065;
+/// Altitude of the tropopause [m]
+const TROPOPAUSE: f32 = 11_000.0;
+/// Specific gas constant of dry air [J/(kg K)]
+const GAS_CONSTANT: f32 = 287.053;
+/// Exponent of the barometric formula, g / (R L)
+const EXPONENT: f32 = 5.25588;
+
+/// Temperature at the given altitude [K]
+pub fn temperature(altitude_asl: f32) -> f32 {
+    TEMPERATURE_SEA_LEVEL - LAPSE_RATE * f32::min(altitude_asl, TROPOPAUSE)
+}
+
+/// Static pressure at the given altitude [hPa]
+pub fn pressure(altitude_asl: f32) -> f32 {
+    let troposphere = f32::min(altitude_asl, TROPOPAUSE);
+    let p = PRESSURE_SEA_LEVEL * (1.0 - LAPSE_RATE * troposphere / TEMPERATURE_SEA_LEVEL).powf(EXPONENT);
+
+    // Above the tropopause, temperature is constant and pressure falls off exponentially.
+    let stratosphere = f32::max(altitude_asl - TROPOPAUSE, 0.0);
+    p * f32::exp(-9.80665 * stratosphere / (GAS_CONSTANT * temperature(TROPOPAUSE)))
+}
+
+/// Air density at the given altitude [kg/m³]
+pub fn density(altitude_asl: f32) -> f32 {
+    pressure(altitude_asl) * 100.0 / (GAS_CONSTANT * temperature(altitude_asl))
+}
+
+/// Converts a barometric pressure reading [hPa] to an altitude [m ASL], the
+/// same way the FC does.
+pub fn pressure_to_altitude(pressure: f32) -> f32 {
+    44307.694 * (1.0 - (pressure / PRESSURE_SEA_LEVEL).powf(0.190284))
+}
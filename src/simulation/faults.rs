@@ -0,0 +1,187 @@
+//! Sensor fault injection for the simulation, for testing how the state
+//! estimator and flight mode logic react to misbehaving sensors.
+
+use rand::{Rng as _, RngCore as _};
+use serde::{Deserialize, Serialize};
+
+use super::atmosphere::pressure;
+use super::Rng;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Sensor {
+    Gyroscope,
+    Accelerometer1,
+    Accelerometer2,
+    Magnetometer,
+    /// Affects the pressure reading [hPa]
+    Barometer,
+    /// Affects the position relative to the launch site (east, north) and
+    /// the altitude [m]
+    Gps,
+}
+
+pub const SENSORS: [Sensor; 6] = [
+    Sensor::Gyroscope,
+    Sensor::Accelerometer1,
+    Sensor::Accelerometer2,
+    Sensor::Magnetometer,
+    Sensor::Barometer,
+    Sensor::Gps,
+];
+
+/// Values are given in the unit of the affected sensor.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FaultKind {
+    /// No readings at all
+    Dropout,
+    /// Readings frozen at the value at the start of the fault
+    Stuck,
+    /// Readings clipped to ±limit
+    Saturation { limit: f32 },
+    /// Bias growing linearly from the start of the fault [unit/s]
+    BiasDrift { rate: f32 },
+    /// Readings multiplied by a factor
+    ScaleError { factor: f32 },
+    /// Random outliers, with the given probability per reading
+    Spike { probability: f32, magnitude: f32 },
+    /// Readings randomly replaced by a fixed value, with the given probability
+    /// per reading
+    Anomaly { probability: f32, value: f32 },
+}
+
+impl FaultKind {
+    pub const ALL: [FaultKind; 7] = [
+        FaultKind::Dropout,
+        FaultKind::Stuck,
+        FaultKind::Saturation { limit: 10.0 },
+        FaultKind::BiasDrift { rate: 0.1 },
+        FaultKind::ScaleError { factor: 1.1 },
+        FaultKind::Spike {
+            probability: 0.001,
+            magnitude: 20.0,
+        },
+        FaultKind::Anomaly {
+            probability: 0.001,
+            value: 0.0,
+        },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FaultKind::Dropout => "Dropout",
+            FaultKind::Stuck => "Stuck",
+            FaultKind::Saturation { .. } => "Saturation",
+            FaultKind::BiasDrift { .. } => "Bias Drift",
+            FaultKind::ScaleError { .. } => "Scale Error",
+            FaultKind::Spike { .. } => "Spikes",
+            FaultKind::Anomaly { .. } => "Anomalies",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SensorFault {
+    pub sensor: Sensor,
+    pub kind: FaultKind,
+    /// Simulation time at which the fault starts [ms]
+    pub start: u32,
+    /// Simulation time at which the fault ends [ms], or None if it persists
+    pub end: Option<u32>,
+}
+
+impl SensorFault {
+    /// The barometer anomaly that used to be the simulation's only fault: the
+    /// pressure at the given altitude [m ASL], reported at random after a delay [ms].
+    pub fn barometer_anomaly(probability: f32, altitude: f32, delay: u32) -> Self {
+        Self {
+            sensor: Sensor::Barometer,
+            kind: FaultKind::Anomaly {
+                probability,
+                value: pressure(altitude),
+            },
+            start: delay.saturating_add(1),
+            end: None,
+        }
+    }
+
+    /// Rejects fault parameters the injector can't apply, such as a negative
+    /// saturation limit, using the ranges of the settings UI.
+    pub(crate) fn validated(self) -> Result<Self, String> {
+        let (name, value, range) = match self.kind {
+            FaultKind::Saturation { limit } => ("limit", limit, 0.0..=100000.0),
+            FaultKind::ScaleError { factor } => ("factor", factor, -10.0..=10.0),
+            FaultKind::Spike { probability, .. } | FaultKind::Anomaly { probability, .. } => {
+                ("probability", probability, 0.0..=1.0)
+            }
+            FaultKind::Dropout | FaultKind::Stuck | FaultKind::BiasDrift { .. } => return Ok(self),
+        };
+
+        if !range.contains(&value) {
+            return Err(format!("{} must be between {} and {}, got {}", name, range.start(), range.end(), value));
+        }
+
+        Ok(self)
+    }
+
+    pub fn active(&self, time: u32) -> bool {
+        time >= self.start && self.end.map(|end| time < end).unwrap_or(true)
+    }
+}
+
+/// Keeps track of the state needed to apply faults over time, e.g. the
+/// values stuck sensors are frozen at.
+#[derive(Clone, Debug, Default)]
+pub struct FaultInjector {
+    held: Vec<Option<Vec<f32>>>,
+}
+
+impl FaultInjector {
+    /// Applies all active faults of the given sensor to a reading, in order.
+    /// Returns false if the reading was dropped.
+    pub fn apply(
+        &mut self,
+        faults: &[SensorFault],
+        sensor: Sensor,
+        time: u32,
+        values: &mut [f32],
+        rng: &mut Rng,
+    ) -> bool {
+        self.held.resize(faults.len(), None);
+
+        for (fault, held) in faults.iter().zip(self.held.iter_mut()).filter(|(f, _)| f.sensor == sensor) {
+            if !fault.active(time) {
+                *held = None;
+                continue;
+            }
+
+            match fault.kind {
+                FaultKind::Dropout => return false,
+                FaultKind::Stuck => {
+                    let held = held.get_or_insert_with(|| values.to_vec());
+                    values.copy_from_slice(held);
+                }
+                FaultKind::Saturation { limit } => values.iter_mut().for_each(|v| *v = v.clamp(-limit, limit)),
+                FaultKind::BiasDrift { rate } => {
+                    let bias = rate * (time - fault.start) as f32 / 1000.0;
+                    values.iter_mut().for_each(|v| *v += bias);
+                }
+                FaultKind::ScaleError { factor } => values.iter_mut().for_each(|v| *v *= factor),
+                FaultKind::Spike { probability, magnitude } => {
+                    if rng.gen_bool(probability.clamp(0.0, 1.0) as f64) {
+                        let spike = if rng.gen() { magnitude } else { -magnitude };
+                        values.iter_mut().for_each(|v| *v += spike);
+                    }
+                }
+                FaultKind::Anomaly { probability, value } => {
+                    // Drawn the same way as the former barometer anomaly, so
+                    // simulations with the default settings stay the same.
+                    if rng.next_u32() < (probability * u32::MAX as f32) as u32 {
+                        values.iter_mut().for_each(|v| *v = value);
+                    }
+                }
+            }
+        }
+
+        true
+    }
+}
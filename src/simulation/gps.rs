@@ -0,0 +1,128 @@
+//! GPS receiver model for the simulation, producing delayed, noisy fixes at a
+//! limited rate instead of the true position.
+
+use std::collections::VecDeque;
+
+use rand::distributions::Distribution;
+use serde::{Deserialize, Serialize};
+
+use mithril::telemetry::GPSFixType;
+
+use super::Rng;
+
+/// HDOP reported without a fix
+const HDOP_NO_FIX: f32 = 99.99;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GpsSettings {
+    /// Rate at which fixes are output [Hz]
+    pub update_rate: f32,
+    /// Delay between a position and its output [ms]
+    pub latency: u32,
+    /// Time from startup until the first fix [ms]
+    pub acquisition_time: u32,
+    /// Time to regain the fix after it was lost [ms]
+    pub reacquisition_time: u32,
+    /// Acceleration above which the receiver loses its fix [m/s²]
+    pub max_acceleration: f32,
+    pub num_satellites: u8,
+    pub hdop: f32,
+    /// Horizontal position error at an HDOP of 1 [m]. The vertical error
+    /// is assumed to be 1.5 times larger.
+    pub std_dev_position: f32,
+}
+
+impl Default for GpsSettings {
+    fn default() -> Self {
+        Self {
+            update_rate: 10.0,
+            latency: 100,
+            acquisition_time: 5_000,
+            reacquisition_time: 2_000,
+            max_acceleration: 4.0 * 9.80665,
+            num_satellites: 8,
+            hdop: 1.2,
+            std_dev_position: 2.5,
+        }
+    }
+}
+
+/// A single output of the GPS receiver.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GpsReading {
+    pub fix: GPSFixType,
+    pub num_satellites: u8,
+    pub hdop: f32,
+    /// Latitude, longitude and altitude [m ASL], if there is a fix
+    pub position: Option<(f64, f64, f32)>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Gps {
+    /// True positions that have not been output yet, due to latency
+    pending: VecDeque<(u32, (f64, f64, f32))>,
+    next_update: u32,
+    /// Start and duration of the current (re-)acquisition [ms]
+    acquisition: Option<(u32, u32)>,
+}
+
+impl Gps {
+    /// Advances the receiver with the current true position and acceleration
+    /// of the vehicle. Returns a new reading if one is due.
+    pub fn update(
+        &mut self,
+        settings: &GpsSettings,
+        time: u32,
+        position: (f64, f64, f32),
+        acceleration: f32,
+        rng: &mut Rng,
+    ) -> Option<GpsReading> {
+        if acceleration > settings.max_acceleration {
+            self.acquisition = Some((time, settings.reacquisition_time));
+        }
+
+        let (acquisition_start, acquisition_time) = *self.acquisition.get_or_insert((time, settings.acquisition_time));
+
+        self.pending.push_back((time, position));
+        let delayed = time.saturating_sub(settings.latency);
+        while self.pending.len() > 1 && self.pending[1].0 <= delayed {
+            self.pending.pop_front();
+        }
+
+        if time < self.next_update {
+            return None;
+        }
+        self.next_update = time + (1000.0 / settings.update_rate.max(0.1)) as u32;
+
+        let elapsed = time - acquisition_start;
+        if elapsed < acquisition_time {
+            // Satellites are found one by one, but we need at least four for a fix.
+            let found = settings.num_satellites as u32 * elapsed / acquisition_time;
+            return Some(GpsReading {
+                fix: GPSFixType::NoFix,
+                num_satellites: found.min(3) as u8,
+                hdop: HDOP_NO_FIX,
+                position: None,
+            });
+        }
+
+        let std_dev = settings.std_dev_position * settings.hdop;
+        let horizontal = rand_distr::Normal::new(0.0, std_dev).unwrap();
+        let vertical = rand_distr::Normal::new(0.0, 1.5 * std_dev).unwrap();
+
+        let (_, (latitude, longitude, altitude)) = self.pending[0];
+        let north = horizontal.sample(rng) as f64;
+        let east = horizontal.sample(rng) as f64;
+        Some(GpsReading {
+            fix: GPSFixType::AutonomousFix,
+            num_satellites: settings.num_satellites,
+            hdop: settings.hdop,
+            position: Some((
+                latitude + north / 111_320.0,
+                longitude + east / (111_320.0 * f64::cos(latitude.to_radians())),
+                altitude + vertical.sample(rng),
+            )),
+        })
+    }
+}
@@ -0,0 +1,213 @@
+//! LoRa downlink model for the simulation. Turns simulated vehicle states into
+//! the telemetry messages the FC would send at its current data rate, and
+//! drops them depending on the signal received by the ground station.
+
+use std::collections::VecDeque;
+
+use nalgebra::Vector3;
+use rand::distributions::Distribution;
+use rand::Rng as _;
+use serde::{Deserialize, Serialize};
+
+use mithril::telemetry::*;
+
+use super::Rng;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkSettings {
+    /// Simulate the downlink instead of receiving every state
+    pub enabled: bool,
+    pub transmit_power: TransmitPower,
+    /// Carrier frequency [MHz]
+    pub frequency: f32,
+    /// Combined gain of both antennas [dBi]
+    pub antenna_gain: f32,
+    /// Losses in addition to free-space path loss, e.g. cables and terrain [dB]
+    pub additional_loss: f32,
+    /// Standard deviation of signal fading [dB]
+    pub fading: f32,
+    /// Noise floor at the ground station [dBm]
+    pub noise_floor: f32,
+    /// SNR at which half of all packets can be decoded [dB]
+    pub min_snr: f32,
+    /// Probability of losing a packet regardless of the signal
+    pub packet_loss: f32,
+    /// Horizontal distance of the ground station from the launch site [m]
+    pub gcs_distance: f32,
+    /// Direction of the ground station as seen from the launch site [°]
+    pub gcs_bearing: f32,
+}
+
+impl Default for LinkSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            transmit_power: TransmitPower::P14dBm,
+            frequency: 868.0,
+            antenna_gain: 2.0,
+            additional_loss: 20.0,
+            fading: 3.0,
+            noise_floor: -117.0,
+            min_snr: -7.5,
+            packet_loss: 0.01,
+            gcs_distance: 200.0,
+            gcs_bearing: 180.0,
+        }
+    }
+}
+
+impl LinkSettings {
+    fn transmit_power_dbm(&self) -> f32 {
+        match self.transmit_power {
+            TransmitPower::P14dBm => 14.0,
+            TransmitPower::P17dBm => 17.0,
+            TransmitPower::P20dBm => 20.0,
+            TransmitPower::P22dBm => 22.0,
+        }
+    }
+}
+
+/// The FC's telemetry schedule: one message per slot, with GPS messages once
+/// a second, diagnostics every 200 ms, main telemetry at 10 Hz and raw sensor
+/// values in between at the high data rate.
+pub fn scheduled_message(vs: &VehicleState, data_rate: TelemetryDataRate) -> Option<DownlinkMessage> {
+    let slot = match data_rate {
+        TelemetryDataRate::Low => 50,
+        TelemetryDataRate::High => 25,
+    };
+
+    let time = vs.time;
+    if time % slot != 0 {
+        None
+    } else if time % 1000 == 0 {
+        Some(DownlinkMessage::TelemetryGPS(gps_message(vs)))
+    } else if time % 200 == 0 {
+        // The simulation has no diagnostics (battery, CPU, etc.) to send.
+        None
+    } else if time % 100 == 50 {
+        Some(DownlinkMessage::TelemetryMain(main_message(vs)))
+    } else if time % 50 == 25 {
+        Some(DownlinkMessage::TelemetryRawSensors(raw_sensors_message(vs)))
+    } else {
+        None
+    }
+}
+
+fn main_message(vs: &VehicleState) -> TelemetryMain {
+    TelemetryMain {
+        time: vs.time,
+        mode: vs.mode.unwrap_or(FlightMode::Idle),
+        orientation: vs.orientation,
+        vertical_speed: vs.vertical_speed.unwrap_or_default(),
+        vertical_accel: vs.vertical_accel.unwrap_or_default(),
+        vertical_accel_filtered: vs.vertical_accel_filtered.unwrap_or_default(),
+        altitude_baro: vs.altitude_baro.unwrap_or_default(),
+        altitude_max: vs.apogee_asl.unwrap_or_default(),
+        altitude: vs.altitude_asl.unwrap_or_default(),
+    }
+}
+
+fn raw_sensors_message(vs: &VehicleState) -> TelemetryRawSensors {
+    TelemetryRawSensors {
+        time: vs.time,
+        gyro: vs.gyroscope.unwrap_or(Vector3::zeros()),
+        accelerometer1: vs.accelerometer1.unwrap_or(Vector3::zeros()),
+        accelerometer2: vs.accelerometer2.unwrap_or(Vector3::zeros()),
+        magnetometer: vs.magnetometer.unwrap_or(Vector3::zeros()),
+        temperature_baro: vs.temperature_baro.unwrap_or_default(),
+        pressure_baro: vs.pressure_baro.unwrap_or_default(),
+    }
+}
+
+/// Packs a coordinate into 24 bits, with zero meaning no value.
+fn pack_coordinate(value: Option<f32>, range: f32) -> [u8; 3] {
+    let packed = value.map(|v| ((v + range / 2.0) / range * 16_777_215.0) as u32).unwrap_or(0);
+    let [_, b0, b1, b2] = packed.to_be_bytes();
+    [b0, b1, b2]
+}
+
+fn gps_message(vs: &VehicleState) -> TelemetryGPS {
+    let fix = vs.gps_fix.unwrap_or(GPSFixType::NoFix) as u8;
+    TelemetryGPS {
+        time: vs.time,
+        fix_and_sats: (fix << 5) | vs.num_satellites.unwrap_or(0).min(0x1f),
+        hdop: vs.hdop.unwrap_or(9999),
+        latitude: pack_coordinate(vs.latitude, 180.0),
+        longitude: pack_coordinate(vs.longitude, 360.0),
+        altitude_asl: vs.altitude_gps_asl.map(|alt| (alt * 10.0).clamp(0.0, u16::MAX as f32) as u16).unwrap_or(0),
+        flash_pointer: 0,
+    }
+}
+
+/// Free-space path loss [dB] for the given distance [m] and frequency [MHz]
+fn free_space_path_loss(distance: f32, frequency: f32) -> f32 {
+    20.0 * distance.max(1.0).log10() + 20.0 * frequency.log10() - 27.55
+}
+
+#[derive(Clone, Debug)]
+pub struct Link {
+    data_rate: TelemetryDataRate,
+    /// FC time and success of the messages sent within the last second
+    history: VecDeque<(u32, bool)>,
+}
+
+impl Link {
+    pub fn new(data_rate: TelemetryDataRate) -> Self {
+        Self {
+            data_rate,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Sends the message scheduled for the given state, if any, over the
+    /// given distance [m]. Returns the messages received by the ground
+    /// station, i.e. the telemetry message followed by the ground station's
+    /// own signal information.
+    pub fn transmit(
+        &mut self,
+        settings: &LinkSettings,
+        vs: &VehicleState,
+        distance: f32,
+        rng: &mut Rng,
+    ) -> Vec<DownlinkMessage> {
+        let Some(msg) = scheduled_message(vs, self.data_rate) else {
+            return Vec::new();
+        };
+
+        let fading = rand_distr::Normal::new(0.0, settings.fading).unwrap().sample(rng);
+        let path_loss = free_space_path_loss(distance, settings.frequency) + settings.additional_loss;
+        let rssi_signal = settings.transmit_power_dbm() + settings.antenna_gain - path_loss + fading;
+        let snr = rssi_signal - settings.noise_floor;
+        // The total received power includes the noise.
+        let rssi = 10.0 * (10f32.powf(rssi_signal / 10.0) + 10f32.powf(settings.noise_floor / 10.0)).log10();
+
+        // Packets become undecodable over a few dB around the SNR limit.
+        let p_decoded = 1.0 / (1.0 + f32::exp(-(snr - settings.min_snr)));
+        let received = rng.gen_bool((p_decoded * (1.0 - settings.packet_loss)).clamp(0.0, 1.0) as f64);
+
+        self.history.push_back((vs.time, received));
+        while self.history.front().map(|(t, _)| *t + 1000 <= vs.time).unwrap_or(false) {
+            self.history.pop_front();
+        }
+
+        if !received {
+            return Vec::new();
+        }
+
+        let gcs = TelemetryGCS {
+            time: vs.time,
+            lora_rssi: (-rssi * 2.0).clamp(0.0, 255.0) as u8,
+            lora_rssi_signal: (-rssi_signal * 2.0).clamp(0.0, 255.0) as u8,
+            lora_snr: (snr * 4.0).clamp(-128.0, 127.0) as i8,
+        };
+
+        vec![msg, DownlinkMessage::TelemetryGCS(gcs)]
+    }
+
+    /// Share of the messages sent within the last second that were received [%]
+    pub fn link_quality(&self) -> Option<f32> {
+        let received = self.history.iter().filter(|(_, received)| *received).count();
+        (!self.history.is_empty()).then_some(100.0 * received as f32 / self.history.len() as f32)
+    }
+}
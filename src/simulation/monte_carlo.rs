@@ -0,0 +1,396 @@
+//! Monte Carlo dispersion analysis. Runs many simulations with randomized
+//! launch and vehicle parameters in parallel and collects the distributions
+//! of apogee, maximum velocity and landing point.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use rand::distributions::Distribution;
+use rand::SeedableRng;
+use serde::Serialize;
+
+use crate::simulation::*;
+
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// Standard deviations of the randomized simulation parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dispersion {
+    /// Launch angle [°]
+    pub launch_angle: f32,
+    /// Launch azimuth [°]
+    pub launch_azimuth: f32,
+    /// Thrust, relative to the nominal thrust
+    pub thrust: f32,
+    /// Drag coefficients, relative to the nominal values
+    pub drag: f32,
+    /// Wind speed, applied to all layers [m/s]
+    pub wind_speed: f32,
+    /// Wind direction, applied to all layers [°]
+    pub wind_direction: f32,
+}
+
+impl Default for Dispersion {
+    fn default() -> Self {
+        Self {
+            launch_angle: 1.0,
+            launch_azimuth: 5.0,
+            thrust: 0.05,
+            drag: 0.1,
+            wind_speed: 2.0,
+            wind_direction: 20.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonteCarloSettings {
+    pub runs: usize,
+    /// Seed for the first run, following runs use consecutive seeds.
+    pub seed: u64,
+    pub dispersion: Dispersion,
+}
+
+impl Default for MonteCarloSettings {
+    fn default() -> Self {
+        Self {
+            runs: 100,
+            seed: 0,
+            dispersion: Dispersion::default(),
+        }
+    }
+}
+
+/// Parameters and results of a single simulation run.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MonteCarloRun {
+    pub index: usize,
+    pub seed: u64,
+    pub launch_angle: f32,
+    pub launch_azimuth: f32,
+    /// Factor applied to the nominal thrust
+    pub thrust_scale: f32,
+    pub drag_flight: f32,
+    pub drag_drogue: f32,
+    pub drag_main: f32,
+    pub wind_speed: f32,
+    pub wind_direction: f32,
+    /// Maximum true altitude [m ASL]
+    pub apogee_asl: f32,
+    /// Maximum true velocity [m/s]
+    pub max_velocity: f32,
+    pub landing_latitude: f64,
+    pub landing_longitude: f64,
+    /// Whether the vehicle landed before the end of the simulation
+    pub landed: bool,
+}
+
+impl MonteCarloRun {
+    /// Column names for [MonteCarloRun::csv_row]
+    pub const CSV_HEADER: &'static str = "index,seed,launch_angle [°],launch_azimuth [°],thrust_scale,drag_flight,\
+        drag_drogue,drag_main,wind_speed [m/s],wind_direction [°],apogee_asl [m],max_velocity [m/s],\
+        landing_latitude [°],landing_longitude [°],landed";
+
+    pub fn csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.index,
+            self.seed,
+            self.launch_angle,
+            self.launch_azimuth,
+            self.thrust_scale,
+            self.drag_flight,
+            self.drag_drogue,
+            self.drag_main,
+            self.wind_speed,
+            self.wind_direction,
+            self.apogee_asl,
+            self.max_velocity,
+            self.landing_latitude,
+            self.landing_longitude,
+            self.landed
+        )
+    }
+}
+
+/// Randomizes the given settings according to the dispersion. Also returns
+/// the factor applied to the thrust.
+fn randomize(settings: &SimulationSettings, dispersion: &Dispersion, rng: &mut Rng) -> (SimulationSettings, f32) {
+    let mut sample = |mean: f32, std_dev: f32| rand_distr::Normal::new(mean, std_dev.abs()).unwrap().sample(rng);
+
+    let mut settings = settings.clone();
+    settings.launch_angle = sample(settings.launch_angle, dispersion.launch_angle).clamp(0.5, 90.0);
+    settings.launch_azimuth = sample(settings.launch_azimuth, dispersion.launch_azimuth);
+    let thrust_scale = sample(1.0, dispersion.thrust).max(0.0);
+    settings.thrust *= thrust_scale;
+    if let Some(motor) = settings.motor.as_mut() {
+        motor.scale_thrust(thrust_scale);
+    }
+    settings.drag_flight *= sample(1.0, dispersion.drag).max(0.0);
+    settings.drag_drogue *= sample(1.0, dispersion.drag).max(0.0);
+    settings.drag_main *= sample(1.0, dispersion.drag).max(0.0);
+    let (wind_speed, wind_direction) = (sample(0.0, dispersion.wind_speed), sample(0.0, dispersion.wind_direction));
+    settings.wind.offset(wind_speed, wind_direction);
+    (settings, thrust_scale)
+}
+
+/// Runs a single simulation to completion, only keeping track of the true
+/// state of the vehicle.
+fn run(index: usize, seed: u64, settings: &SimulationSettings, dispersion: &Dispersion) -> MonteCarloRun {
+    let mut rng = Rng::seed_from_u64(seed);
+    let (mut settings, thrust_scale) = randomize(settings, dispersion, &mut rng);
+    settings.seed = seed;
+    let mut state = SimulationState::initialize(&settings);
+
+    let burnout = settings.sim_start_delay + settings.burn_time();
+    let mut apogee_asl = state.altitude;
+    let mut max_velocity: f32 = 0.0;
+    let mut landed = false;
+
+    while !state.tick() {
+        apogee_asl = apogee_asl.max(state.altitude);
+        max_velocity = max_velocity.max(state.velocity.magnitude());
+
+        // The remaining time on the ground is of no interest to us.
+        if state.time > burnout && state.altitude <= state.altitude_ground {
+            landed = true;
+            break;
+        }
+    }
+
+    MonteCarloRun {
+        index,
+        seed,
+        launch_angle: settings.launch_angle,
+        launch_azimuth: settings.launch_azimuth,
+        thrust_scale,
+        drag_flight: settings.drag_flight,
+        drag_drogue: settings.drag_drogue,
+        drag_main: settings.drag_main,
+        wind_speed: settings.wind.speed,
+        wind_direction: settings.wind.direction,
+        apogee_asl,
+        max_velocity,
+        landing_latitude: state.latitude,
+        landing_longitude: state.longitude,
+        landed,
+    }
+}
+
+/// A confidence ellipse around the landing points.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LandingEllipse {
+    pub center_latitude: f64,
+    pub center_longitude: f64,
+    /// Semi-major axis [m]
+    pub semi_major: f64,
+    /// Semi-minor axis [m]
+    pub semi_minor: f64,
+    /// Orientation of the semi-major axis, counter-clockwise from east [rad]
+    pub rotation: f64,
+    pub confidence: f64,
+}
+
+impl LandingEllipse {
+    /// Points on the ellipse as [longitude, latitude] pairs, for drawing on the map.
+    pub fn points(&self) -> Vec<[f64; 2]> {
+        let meters_per_degree_lng = METERS_PER_DEGREE * self.center_latitude.to_radians().cos();
+        let (sin, cos) = self.rotation.sin_cos();
+        (0..=64)
+            .map(|i| (i as f64) * std::f64::consts::TAU / 64.0)
+            .map(|t| (self.semi_major * t.cos(), self.semi_minor * t.sin()))
+            .map(|(x, y)| (x * cos - y * sin, x * sin + y * cos))
+            .map(|(east, north)| {
+                [
+                    self.center_longitude + east / meters_per_degree_lng,
+                    self.center_latitude + north / METERS_PER_DEGREE,
+                ]
+            })
+            .collect()
+    }
+}
+
+/// Mean and standard deviation of the given values.
+pub fn mean_std_dev(values: &[f32]) -> Option<(f32, f32)> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let n = values.len() as f32;
+    let mean = values.iter().sum::<f32>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / n;
+    Some((mean, variance.sqrt()))
+}
+
+/// Sorts the values into the given number of equally sized bins, returning
+/// the center and count of each bin.
+pub fn histogram(values: &[f32], bins: usize) -> Vec<(f32, usize)> {
+    let min = values.iter().copied().reduce(f32::min).unwrap_or(0.0);
+    let max = values.iter().copied().reduce(f32::max).unwrap_or(0.0);
+    let width = f32::max((max - min) / bins as f32, f32::EPSILON);
+
+    let mut counts = vec![0; bins];
+    for v in values {
+        let i = usize::min(((v - min) / width) as usize, bins - 1);
+        counts[i] += 1;
+    }
+
+    counts.into_iter().enumerate().map(|(i, n)| (min + (i as f32 + 0.5) * width, n)).collect()
+}
+
+/// Collected results of a Monte Carlo analysis.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct MonteCarloResults {
+    pub runs: Vec<MonteCarloRun>,
+}
+
+impl MonteCarloResults {
+    pub fn apogees(&self) -> Vec<f32> {
+        self.runs.iter().map(|r| r.apogee_asl).collect()
+    }
+
+    pub fn max_velocities(&self) -> Vec<f32> {
+        self.runs.iter().map(|r| r.max_velocity).collect()
+    }
+
+    /// Distances of the landing points from the launch site [m]
+    pub fn landing_distances(&self, settings: &SimulationSettings) -> Vec<f32> {
+        self.runs
+            .iter()
+            .map(|r| local_offset(settings, r.landing_latitude, r.landing_longitude))
+            .map(|(east, north)| f64::hypot(east, north) as f32)
+            .collect()
+    }
+
+    /// Computes the ellipse containing the given fraction of landing points,
+    /// assuming a normal distribution.
+    pub fn landing_ellipse(&self, settings: &SimulationSettings, confidence: f64) -> Option<LandingEllipse> {
+        if self.runs.len() < 3 {
+            return None;
+        }
+
+        let points: Vec<(f64, f64)> =
+            self.runs.iter().map(|r| local_offset(settings, r.landing_latitude, r.landing_longitude)).collect();
+
+        let n = points.len() as f64;
+        let mean_e = points.iter().map(|(e, _)| e).sum::<f64>() / n;
+        let mean_n = points.iter().map(|(_, n)| n).sum::<f64>() / n;
+        let cov_ee = points.iter().map(|(e, _)| (e - mean_e).powi(2)).sum::<f64>() / (n - 1.0);
+        let cov_nn = points.iter().map(|(_, n)| (n - mean_n).powi(2)).sum::<f64>() / (n - 1.0);
+        let cov_en = points.iter().map(|(e, n)| (e - mean_e) * (n - mean_n)).sum::<f64>() / (n - 1.0);
+
+        // Eigenvalues of the 2x2 covariance matrix
+        let mid = (cov_ee + cov_nn) / 2.0;
+        let diff = f64::hypot((cov_ee - cov_nn) / 2.0, cov_en);
+        let scale = f64::sqrt(-2.0 * f64::ln(1.0 - confidence));
+
+        let meters_per_degree_lng = METERS_PER_DEGREE * settings.launch_latitude.to_radians().cos();
+        Some(LandingEllipse {
+            center_latitude: settings.launch_latitude + mean_n / METERS_PER_DEGREE,
+            center_longitude: settings.launch_longitude + mean_e / meters_per_degree_lng,
+            semi_major: scale * (mid + diff).max(0.0).sqrt(),
+            semi_minor: scale * (mid - diff).max(0.0).sqrt(),
+            rotation: 0.5 * f64::atan2(2.0 * cov_en, cov_ee - cov_nn),
+            confidence,
+        })
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\n", MonteCarloRun::CSV_HEADER);
+        for run in &self.runs {
+            csv += &run.csv_row();
+            csv += "\n";
+        }
+        csv
+    }
+}
+
+/// East and north offset of the given position from the launch site [m]
+fn local_offset(settings: &SimulationSettings, latitude: f64, longitude: f64) -> (f64, f64) {
+    let meters_per_degree_lng = METERS_PER_DEGREE * settings.launch_latitude.to_radians().cos();
+    let east = (longitude - settings.launch_longitude) * meters_per_degree_lng;
+    let north = (latitude - settings.launch_latitude) * METERS_PER_DEGREE;
+    (east, north)
+}
+
+/// A running (or finished) Monte Carlo analysis. The runs are distributed
+/// over one worker thread per CPU core.
+pub struct MonteCarlo {
+    pub settings: SimulationSettings,
+    pub mc_settings: MonteCarloSettings,
+    pub results: MonteCarloResults,
+    results_rx: Receiver<MonteCarloRun>,
+    cancelled: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl MonteCarlo {
+    pub fn start(settings: &SimulationSettings, mc_settings: &MonteCarloSettings) -> Self {
+        let mut settings = settings.clone();
+        settings.replication_log_index = None;
+
+        let (results_tx, results_rx) = channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let next_run = Arc::new(AtomicUsize::new(0));
+
+        let num_threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let workers = (0..usize::min(num_threads, mc_settings.runs))
+            .map(|_| {
+                let (settings, mc_settings) = (settings.clone(), mc_settings.clone());
+                let (results_tx, cancelled, next_run) = (results_tx.clone(), cancelled.clone(), next_run.clone());
+                std::thread::spawn(move || loop {
+                    let index = next_run.fetch_add(1, Ordering::Relaxed);
+                    if index >= mc_settings.runs || cancelled.load(Ordering::Relaxed) {
+                        break;
+                    }
+
+                    let seed = mc_settings.seed.wrapping_add(index as u64);
+                    let result = run(index, seed, &settings, &mc_settings.dispersion);
+                    if results_tx.send(result).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+
+        log::info!("Started Monte Carlo analysis with {} runs.", mc_settings.runs);
+
+        Self {
+            settings,
+            mc_settings: mc_settings.clone(),
+            results: MonteCarloResults::default(),
+            results_rx,
+            cancelled,
+            workers,
+        }
+    }
+
+    /// Collects finished runs. Returns true if new results were added.
+    pub fn update(&mut self) -> bool {
+        let len = self.results.runs.len();
+        self.results.runs.extend(self.results_rx.try_iter());
+        self.results.runs.sort_by_key(|r| r.index);
+        self.results.runs.len() > len
+    }
+
+    pub fn progress(&self) -> (usize, usize) {
+        (self.results.runs.len(), self.mc_settings.runs)
+    }
+
+    pub fn done(&self) -> bool {
+        self.results.runs.len() >= self.mc_settings.runs || self.workers.iter().all(|w| w.is_finished())
+    }
+
+    pub fn cancel(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for MonteCarlo {
+    fn drop(&mut self) {
+        // Workers exit after their current run, no need to wait for them.
+        self.cancel();
+    }
+}
@@ -0,0 +1,176 @@
+//! Solid rocket motors for the simulation, loaded from RASP (.eng) or
+//! RockSim (.rse) thrust curve files.
+
+use serde::{Deserialize, Serialize};
+
+/// Motors bundled with the application, as (name, RASP file contents).
+pub const MOTOR_LIBRARY: [(&str, &str); 3] = [
+    ("Estes C6", include_str!("../../assets/motors/Estes_C6.eng")),
+    ("AeroTech H128W", include_str!("../../assets/motors/AeroTech_H128W.eng")),
+    ("AeroTech J350W", include_str!("../../assets/motors/AeroTech_J350W.eng")),
+];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Motor {
+    pub name: String,
+    pub manufacturer: String,
+    /// Diameter [mm]
+    pub diameter: f32,
+    /// Length [mm]
+    pub length: f32,
+    /// Propellant mass [kg]
+    pub propellant_mass: f32,
+    /// Total mass before ignition, including propellant [kg]
+    pub total_mass: f32,
+    /// Thrust curve as (time [s], thrust [N]) pairs, starting at ignition.
+    pub thrust_curve: Vec<(f32, f32)>,
+}
+
+fn parse_f32(s: &str, what: &str) -> Result<f32, String> {
+    s.trim().parse().map_err(|_| format!("Invalid {}: '{}'", what, s))
+}
+
+/// Returns the value of the given attribute of an XML tag.
+fn xml_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!(" {}=\"", name))? + name.len() + 3;
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..(start + len)])
+}
+
+impl Motor {
+    /// Parses a RASP engine file. Only the first motor in the file is used.
+    pub fn from_eng(contents: &str) -> Result<Self, String> {
+        let mut lines = contents.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).skip_while(|l| l.starts_with(';'));
+
+        let header = lines.next().ok_or("Missing motor header")?;
+        let fields: Vec<&str> = header.split_whitespace().collect();
+        if fields.len() < 7 {
+            return Err(format!("Invalid motor header: '{}'", header));
+        }
+
+        let thrust_curve = lines
+            .take_while(|l| !l.starts_with(';'))
+            .map(|l| match l.split_whitespace().collect::<Vec<_>>()[..] {
+                [t, f] => Ok((parse_f32(t, "time")?, parse_f32(f, "thrust")?)),
+                _ => Err(format!("Invalid data point: '{}'", l)),
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Self {
+            name: fields[0].to_string(),
+            manufacturer: fields[6..].join(" "),
+            diameter: parse_f32(fields[1], "diameter")?,
+            length: parse_f32(fields[2], "length")?,
+            propellant_mass: parse_f32(fields[4], "propellant mass")?,
+            total_mass: parse_f32(fields[5], "total mass")?,
+            thrust_curve,
+        }
+        .validated()
+    }
+
+    /// Parses a RockSim engine file. Only the first motor in the file is used.
+    pub fn from_rse(contents: &str) -> Result<Self, String> {
+        let start = contents.find("<engine ").ok_or("Missing engine element")?;
+        let end = contents[start..].find('>').ok_or("Unterminated engine element")? + start;
+        let engine = &contents[start..end];
+        let attribute = |tag: &str, name: &str| {
+            xml_attribute(tag, name).map(|a| a.to_string()).ok_or(format!("Missing attribute '{}'", name))
+        };
+
+        let thrust_curve = contents[end..]
+            .split("<eng-data")
+            .skip(1)
+            .map(|tag| Ok((parse_f32(&attribute(tag, "t")?, "time")?, parse_f32(&attribute(tag, "f")?, "thrust")?)))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let engine_attribute = |name: &str| attribute(engine, name);
+        Self {
+            name: engine_attribute("code")?,
+            manufacturer: engine_attribute("mfg")?,
+            diameter: parse_f32(&engine_attribute("dia")?, "diameter")?,
+            length: parse_f32(&engine_attribute("len")?, "length")?,
+            // RockSim stores masses in grams
+            propellant_mass: parse_f32(&engine_attribute("propWt")?, "propellant mass")? / 1000.0,
+            total_mass: parse_f32(&engine_attribute("initWt")?, "total mass")? / 1000.0,
+            thrust_curve,
+        }
+        .validated()
+    }
+
+    /// Parses either format, guessing it from the contents.
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        if contents.trim_start().starts_with('<') {
+            Self::from_rse(contents)
+        } else {
+            Self::from_eng(contents)
+        }
+    }
+
+    /// Checks the thrust curve, e.g. of a motor loaded from a scenario file.
+    pub(crate) fn validated(mut self) -> Result<Self, String> {
+        if self.thrust_curve.is_empty() {
+            return Err("Empty thrust curve".into());
+        }
+
+        if self.thrust_curve.windows(2).any(|w| w[1].0 < w[0].0) {
+            return Err("Thrust curve is not sorted by time".into());
+        }
+
+        // The curve implicitly starts at zero thrust at ignition.
+        if self.thrust_curve[0].0 > 0.0 {
+            self.thrust_curve.insert(0, (0.0, 0.0));
+        }
+
+        Ok(self)
+    }
+
+    /// Thrust at the given time after ignition [N]
+    pub fn thrust(&self, time: f32) -> f32 {
+        let i = self.thrust_curve.partition_point(|(t, _)| *t <= time);
+        match (self.thrust_curve.get(i.wrapping_sub(1)), self.thrust_curve.get(i)) {
+            (Some((t0, f0)), Some((t1, f1))) => f0 + (f1 - f0) * (time - t0) / (t1 - t0),
+            _ => 0.0,
+        }
+    }
+
+    pub fn burn_time(&self) -> f32 {
+        self.thrust_curve.last().map(|(t, _)| *t).unwrap_or(0.0)
+    }
+
+    /// Impulse delivered until the given time after ignition [Ns]
+    pub fn impulse(&self, time: f32) -> f32 {
+        self.thrust_curve
+            .windows(2)
+            .filter(|w| w[0].0 < time && w[1].0 > w[0].0)
+            .map(|w| {
+                let ((t0, f0), (t1, f1)) = (w[0], w[1]);
+                let t = f32::min(t1, time);
+                let f = f0 + (f1 - f0) * (t - t0) / (t1 - t0);
+                (t - t0) * (f0 + f) / 2.0
+            })
+            .sum()
+    }
+
+    pub fn total_impulse(&self) -> f32 {
+        self.impulse(self.burn_time())
+    }
+
+    /// Mass of the motor at the given time after ignition [kg]. Propellant
+    /// is assumed to burn off proportionally to the delivered impulse.
+    pub fn mass(&self, time: f32) -> f32 {
+        let total_impulse = self.total_impulse();
+        let burned = if total_impulse > 0.0 {
+            self.propellant_mass * f32::min(self.impulse(time) / total_impulse, 1.0)
+        } else {
+            0.0
+        };
+        self.total_mass - burned
+    }
+
+    /// Scales the thrust curve, e.g. for dispersion analyses.
+    pub fn scale_thrust(&mut self, factor: f32) {
+        for (_, f) in self.thrust_curve.iter_mut() {
+            *f *= factor;
+        }
+    }
+}
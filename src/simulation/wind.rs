@@ -0,0 +1,134 @@
+//! Wind model for the simulation. A mean wind profile, either constant or
+//! interpolated between layers, with optional gusts on top.
+
+use nalgebra::Vector3;
+use rand::distributions::Distribution;
+use serde::{Deserialize, Serialize};
+
+use super::Rng;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WindProfile {
+    /// The same wind at all altitudes
+    Constant,
+    /// Wind linearly interpolated between layers
+    Layered,
+}
+
+/// Wind at a given altitude.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WindLayer {
+    /// Altitude above ground [m]
+    pub altitude: f32,
+    /// Wind speed [m/s]
+    pub speed: f32,
+    /// Direction the wind is coming from [°]
+    pub direction: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindSettings {
+    pub profile: WindProfile,
+    /// Surface wind speed [m/s], used at all altitudes for a constant profile
+    pub speed: f32,
+    /// Direction the surface wind is coming from [°]
+    pub direction: f32,
+    /// Wind above the surface, for a layered profile
+    pub layers: Vec<WindLayer>,
+    /// Standard deviation of gusts [m/s], zero disables gusts
+    pub gust_intensity: f32,
+    /// Correlation time of gusts [s]
+    pub gust_time_constant: f32,
+}
+
+impl Default for WindSettings {
+    fn default() -> Self {
+        Self {
+            profile: WindProfile::Constant,
+            speed: 0.0,
+            direction: 270.0,
+            layers: vec![
+                WindLayer {
+                    altitude: 500.0,
+                    speed: 8.0,
+                    direction: 270.0,
+                },
+                WindLayer {
+                    altitude: 2000.0,
+                    speed: 15.0,
+                    direction: 260.0,
+                },
+            ],
+            gust_intensity: 0.0,
+            gust_time_constant: 2.0,
+        }
+    }
+}
+
+/// Velocity of the air in the local ENU frame [m/s] for the given wind speed
+/// and meteorological direction.
+fn wind_vector(speed: f32, direction: f32) -> Vector3<f32> {
+    let direction = direction.to_radians();
+    -speed * Vector3::new(f32::sin(direction), f32::cos(direction), 0.0)
+}
+
+impl WindSettings {
+    /// Mean wind velocity in the local ENU frame at the given altitude [m/s]
+    pub fn mean_wind(&self, altitude_agl: f32) -> Vector3<f32> {
+        let surface = wind_vector(self.speed, self.direction);
+        if self.profile == WindProfile::Constant {
+            return surface;
+        }
+
+        let mut layers: Vec<(f32, Vector3<f32>)> =
+            self.layers.iter().map(|l| (l.altitude, wind_vector(l.speed, l.direction))).collect();
+        layers.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        let mut below = (0.0, surface);
+        for (altitude, wind) in layers {
+            if altitude_agl < altitude {
+                let fraction = ((altitude_agl - below.0) / (altitude - below.0)).clamp(0.0, 1.0);
+                return below.1.lerp(&wind, fraction);
+            }
+            below = (altitude, wind);
+        }
+
+        below.1
+    }
+
+    /// Shifts speed and direction of all layers, for dispersion analyses.
+    pub fn offset(&mut self, speed: f32, direction: f32) {
+        self.speed = (self.speed + speed).max(0.0);
+        self.direction = (self.direction + direction).rem_euclid(360.0);
+        for layer in self.layers.iter_mut() {
+            layer.speed = (layer.speed + speed).max(0.0);
+            layer.direction = (layer.direction + direction).rem_euclid(360.0);
+        }
+    }
+}
+
+/// Gusts, modeled as a first-order Gauss-Markov process per axis. Vertical
+/// gusts are weaker than horizontal ones.
+#[derive(Clone, Debug, Default)]
+pub struct Gusts {
+    velocity: Vector3<f32>,
+}
+
+impl Gusts {
+    /// Advances the gusts by the given time step [s]
+    pub fn update(&mut self, settings: &WindSettings, dt: f32, rng: &mut Rng) -> Vector3<f32> {
+        if settings.gust_intensity <= 0.0 {
+            self.velocity = Vector3::zeros();
+            return self.velocity;
+        }
+
+        let decay = f32::exp(-dt / settings.gust_time_constant.max(dt));
+        let std_dev = settings.gust_intensity * f32::sqrt(1.0 - decay * decay);
+        let normal = rand_distr::Normal::new(0.0, std_dev).unwrap();
+        let noise = Vector3::new(normal.sample(rng), normal.sample(rng), 0.5 * normal.sample(rng));
+
+        self.velocity = self.velocity * decay + noise;
+        self.velocity
+    }
+}